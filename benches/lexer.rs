@@ -0,0 +1,52 @@
+//! Lexer throughput over growing inputs.
+//!
+//! The reported MB/s should stay flat as the input grows; a drop with size
+//! means lexing is no longer linear. `lexing_is_linear` in `src/lexer.rs`
+//! checks the same by counting the characters the lexer reads.
+#![feature(test)]
+
+extern crate test;
+
+use aoxo_toml::{lexer::Lexer, token};
+use test::Bencher;
+
+const ENTRY: &str = r#"[servers.alpha]
+ip = "10.0.0.1"
+dc = "eqdc10"
+name = "Ünïcödé — 東京 🚀"
+ports = [ 8000, 8001, 8002 ]
+limits = { cpu = 1.5, memory = 2048 }
+"#;
+
+fn source(size: usize) -> String {
+    ENTRY.repeat(size.div_ceil(ENTRY.len()))
+}
+
+fn lex(b: &mut Bencher, size: usize) {
+    let source = source(size);
+    b.bytes = source.len() as u64;
+    b.iter(|| {
+        let mut lexer = Lexer::<3>::new(&source);
+        let mut count = 0usize;
+        while lexer.peek_kind::<0>() != token::Kind::Eof {
+            lexer.next_token(None);
+            count += 1;
+        }
+        count
+    });
+}
+
+#[bench]
+fn lex_64k(b: &mut Bencher) {
+    lex(b, 64 * 1024);
+}
+
+#[bench]
+fn lex_1m(b: &mut Bencher) {
+    lex(b, 1024 * 1024);
+}
+
+#[bench]
+fn lex_4m(b: &mut Bencher) {
+    lex(b, 4 * 1024 * 1024);
+}
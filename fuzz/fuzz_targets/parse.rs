@@ -0,0 +1,11 @@
+//! The parser must report malformed input as errors, never panic on it.
+//!
+//! Run with `cargo +nightly fuzz run parse` from the repository root.
+#![no_main]
+
+use aoxo_toml::parser::Parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = Parser::new(source).parse().tree();
+});
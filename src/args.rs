@@ -1,9 +1,253 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
+use crate::config::TomlVersion;
+
+/// Runs the language server over stdio unless a command is given.
 #[derive(Parser)]
 pub struct Args {
     #[clap(long, short)]
     pub parse: Option<PathBuf>,
+
+    /// Serve the language server on `tcp:HOST:PORT` or `unix:PATH`
+    /// instead of stdio, to any number of clients
+    #[arg(long, value_name = "ADDRESS")]
+    pub listen: Option<Listen>,
+
+    /// Exit when the process with this id, usually the editor, exits
+    #[arg(long = "clientProcessId", value_name = "PID")]
+    pub client_process_id: Option<u32>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Where the language server listens for clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listen {
+    /// An address like `127.0.0.1:9257`.
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("tcp", address)) if !address.is_empty() => Ok(Listen::Tcp(address.to_string())),
+            Some(("unix", path)) if !path.is_empty() => Ok(Listen::Unix(PathBuf::from(path))),
+            _ => Err("expected `tcp:HOST:PORT` or `unix:PATH`".to_string()),
+        }
+    }
+}
+
+fn toml_version(name: &str) -> Result<TomlVersion, String> {
+    TomlVersion::from_name(name).ok_or_else(|| "expected `1.0` or `1.1`".to_string())
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Reports syntax errors in TOML files, and data that isn't valid, like
+    /// keys defined twice
+    Check(Check),
+    /// Formats TOML files in place
+    Fmt(Fmt),
+    /// Reports style problems in TOML files, like keys quoted for no reason
+    Lint(Lint),
+    /// Sorts the keys of each table alphabetically, in place
+    Sort(Sort),
+    /// Converts a TOML file to JSON
+    ToJson(Convert),
+    /// Converts a JSON file to TOML
+    FromJson(Convert),
+    /// Prints the value at a key path, like `servers.alpha.ip` or
+    /// `products[1].name`
+    Get(Get),
+    /// Sets the value at a key path, leaving the rest of the file as it is
+    Set(Set),
+    /// Removes the value at a key path, leaving the rest of the file as it is
+    Unset(Unset),
+}
+
+#[derive(clap::Args)]
+pub struct Check {
+    /// Files, directories or glob patterns to check
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    pub format: Format,
+
+    #[arg(long, value_enum, default_value_t = Color::Auto)]
+    pub color: Color,
+}
+
+#[derive(clap::Args)]
+pub struct Fmt {
+    /// Files, directories or glob patterns to format, or `-` to format
+    /// stdin to stdout
+    #[arg(default_value = ".")]
+    pub paths: Vec<String>,
+
+    /// Don't write the files, fail if any isn't formatted
+    #[arg(long)]
+    pub check: bool,
+
+    /// Don't write the files, print a unified diff of the changes
+    #[arg(long)]
+    pub diff: bool,
+
+    /// Format files even if they have syntax errors
+    #[arg(long)]
+    pub force: bool,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+}
+
+#[derive(clap::Args)]
+pub struct Lint {
+    /// Files, directories or glob patterns to lint
+    #[arg(default_value = ".")]
+    pub paths: Vec<String>,
+
+    /// Fix in place what can be fixed, then report what's left
+    #[arg(long)]
+    pub fix: bool,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    pub format: Format,
+
+    #[arg(long, value_enum, default_value_t = Color::Auto)]
+    pub color: Color,
+}
+
+#[derive(clap::Args)]
+pub struct Sort {
+    /// Files, directories or glob patterns to sort, or `-` to sort stdin
+    /// to stdout
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+
+    /// Sort the tables too, not only the keys in each one
+    #[arg(long)]
+    pub tables: bool,
+
+    /// Don't write the files, fail if any isn't sorted
+    #[arg(long)]
+    pub check: bool,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+}
+
+#[derive(clap::Args)]
+pub struct Convert {
+    /// File to convert, or `-` for stdin
+    #[arg(default_value = "-")]
+    pub path: String,
+
+    /// Use the toml-test encoding, where each value carries its TOML type,
+    /// like `{"type": "integer", "value": "42"}`
+    #[arg(long)]
+    pub tagged: bool,
+
+    /// Read TOML as this version, `1.0` or `1.1`, instead of the one the
+    /// project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+}
+
+#[derive(clap::Args)]
+pub struct Get {
+    /// File to read, or `-` for stdin
+    pub file: String,
+
+    /// Dotted key path, where keys may be quoted and followed by indices
+    pub path: String,
+
+    #[arg(long, short, value_enum, default_value_t = Output::Raw)]
+    pub output: Output,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+}
+
+#[derive(clap::Args)]
+pub struct Set {
+    /// File to edit in place, or `-` to edit stdin to stdout
+    pub file: String,
+
+    /// Dotted key path, where keys may be quoted and followed by indices
+    pub path: String,
+
+    /// The new value as TOML, like `42`, `'"text"'` or `'[1, 2]'`
+    pub value: String,
+
+    /// Take the value as a string as it is, without quotes
+    #[arg(long)]
+    pub string: bool,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+}
+
+#[derive(clap::Args)]
+pub struct Unset {
+    /// File to edit in place, or `-` to edit stdin to stdout
+    pub file: String,
+
+    /// Dotted key path, where keys may be quoted and followed by indices
+    pub path: String,
+
+    /// Read files as this version of TOML, `1.0` or `1.1`, instead of the
+    /// one their project file sets
+    #[arg(long, value_name = "VERSION", value_parser = toml_version)]
+    pub toml_version: Option<TomlVersion>,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// Strings without quotes, other values as TOML
+    Raw,
+    Json,
+    Toml,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Source snippets with the error underlined
+    Human,
+    /// One `path:line:col: message` line per error
+    Short,
+    Json,
+    Sarif,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Colour when writing to a terminal, unless `NO_COLOR` is set
+    Auto,
+    Always,
+    Never,
 }
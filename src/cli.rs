@@ -0,0 +1,190 @@
+//! Commands run from the terminal, as opposed to the language server.
+
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::args::Color;
+use crate::config::{self, Config, ProjectError, TomlVersion};
+use crate::report::Report;
+use crate::value::PathError;
+use crate::workspace::Workspace;
+
+pub mod check;
+pub mod edit;
+pub mod fmt;
+pub mod get;
+pub mod json;
+pub mod lint;
+pub mod sort;
+
+/// The command ran and found nothing wrong.
+pub const SUCCESS: u8 = 0;
+/// The command ran and found problems in the input.
+pub const FAILURE: u8 = 1;
+/// The command could not run, like when a file can't be read.
+pub const ERROR: u8 = 2;
+
+pub fn exit(code: u8) -> ExitCode {
+    ExitCode::from(code)
+}
+
+/// Whether to colour output written to stdout.
+pub fn use_color(color: Color) -> bool {
+    match color {
+        Color::Always => true,
+        Color::Never => false,
+        Color::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Reads the file at `path`, or stdin if it's `-`.
+pub fn read_input(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut source = String::new();
+        std::io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|error| format!("could not read stdin: {error}"))?;
+        Ok(source)
+    } else {
+        std::fs::read_to_string(path).map_err(|error| format!("could not read `{path}`: {error}"))
+    }
+}
+
+/// The version of TOML to read the file at `path`, or stdin for `-`, as:
+/// `flag` if `--toml-version` was given, or else the one the project file
+/// sets. A project file that can't be used doesn't set one.
+pub fn toml_version(flag: Option<TomlVersion>, path: &str) -> TomlVersion {
+    flag.unwrap_or_else(|| {
+        let path = match path {
+            "-" => std::env::current_dir().unwrap_or_default(),
+            path => PathBuf::from(path),
+        };
+        Config::default()
+            .for_file(&path)
+            .map_or(TomlVersion::default(), |config| config.toml_version)
+    })
+}
+
+/// Prints `error`, found in the key path `path`, pointing at the part of
+/// the path it's about.
+pub fn path_error(path: &str, error: &PathError) {
+    let before = path[..error.span.start].chars().count();
+    let len = path[error.span.start..error.span.end].chars().count();
+    eprintln!("error: {error}");
+    eprintln!("  {path}");
+    eprintln!("  {}{}", " ".repeat(before), "^".repeat(len.max(1)));
+}
+
+/// Prints why a project file can't be used, with its errors if it has
+/// any.
+pub fn project_error(error: &ProjectError) {
+    if let ProjectError::Invalid {
+        path,
+        source,
+        errors,
+    } = error
+    {
+        let path = path.display().to_string();
+        let report = Report {
+            path: &path,
+            source,
+            errors,
+        };
+        eprint!("{}", report.short());
+    }
+    eprintln!("error: {error}");
+}
+
+/// The settings of each project file that applies to `paths`, by path of
+/// the project file, or `None` after printing the errors of one that can't
+/// be used. `toml_version`, from `--toml-version`, overrides them all.
+pub fn configs<'p>(
+    paths: impl Iterator<Item = &'p Path>,
+    toml_version: Option<TomlVersion>,
+) -> Option<HashMap<Option<PathBuf>, Config>> {
+    let mut configs = HashMap::new();
+    for path in paths {
+        let project = config::find(path);
+        if configs.contains_key(&project) {
+            continue;
+        }
+        let config = match &project {
+            Some(project) => Config::default().with_project(project),
+            None => Ok(Config::default()),
+        };
+        match config {
+            Ok(mut config) => {
+                config.toml_version = toml_version.unwrap_or(config.toml_version);
+                configs.insert(project, config)
+            }
+            Err(error) => {
+                project_error(&error);
+                return None;
+            }
+        };
+    }
+    Some(configs)
+}
+
+/// Turns the paths given on the command line into the files they name.
+///
+/// Glob patterns are expanded and directories are searched recursively for
+/// `*.toml` files, leaving out what git ignores and without following
+/// symlinked directories, like [`Workspace::scan`]. Files are returned in
+/// order, without duplicates.
+pub fn expand_paths(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.contains(['*', '?', '[']) {
+            let matches =
+                glob::glob(path).map_err(|error| format!("invalid pattern `{path}`: {error}"))?;
+            let before = files.len();
+            for entry in matches {
+                let entry = entry.map_err(|error| error.to_string())?;
+                push_path(&mut files, &entry)?;
+            }
+            if files.len() == before {
+                return Err(format!("no files match `{path}`"));
+            }
+        } else {
+            push_path(&mut files, Path::new(path))?;
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files)
+}
+
+fn push_path(files: &mut Vec<PathBuf>, path: &Path) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    std::fs::read_dir(path)
+        .map_err(|error| format!("could not read `{}`: {error}", path.display()))?;
+    files.extend(Workspace::new(path.to_path_buf(), Vec::new()).scan());
+    Ok(())
+}
+
+/// Runs `f` on every item using all available cores, keeping the order.
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = items.len().div_ceil(threads).max(1);
+
+    std::thread::scope(|scope| {
+        let handles = items
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    })
+}
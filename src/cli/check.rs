@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::{
+    args::{Check, Format},
+    config::{self, Severity, TomlVersion},
+    parser::Error,
+    report::{self, Report},
+    value,
+};
+
+struct Checked {
+    path: PathBuf,
+    source: String,
+    errors: Vec<Error>,
+}
+
+fn check_file(path: &PathBuf, version: Option<TomlVersion>) -> Result<Checked, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| format!("could not read `{}`: {error}", path.display()))?;
+    // Project files are checked for their settings too
+    let mut errors = if config::is_project_file(path) {
+        config::read(&source).1
+    } else {
+        let version = super::toml_version(version, &path.display().to_string());
+        value::parse_with(&source, version).1
+    };
+    errors.sort_by_key(|error| (error.span.start, error.span.end));
+
+    Ok(Checked {
+        path: path.clone(),
+        source,
+        errors,
+    })
+}
+
+/// Parses and lowers every file and reports the errors found. Exits with
+/// [`super::FAILURE`] if there are any; warnings, like those about reserved
+/// settings in project files, don't fail.
+pub fn run(args: &Check) -> ExitCode {
+    let files = match super::expand_paths(&args.paths) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+
+    let mut checked = Vec::new();
+    let mut failed = false;
+    for result in super::parallel_map(&files, |path| check_file(path, args.toml_version)) {
+        match result {
+            Ok(file) => checked.push(file),
+            Err(error) => {
+                eprintln!("error: {error}");
+                failed = true;
+            }
+        }
+    }
+
+    let paths = checked
+        .iter()
+        .map(|file| file.path.display().to_string())
+        .collect::<Vec<_>>();
+    let reports = checked
+        .iter()
+        .zip(&paths)
+        .map(|(file, path)| Report {
+            path,
+            source: &file.source,
+            errors: &file.errors,
+        })
+        .collect::<Vec<_>>();
+
+    let color = super::use_color(args.color);
+    match args.format {
+        Format::Human => {
+            for report in &reports {
+                print!("{}", report.human(color));
+            }
+        }
+        Format::Short => {
+            for report in &reports {
+                print!("{}", report.short());
+            }
+        }
+        Format::Json => {
+            let errors = reports.iter().flat_map(Report::json).collect::<Vec<_>>();
+            println!("{}", serde_json::Value::Array(errors));
+        }
+        Format::Sarif => println!("{:#}", report::sarif(&reports)),
+    }
+
+    let all = checked.iter().flat_map(|file| &file.errors);
+    let errors = all
+        .clone()
+        .filter(|error| error.severity() == Severity::Error)
+        .count();
+    let warnings = all.count() - errors;
+    if args.format == Format::Human && errors + warnings > 0 {
+        let with_errors = checked
+            .iter()
+            .filter(|file| !file.errors.is_empty())
+            .count();
+        let warnings = match warnings {
+            0 => String::new(),
+            1 => " and 1 warning".to_string(),
+            warnings => format!(" and {warnings} warnings"),
+        };
+        eprintln!(
+            "found {errors} error{}{warnings} in {with_errors} file{}",
+            if errors == 1 { "" } else { "s" },
+            if with_errors == 1 { "" } else { "s" },
+        );
+    }
+
+    if failed {
+        super::exit(super::ERROR)
+    } else if errors > 0 {
+        super::exit(super::FAILURE)
+    } else {
+        super::exit(super::SUCCESS)
+    }
+}
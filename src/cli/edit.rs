@@ -0,0 +1,96 @@
+use std::process::ExitCode;
+
+use crate::{
+    args::{Set, Unset},
+    config::TomlVersion,
+    document::Document,
+    report::Report,
+    value::{self, PathError, Value},
+};
+
+/// Sets the value at the path. Exits with [`super::FAILURE`] if the path
+/// can't hold a value, and with [`super::ERROR`] if the file can't be read,
+/// has errors or the value isn't valid TOML.
+pub fn set(args: &Set) -> ExitCode {
+    let value = if args.string {
+        Value::String(args.value.clone())
+    } else {
+        match parse_value(&args.value) {
+            Some(value) => value,
+            None => {
+                eprintln!("error: `{}` is not a TOML value", args.value);
+                eprintln!("  help: quote strings, like '\"text\"', or pass --string");
+                return super::exit(super::ERROR);
+            }
+        }
+    };
+    let version = super::toml_version(args.toml_version, &args.file);
+    edit(&args.file, &args.path, version, |document| {
+        document.set(&args.path, &value)
+    })
+}
+
+/// Removes the value at the path. Exits with [`super::FAILURE`] if there's
+/// no value there, and with [`super::ERROR`] if the file can't be read or
+/// has errors.
+pub fn unset(args: &Unset) -> ExitCode {
+    let version = super::toml_version(args.toml_version, &args.file);
+    edit(&args.file, &args.path, version, |document| {
+        document.unset(&args.path)
+    })
+}
+
+/// `value` as the value of a key-value pair, if it's a single valid one.
+fn parse_value(value: &str) -> Option<Value> {
+    let (mut table, errors) = value::parse(&format!("value = {value}"));
+    if !errors.is_empty() || table.len() != 1 {
+        return None;
+    }
+    table.shift_remove("value")
+}
+
+/// Applies `change` to the file and writes it back, or to stdout for `-`.
+fn edit(
+    file: &str,
+    path: &str,
+    version: TomlVersion,
+    change: impl FnOnce(&mut Document) -> Result<(), PathError>,
+) -> ExitCode {
+    let source = match super::read_input(file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+
+    // Edits are placed by the syntax tree, which can't be trusted where
+    // it has errors
+    let mut document = Document::with_version(source, version);
+    let mut errors = document.errors.clone();
+    errors.extend(value::lower_with(&document.text, &document.tree, version).1);
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| (error.span.start, error.span.end));
+        let report = Report {
+            path: if file == "-" { "<stdin>" } else { file },
+            source: &document.text,
+            errors: &errors,
+        };
+        eprint!("{}", report.short());
+        return super::exit(super::ERROR);
+    }
+
+    if let Err(error) = change(&mut document) {
+        super::path_error(path, &error);
+        return super::exit(super::FAILURE);
+    }
+
+    if file == "-" {
+        print!("{}", document.text);
+    } else if let Err(error) = std::fs::write(file, &document.text) {
+        eprintln!("error: could not write `{file}`: {error}");
+        return super::exit(super::ERROR);
+    }
+
+    super::exit(super::SUCCESS)
+}
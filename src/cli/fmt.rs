@@ -0,0 +1,200 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use similar::TextDiff;
+
+use crate::{
+    args::Fmt,
+    config::{self, Config},
+    format::format_with,
+    parser::Parser,
+    report::Report,
+};
+
+/// Name shown for stdin in diffs and messages.
+const STDIN: &str = "<stdin>";
+
+enum Outcome {
+    Unchanged,
+    Changed(String),
+    /// The source has syntax errors, rendered one per line.
+    Refused(String),
+}
+
+fn format_source(path: &str, source: &str, force: bool, config: &Config) -> Outcome {
+    if !force {
+        let (_, mut errors) = Parser::new(source)
+            .with_version(config.toml_version)
+            .parse()
+            .tree();
+        if !errors.is_empty() {
+            errors.sort_by_key(|error| (error.span.start, error.span.end));
+            let report = Report {
+                path,
+                source,
+                errors: &errors,
+            };
+            return Outcome::Refused(report.short());
+        }
+    }
+
+    let formatted = format_with(source, config.format);
+    if formatted == source {
+        Outcome::Unchanged
+    } else {
+        Outcome::Changed(formatted)
+    }
+}
+
+fn diff(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(path, path)
+        .to_string()
+}
+
+/// What happened to one input, to decide the exit status.
+#[derive(Default)]
+struct Status {
+    unformatted: bool,
+    failed: bool,
+}
+
+impl Status {
+    fn merge(&mut self, other: Status) {
+        self.unformatted |= other.unformatted;
+        self.failed |= other.failed;
+    }
+}
+
+fn refused(path: &str, errors: &str) -> Status {
+    eprint!("{errors}");
+    eprintln!("error: not formatting `{path}` because it has syntax errors, use `--force` to format it anyway");
+    Status {
+        unformatted: false,
+        failed: true,
+    }
+}
+
+fn run_stdin(args: &Fmt, config: &Config) -> Status {
+    let mut source = String::new();
+    if let Err(error) = std::io::stdin().read_to_string(&mut source) {
+        eprintln!("error: could not read stdin: {error}");
+        return Status {
+            unformatted: false,
+            failed: true,
+        };
+    }
+
+    let formatted = match format_source(STDIN, &source, args.force, config) {
+        Outcome::Refused(errors) => return refused(STDIN, &errors),
+        Outcome::Unchanged => None,
+        Outcome::Changed(formatted) => Some(formatted),
+    };
+
+    let mut stdout = std::io::stdout();
+    let written = match (&formatted, args.diff, args.check) {
+        (Some(formatted), true, _) => write!(stdout, "{}", diff(STDIN, &source, formatted)),
+        (_, true, _) | (_, false, true) => Ok(()),
+        (Some(formatted), false, false) => write!(stdout, "{formatted}"),
+        (None, false, false) => write!(stdout, "{source}"),
+    };
+    if let Err(error) = written {
+        eprintln!("error: could not write to stdout: {error}");
+        return Status {
+            unformatted: false,
+            failed: true,
+        };
+    }
+
+    Status {
+        unformatted: formatted.is_some(),
+        failed: false,
+    }
+}
+
+fn read_and_format(path: &Path, force: bool, config: &Config) -> Result<(String, Outcome), String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| format!("could not read `{}`: {error}", path.display()))?;
+    let outcome = format_source(&path.display().to_string(), &source, force, config);
+    Ok((source, outcome))
+}
+
+fn run_file(args: &Fmt, path: &Path, result: Result<(String, Outcome), String>) -> Status {
+    let name = path.display().to_string();
+    let (source, formatted) = match result {
+        Ok((_, Outcome::Refused(errors))) => return refused(&name, &errors),
+        Ok((_, Outcome::Unchanged)) => return Status::default(),
+        Ok((source, Outcome::Changed(formatted))) => (source, formatted),
+        Err(error) => {
+            eprintln!("error: {error}");
+            return Status {
+                unformatted: false,
+                failed: true,
+            };
+        }
+    };
+
+    if args.diff {
+        print!("{}", diff(&name, &source, &formatted));
+    } else if args.check {
+        eprintln!("would reformat {name}");
+    } else if let Err(error) = std::fs::write(path, formatted) {
+        eprintln!("error: could not write `{name}`: {error}");
+        return Status {
+            unformatted: false,
+            failed: true,
+        };
+    }
+
+    Status {
+        unformatted: true,
+        failed: false,
+    }
+}
+
+/// Formats every file in place, or only reports the changes with `--check`
+/// and `--diff`. With `--check`, exits with [`super::FAILURE`] if any file
+/// isn't formatted.
+pub fn run(args: &Fmt) -> ExitCode {
+    let (stdin, paths): (Vec<_>, Vec<_>) = args.paths.iter().cloned().partition(|path| path == "-");
+
+    let files = match super::expand_paths(&paths) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+
+    // Stdin is formatted with the settings of the current directory
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let stdin_path = (!stdin.is_empty()).then_some(cwd.as_path());
+    let Some(configs) = super::configs(
+        files.iter().map(PathBuf::as_path).chain(stdin_path),
+        args.toml_version,
+    ) else {
+        return super::exit(super::ERROR);
+    };
+    let config = |path: &Path| &configs[&config::find(path)];
+
+    let mut status = Status::default();
+    if let Some(cwd) = stdin_path {
+        status.merge(run_stdin(args, config(cwd)));
+    }
+    let results = super::parallel_map(&files, |path| {
+        read_and_format(path, args.force, config(path))
+    });
+    for (path, result) in files.iter().zip(results) {
+        status.merge(run_file(args, path, result));
+    }
+
+    if status.failed {
+        super::exit(super::ERROR)
+    } else if status.unformatted && args.check {
+        super::exit(super::FAILURE)
+    } else {
+        super::exit(super::SUCCESS)
+    }
+}
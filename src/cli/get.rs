@@ -0,0 +1,61 @@
+use std::process::ExitCode;
+
+use crate::{
+    args::{Get, Output},
+    report::Report,
+    value::{self, Value},
+};
+
+/// Prints the value at the path. Exits with [`super::FAILURE`] if there's
+/// no value there, and with [`super::ERROR`] if the file can't be read or
+/// has errors.
+pub fn run(args: &Get) -> ExitCode {
+    let source = match super::read_input(&args.file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+
+    let (table, mut errors) =
+        value::parse_with(&source, super::toml_version(args.toml_version, &args.file));
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| (error.span.start, error.span.end));
+        let report = Report {
+            path: if args.file == "-" {
+                "<stdin>"
+            } else {
+                &args.file
+            },
+            source: &source,
+            errors: &errors,
+        };
+        eprint!("{}", report.short());
+        return super::exit(super::ERROR);
+    }
+
+    let document = Value::Table(table);
+    let value = match document.get(&args.path) {
+        Ok(value) => value,
+        Err(error) => {
+            super::path_error(&args.path, &error);
+            return super::exit(super::FAILURE);
+        }
+    };
+
+    match (args.output, value) {
+        (Output::Raw, Value::String(string)) => println!("{string}"),
+        (Output::Raw | Output::Toml, Value::Table(table)) => print!("{}", value::to_toml(table)),
+        (Output::Raw | Output::Toml, value) => println!("{value}"),
+        (Output::Json, value) => match value.to_json() {
+            Ok(json) => println!("{json:#}"),
+            Err(error) => {
+                eprintln!("error: {error}");
+                return super::exit(super::FAILURE);
+            }
+        },
+    }
+
+    super::exit(super::SUCCESS)
+}
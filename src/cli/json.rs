@@ -0,0 +1,94 @@
+use std::process::ExitCode;
+
+use crate::{
+    args::Convert,
+    report::Report,
+    value::{self, Value},
+};
+
+fn name(path: &str) -> &str {
+    if path == "-" {
+        "<stdin>"
+    } else {
+        path
+    }
+}
+
+/// Prints the TOML file as JSON. Exits with [`super::FAILURE`] if the file
+/// has errors, like toml-test expects from a decoder.
+pub fn to_json(args: &Convert) -> ExitCode {
+    let source = match super::read_input(&args.path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+
+    let (table, mut errors) =
+        value::parse_with(&source, super::toml_version(args.toml_version, &args.path));
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| (error.span.start, error.span.end));
+        let report = Report {
+            path: name(&args.path),
+            source: &source,
+            errors: &errors,
+        };
+        eprint!("{}", report.short());
+        return super::exit(super::FAILURE);
+    }
+
+    let table = Value::Table(table);
+    let json = if args.tagged {
+        Ok(table.to_tagged_json())
+    } else {
+        table.to_json()
+    };
+    match json {
+        Ok(json) => {
+            println!("{json:#}");
+            super::exit(super::SUCCESS)
+        }
+        Err(error) => {
+            eprintln!("error: {error}");
+            super::exit(super::FAILURE)
+        }
+    }
+}
+
+/// Prints the JSON file as TOML. Exits with [`super::FAILURE`] if it's not
+/// valid JSON or has no TOML equivalent.
+pub fn from_json(args: &Convert) -> ExitCode {
+    let source = match super::read_input(&args.path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+
+    let value = serde_json::from_str(&source)
+        .map_err(|error| format!("invalid JSON in {}: {error}", name(&args.path)))
+        .and_then(|json| {
+            if args.tagged {
+                Value::from_tagged_json(&json)
+            } else {
+                Value::from_json(&json)
+            }
+        });
+
+    match value {
+        Ok(Value::Table(table)) => {
+            print!("{}", value::to_toml(&table));
+            super::exit(super::SUCCESS)
+        }
+        Ok(_) => {
+            eprintln!("error: a TOML document is a table, so the JSON must be an object");
+            super::exit(super::FAILURE)
+        }
+        Err(error) => {
+            eprintln!("error: {error}");
+            super::exit(super::FAILURE)
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::{
+    args::{Format, Lint},
+    config::{self, Config, Severity},
+    lint,
+    parser::{Error, Parser},
+    report::{self, Report},
+};
+
+struct Linted {
+    path: PathBuf,
+    source: String,
+    /// Syntax errors or, if there are none, lints.
+    errors: Vec<Error>,
+    fixed: bool,
+}
+
+fn lint_file(path: &Path, config: &Config, fix: bool) -> Result<Linted, String> {
+    let name = path.display();
+    let mut source = std::fs::read_to_string(path)
+        .map_err(|error| format!("could not read `{name}`: {error}"))?;
+
+    let mut fixed = false;
+    if fix
+        && let Some(new) = lint::fix_all(&source, config)
+        && new != source
+    {
+        std::fs::write(path, &new).map_err(|error| format!("could not write `{name}`: {error}"))?;
+        source = new;
+        fixed = true;
+    }
+
+    let (tree, mut errors) = Parser::new(&source)
+        .with_version(config.toml_version)
+        .parse()
+        .tree();
+    if errors.is_empty() {
+        errors = lint::lint(&source, &tree, config)
+            .into_iter()
+            .map(|lint| lint.error)
+            .collect();
+    }
+    errors.sort_by_key(|error| (error.span.start, error.span.end));
+
+    Ok(Linted {
+        path: path.to_path_buf(),
+        source,
+        errors,
+        fixed,
+    })
+}
+
+fn plural(count: usize, word: &str) -> String {
+    format!("{count} {word}{}", if count == 1 { "" } else { "s" })
+}
+
+/// Lints every file with the rules its project file sets, fixing them
+/// first with `--fix`. Exits with [`super::FAILURE`] if a file has syntax
+/// errors or lints set to `error`; warnings alone don't fail.
+pub fn run(args: &Lint) -> ExitCode {
+    let files = match super::expand_paths(&args.paths) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+    let Some(configs) = super::configs(files.iter().map(PathBuf::as_path), args.toml_version)
+    else {
+        return super::exit(super::ERROR);
+    };
+
+    let mut linted = Vec::new();
+    let mut failed = false;
+    let results = super::parallel_map(&files, |path| {
+        lint_file(path, &configs[&config::find(path)], args.fix)
+    });
+    for result in results {
+        match result {
+            Ok(file) => linted.push(file),
+            Err(error) => {
+                eprintln!("error: {error}");
+                failed = true;
+            }
+        }
+    }
+
+    let paths = linted
+        .iter()
+        .map(|file| file.path.display().to_string())
+        .collect::<Vec<_>>();
+    let reports = linted
+        .iter()
+        .zip(&paths)
+        .map(|(file, path)| Report {
+            path,
+            source: &file.source,
+            errors: &file.errors,
+        })
+        .collect::<Vec<_>>();
+
+    let color = super::use_color(args.color);
+    match args.format {
+        Format::Human => {
+            for report in &reports {
+                print!("{}", report.human(color));
+            }
+        }
+        Format::Short => {
+            for report in &reports {
+                print!("{}", report.short());
+            }
+        }
+        Format::Json => {
+            let errors = reports.iter().flat_map(Report::json).collect::<Vec<_>>();
+            println!("{}", serde_json::Value::Array(errors));
+        }
+        Format::Sarif => println!("{:#}", report::sarif(&reports)),
+    }
+
+    let all = linted.iter().flat_map(|file| &file.errors);
+    let errors = all
+        .clone()
+        .filter(|error| error.severity() == Severity::Error)
+        .count();
+    let warnings = all.count() - errors;
+    if args.format == Format::Human {
+        let fixed = linted.iter().filter(|file| file.fixed).count();
+        if fixed > 0 {
+            eprintln!("fixed {}", plural(fixed, "file"));
+        }
+        if errors + warnings > 0 {
+            eprintln!(
+                "found {} and {}",
+                plural(errors, "error"),
+                plural(warnings, "warning")
+            );
+        }
+    }
+
+    if failed {
+        super::exit(super::ERROR)
+    } else if errors > 0 {
+        super::exit(super::FAILURE)
+    } else {
+        super::exit(super::SUCCESS)
+    }
+}
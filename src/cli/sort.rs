@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::{
+    args::Sort,
+    parser::Parser,
+    report::Report,
+    sort::{sort, Options},
+};
+
+/// Name shown for stdin in messages.
+const STDIN: &str = "<stdin>";
+
+/// `source` sorted, or `None` after printing its syntax errors.
+fn sort_source(path: &str, source: &str, options: Options) -> Option<String> {
+    let sorted = sort(source, options);
+    if sorted.is_none() {
+        let (_, mut errors) = Parser::new(source)
+            .with_version(options.version)
+            .parse()
+            .tree();
+        errors.sort_by_key(|error| (error.span.start, error.span.end));
+        let report = Report {
+            path,
+            source,
+            errors: &errors,
+        };
+        eprint!("{}", report.short());
+        eprintln!("error: not sorting `{path}` because it has syntax errors");
+    }
+    sorted
+}
+
+/// Sorts every file in place, or stdin to stdout for `-`. With `--check`,
+/// exits with [`super::FAILURE`] if any file isn't sorted. Files with
+/// syntax errors are left alone.
+pub fn run(args: &Sort) -> ExitCode {
+    let (stdin, paths): (Vec<_>, Vec<_>) = args.paths.iter().cloned().partition(|path| path == "-");
+    let files = match super::expand_paths(&paths) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return super::exit(super::ERROR);
+        }
+    };
+    let options = |path: &str| Options {
+        tables: args.tables,
+        version: super::toml_version(args.toml_version, path),
+    };
+
+    let mut unsorted = false;
+    let mut failed = false;
+    if !stdin.is_empty() {
+        match super::read_input("-") {
+            Ok(source) => match sort_source(STDIN, &source, options("-")) {
+                Some(sorted) => {
+                    unsorted |= sorted != source;
+                    if !args.check {
+                        print!("{sorted}");
+                    }
+                }
+                None => failed = true,
+            },
+            Err(error) => {
+                eprintln!("error: {error}");
+                failed = true;
+            }
+        }
+    }
+
+    let results = super::parallel_map(&files, |path: &PathBuf| {
+        std::fs::read_to_string(path)
+            .map_err(|error| format!("could not read `{}`: {error}", path.display()))
+    });
+    for (path, result) in files.iter().zip(results) {
+        let name = path.display().to_string();
+        let source = match result {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("error: {error}");
+                failed = true;
+                continue;
+            }
+        };
+        let Some(sorted) = sort_source(&name, &source, options(&name)) else {
+            failed = true;
+            continue;
+        };
+        if sorted == source {
+            continue;
+        }
+        unsorted = true;
+        if args.check {
+            eprintln!("would sort {name}");
+        } else if let Err(error) = std::fs::write(path, sorted) {
+            eprintln!("error: could not write `{name}`: {error}");
+            failed = true;
+        }
+    }
+
+    if failed {
+        super::exit(super::ERROR)
+    } else if unsorted && args.check {
+        super::exit(super::FAILURE)
+    } else {
+        super::exit(super::SUCCESS)
+    }
+}
@@ -0,0 +1,171 @@
+//! Comments that belong to key-value pairs and tables.
+//!
+//! The comment lines right above a node, down to a blank line or anything
+//! else, lead it, and a comment after it on its last line trails it. For a
+//! table, that's the line of its header. Comments inside arrays, or on
+//! lines of their own away from any node, belong to nothing.
+
+use crate::{
+    span::{line_end, line_start, Span},
+    tree::{self, Child, Tree},
+};
+
+/// The comments of a key-value pair, a `[table]` or a `[[table]]`, each
+/// from its `#` to the end of its line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Comments {
+    /// The comment lines right above the node, in order.
+    pub leading: Vec<Span>,
+    /// The comment after the node, on the same line.
+    pub trailing: Option<Span>,
+}
+
+/// Which of the comments of a node to read or change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Leading,
+    Trailing,
+}
+
+impl Comments {
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_none()
+    }
+
+    /// The text of the leading comments, one line each, without their `#`.
+    pub fn leading_text(&self, source: &str) -> Option<String> {
+        if self.leading.is_empty() {
+            return None;
+        }
+        let lines = self
+            .leading
+            .iter()
+            .map(|span| text(&source[span.start..span.end]))
+            .collect::<Vec<_>>();
+        Some(lines.join("\n"))
+    }
+
+    /// The text of the trailing comment, without its `#`.
+    pub fn trailing_text<'s>(&self, source: &'s str) -> Option<&'s str> {
+        self.trailing
+            .map(|span| text(&source[span.start..span.end]))
+    }
+
+    pub(crate) fn shift(&mut self, delta: isize) {
+        for span in &mut self.leading {
+            *span = span.shift(delta);
+        }
+        self.trailing = self.trailing.map(|span| span.shift(delta));
+    }
+}
+
+/// `comment` without its `#` and the space after it.
+fn text(comment: &str) -> &str {
+    let text = comment.strip_prefix('#').unwrap_or(comment);
+    text.strip_prefix(' ').unwrap_or(text).trim_end()
+}
+
+/// Whether comments belong to nodes of `kind`.
+fn commented(kind: tree::Kind) -> bool {
+    matches!(
+        kind,
+        tree::Kind::KeyVal | tree::Kind::Table | tree::Kind::TableArray
+    )
+}
+
+/// Where the part of `node` a trailing comment follows ends: its header
+/// for a table, all of it otherwise.
+pub(crate) fn end_of(source: &str, node: &Tree) -> usize {
+    if !matches!(node.kind, tree::Kind::Table | tree::Kind::TableArray) {
+        return node.span.end;
+    }
+    let key = node.children.iter().find_map(|child| match child {
+        Child::Tree(key) if key.kind == tree::Kind::Key => Some(key.span.end),
+        _ => None,
+    });
+    let Some(key) = key else {
+        return node.span.start;
+    };
+    let end = line_end(source, key).max(key);
+    let rest = &source[key..end];
+    key + rest.len() - rest.trim_start_matches([' ', '\t', ']']).len()
+}
+
+/// The comment lines right above the line `start` is on, that start at
+/// `floor` or later.
+fn leading(source: &str, start: usize, floor: usize) -> Vec<Span> {
+    let mut comments = Vec::new();
+    let mut top = line_start(source, start);
+    while top > floor {
+        let previous = line_start(source, top - 1);
+        if previous < floor {
+            break;
+        }
+        let line = &source[previous..line_end(source, previous)];
+        let comment = line.trim_start();
+        if !comment.starts_with('#') {
+            break;
+        }
+        let start = previous + line.len() - comment.len();
+        comments.push(Span::from(start..start + comment.trim_end().len()));
+        top = previous;
+    }
+    comments.reverse();
+    comments
+}
+
+/// The comment after `end` on its line, past a comma, if nothing else is.
+fn trailing(source: &str, end: usize) -> Option<Span> {
+    let line = line_end(source, end.min(source.len()));
+    let rest = source.get(end..line)?;
+    let after = rest.trim_start_matches([' ', '\t']);
+    let after = after.strip_prefix(',').unwrap_or(after);
+    let comment = after.trim_start_matches([' ', '\t']);
+    comment.starts_with('#').then(|| {
+        let start = line - comment.len();
+        Span::from(start..start + comment.trim_end().len())
+    })
+}
+
+/// Attaches the comments of `source` to the nodes of `root`, its syntax
+/// tree.
+pub fn attach(source: &str, root: &mut Tree) {
+    let len = root.children.len();
+    attach_children(source, root, 0..len);
+}
+
+/// Attaches the comments of `source` to the `range` children of `root`,
+/// and to their descendants.
+pub(crate) fn attach_children(source: &str, root: &mut Tree, range: core::ops::Range<usize>) {
+    let floor = match range.start.checked_sub(1) {
+        Some(previous) => span_of(&root.children[previous]).end,
+        None => 0,
+    };
+    let end = range.end.min(root.children.len());
+    attach_all(source, &mut root.children[range.start.min(end)..end], floor);
+}
+
+fn span_of(child: &Child) -> Span {
+    match child {
+        Child::Tree(tree) => tree.span,
+        Child::Token(token) => token.span,
+    }
+}
+
+/// Attaches comments to `children` and their descendants, the first of
+/// them no higher than `floor`.
+fn attach_all(source: &str, children: &mut [Child], mut floor: usize) {
+    for child in children {
+        if let Child::Tree(tree) = child {
+            if commented(tree.kind) {
+                tree.comments = Comments {
+                    leading: leading(source, tree.span.start, floor),
+                    trailing: trailing(source, end_of(source, tree)),
+                };
+            }
+            let start = tree.span.start;
+            attach_all(source, &mut tree.children, start);
+        }
+        floor = span_of(child).end;
+    }
+}
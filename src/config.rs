@@ -0,0 +1,376 @@
+//! Settings shared by the language server and the commands.
+//!
+//! Settings come in layers, each overriding only the settings it sets: the
+//! defaults, then those the client sends, then those of the
+//! [`FILE_NAME`] file closest to the TOML file, which the commands read
+//! too. The project file wins over the client so everyone working on a
+//! project gets the same results, whatever their editor says.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    document,
+    error::{Error, ErrorKind},
+    format::Style,
+    lint,
+    parser::Parser,
+    span::Span,
+    tree::Tree,
+    value::{self, path::Segment, Table, Value},
+};
+
+/// Name of the project file, looked for in the directory of a TOML file
+/// and each of its ancestors.
+pub const FILE_NAME: &str = ".aoxo-toml.toml";
+
+/// Settings that are read, so that project files can already set them, but
+/// that nothing uses yet. [`check`] warns about them.
+pub const RESERVED: &[&str] = &["inlay-hints", "schemas"];
+
+/// Most spaces [`Style::indent_width`] can be set to.
+const MAX_INDENT_WIDTH: i64 = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub format: Style,
+    /// Severity of the lint rules, by id, for those not left at their
+    /// default.
+    pub lints: BTreeMap<String, Severity>,
+    /// Schemas describing TOML files, by pattern of the paths of the files,
+    /// relative to the workspace. Reserved: nothing validates files against
+    /// them yet, see [`RESERVED`].
+    pub schemas: Vec<Schema>,
+    pub toml_version: TomlVersion,
+    /// Whether the server shows inlay hints. Reserved: the server has none
+    /// to show yet, see [`RESERVED`].
+    pub inlay_hints: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            format: Style::default(),
+            lints: BTreeMap::new(),
+            schemas: Vec::new(),
+            toml_version: TomlVersion::default(),
+            inlay_hints: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub pattern: glob::Pattern,
+    pub url: String,
+}
+
+/// How a lint rule reports what it finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The rule doesn't run.
+    Allow,
+    Warn,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Self::Allow, Self::Warn, Self::Error]
+            .into_iter()
+            .find(|severity| severity.name() == name)
+    }
+}
+
+/// The version of TOML files are read as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum TomlVersion {
+    #[default]
+    V1_0,
+    V1_1,
+}
+
+impl TomlVersion {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::V1_0 => "1.0",
+            Self::V1_1 => "1.1",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::V1_0, Self::V1_1]
+            .into_iter()
+            .find(|version| version.name() == name)
+    }
+}
+
+/// A setting that isn't valid, found at the key path `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingError {
+    pub path: Vec<String>,
+    pub message: String,
+}
+
+impl core::fmt::Display for SettingError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "`{}`: {}", self.path.join("."), self.message)
+    }
+}
+
+/// Collects the errors found while applying settings.
+#[derive(Default)]
+struct Errors(Vec<SettingError>);
+
+impl Errors {
+    fn push(&mut self, path: &[&str], message: impl Into<String>) {
+        self.0.push(SettingError {
+            path: path.iter().map(|key| key.to_string()).collect(),
+            message: message.into(),
+        });
+    }
+
+    fn table<'v>(&mut self, path: &[&str], value: &'v Value) -> Option<&'v Table> {
+        match value {
+            Value::Table(table) => Some(table),
+            _ => {
+                self.push(path, "expected a table");
+                None
+            }
+        }
+    }
+
+    fn string<'v>(&mut self, path: &[&str], value: &'v Value) -> Option<&'v str> {
+        match value {
+            Value::String(string) => Some(string),
+            _ => {
+                self.push(path, "expected a string");
+                None
+            }
+        }
+    }
+
+    /// A string among the names `from_name` knows, described by `expected`.
+    fn name<T>(
+        &mut self,
+        path: &[&str],
+        value: &Value,
+        from_name: fn(&str) -> Option<T>,
+        expected: &str,
+    ) -> Option<T> {
+        let name = self.string(path, value)?;
+        let parsed = from_name(name);
+        if parsed.is_none() {
+            self.push(path, format!("expected {expected}, found `{name}`"));
+        }
+        parsed
+    }
+}
+
+impl Config {
+    /// Overrides the settings `table` sets, leaving the others as they are.
+    ///
+    /// Settings that aren't valid, or that don't exist, are left out and
+    /// returned as errors.
+    pub fn apply(&mut self, table: &Table) -> Vec<SettingError> {
+        let mut errors = Errors::default();
+        for (key, value) in table {
+            let path = [key.as_str()];
+            match key.as_str() {
+                "toml-version" => {
+                    let expected = "`\"1.0\"` or `\"1.1\"`";
+                    if let Some(version) =
+                        errors.name(&path, value, TomlVersion::from_name, expected)
+                    {
+                        self.toml_version = version;
+                    }
+                }
+                "inlay-hints" => match value {
+                    Value::Boolean(enabled) => self.inlay_hints = *enabled,
+                    _ => errors.push(&path, "expected a boolean"),
+                },
+                "format" => {
+                    for (key, value) in errors.table(&path, value).into_iter().flatten() {
+                        let path = ["format", key.as_str()];
+                        match (key.as_str(), value) {
+                            ("indent-width", Value::Integer(width))
+                                if (0..=MAX_INDENT_WIDTH).contains(width) =>
+                            {
+                                self.format.indent_width = *width as usize;
+                            }
+                            ("indent-width", _) => errors.push(
+                                &path,
+                                format!("expected an integer from 0 to {MAX_INDENT_WIDTH}"),
+                            ),
+                            _ => errors.push(&path, "no such setting"),
+                        }
+                    }
+                }
+                "lint" => {
+                    for (rule, value) in errors.table(&path, value).into_iter().flatten() {
+                        let path = ["lint", rule.as_str()];
+                        if lint::rule(rule).is_none() {
+                            errors.push(&path, "no such lint rule");
+                            continue;
+                        }
+                        let expected = "`\"allow\"`, `\"warn\"` or `\"error\"`";
+                        if let Some(severity) =
+                            errors.name(&path, value, Severity::from_name, expected)
+                        {
+                            self.lints.insert(rule.clone(), severity);
+                        }
+                    }
+                }
+                "schemas" => {
+                    for (pattern, value) in errors.table(&path, value).into_iter().flatten() {
+                        let path = ["schemas", pattern.as_str()];
+                        let Some(url) = errors.string(&path, value) else {
+                            continue;
+                        };
+                        match glob::Pattern::new(pattern) {
+                            Ok(pattern) => {
+                                // A later layer replaces the schema of a pattern
+                                self.schemas.retain(|schema| schema.pattern != pattern);
+                                self.schemas.push(Schema {
+                                    pattern,
+                                    url: url.to_string(),
+                                });
+                            }
+                            Err(error) => errors.push(&path, format!("invalid pattern: {error}")),
+                        }
+                    }
+                }
+                _ => errors.push(&path, "no such setting"),
+            }
+        }
+        errors.0
+    }
+
+    /// These settings, overridden by those of the project file closest to
+    /// the file at `path`, if there's one.
+    pub fn for_file(&self, path: &Path) -> Result<Config, ProjectError> {
+        match find(path) {
+            Some(project) => self.with_project(&project),
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// These settings, overridden by those of the project file at
+    /// `project`.
+    pub fn with_project(&self, project: &Path) -> Result<Config, ProjectError> {
+        let source = std::fs::read_to_string(project).map_err(|error| ProjectError::Read {
+            path: project.to_path_buf(),
+            error,
+        })?;
+        let (table, mut errors) = read(&source);
+        // Warnings, like those about reserved settings, don't stop the
+        // file from being used
+        errors.retain(|error| error.severity() == Severity::Error);
+        if !errors.is_empty() {
+            return Err(ProjectError::Invalid {
+                path: project.to_path_buf(),
+                source,
+                errors,
+            });
+        }
+        let mut config = self.clone();
+        config.apply(&table);
+        Ok(config)
+    }
+}
+
+/// Why the project file for a TOML file couldn't be used.
+#[derive(Debug)]
+pub enum ProjectError {
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The file has syntax errors or settings that aren't valid.
+    Invalid {
+        path: PathBuf,
+        source: String,
+        errors: Vec<Error>,
+    },
+}
+
+impl core::fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Read { path, error } => write!(f, "could not read `{}`: {error}", path.display()),
+            Self::Invalid { path, errors, .. } => {
+                let count = errors.len();
+                let s = if count == 1 { "" } else { "s" };
+                write!(f, "`{}` has {count} error{s}", path.display())
+            }
+        }
+    }
+}
+
+/// The project file that applies to the file or directory at `path`.
+pub fn find(path: &Path) -> Option<PathBuf> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+/// Whether `path` names a project file.
+pub fn is_project_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == FILE_NAME)
+}
+
+/// The settings of the project file with the text `source`, with its
+/// syntax errors or, if there are none, its settings that aren't valid.
+pub fn read(source: &str) -> (Table, Vec<Error>) {
+    let (tree, mut errors) = Parser::new(source).parse().tree();
+    let (table, lowering) = value::lower(source, &tree);
+    errors.extend(lowering);
+    if errors.is_empty() {
+        errors = check(source, &tree, &table);
+    }
+    (table, errors)
+}
+
+/// The settings of a project file, with the text `source` and the tree
+/// and table it parses to, that aren't valid, spanning where they're
+/// written, and warnings for the [`RESERVED`] ones it sets.
+pub fn check(source: &str, tree: &Tree, table: &Table) -> Vec<Error> {
+    let locate = |path: &[String]| {
+        let path = path
+            .iter()
+            .map(|key| Segment::Key(key.clone()))
+            .collect::<Vec<_>>();
+        document::locate(source, tree, &path).unwrap_or(Span::from(0..0))
+    };
+    let mut errors = Config::default()
+        .apply(table)
+        .into_iter()
+        .map(|error| {
+            Error::new(
+                locate(&error.path),
+                ErrorKind::InvalidSetting(error.to_string()),
+            )
+        })
+        .collect::<Vec<_>>();
+    for setting in RESERVED {
+        if table.contains_key(*setting) {
+            errors.push(
+                Error::new(
+                    locate(&[setting.to_string()]),
+                    ErrorKind::UnusedSetting(setting.to_string()),
+                )
+                .with_note("the setting is reserved, and not used by anything yet"),
+            );
+        }
+    }
+    errors
+}
@@ -1,17 +1,25 @@
+#[cfg(test)]
+use std::cell::Cell;
+
 use crate::Slice;
 
+/// Position in a [`Slice`], kept as an offset so that peeking and bumping
+/// are constant time regardless of how far into the source we are.
 #[derive(Debug)]
 pub struct Cursor<'src, Items: ?Sized + 'src> {
     slice: &'src Items,
     cursor: usize,
+    /// Items read so far, to check that lexing stays linear.
+    #[cfg(test)]
+    reads: Cell<usize>,
 }
 
-impl<Item, Items: ?Sized> Cursor<'_, Items>
+impl<'src, Item, Items: ?Sized> Cursor<'src, Items>
 where
     Item: PartialEq,
     for<'a> &'a Items: Slice<Item = Item>,
 {
-    pub fn source(&self) -> &Items {
+    pub fn source(&self) -> &'src Items {
         self.slice
     }
 
@@ -20,32 +28,59 @@ where
     }
 
     pub fn peek(&self) -> Option<Item> {
+        #[cfg(test)]
+        self.reads.set(self.reads.get() + 1);
         self.slice.get_idx(self.cursor)
     }
 
+    /// Item `n` items after the current one.
     pub fn peek_ahead(&self, n: usize) -> Option<Item> {
-        self.slice.get_idx(self.cursor + n)
+        #[cfg(test)]
+        self.reads.set(self.reads.get() + n + 1);
+        let mut idx = self.cursor;
+        for _ in 0..n {
+            idx += <&Items>::width(&self.slice.get_idx(idx)?);
+        }
+        self.slice.get_idx(idx)
     }
 
     pub fn peek_chunk<const SIZE: usize>(&self) -> Option<[Item; SIZE]> {
+        #[cfg(test)]
+        self.reads.set(self.reads.get() + SIZE);
         self.slice.get_chunk::<SIZE>(self.cursor)
     }
 
     pub fn bump(&mut self) {
-        self.cursor += 1;
+        if let Some(item) = self.peek() {
+            self.cursor += <&Items>::width(&item);
+        }
     }
 
     pub fn bump_n(&mut self, n: usize) {
-        self.cursor += n;
+        for _ in 0..n {
+            self.bump();
+        }
     }
 
     pub fn cursor(&self) -> usize {
         self.cursor
     }
+
+    /// Number of items peeked at so far, counting an item each time it's
+    /// read again.
+    #[cfg(test)]
+    pub fn reads(&self) -> usize {
+        self.reads.get()
+    }
 }
 
 impl<'src, T: ?Sized> Cursor<'src, T> {
     pub fn new(slice: &'src T) -> Self {
-        Self { slice, cursor: 0 }
+        Self {
+            slice,
+            cursor: 0,
+            #[cfg(test)]
+            reads: Cell::new(0),
+        }
     }
 }
@@ -0,0 +1,198 @@
+use crate::{
+    config::TomlVersion,
+    parser::{Error, Parser},
+    span::Span,
+    tree::{self, Child, Tree},
+};
+
+mod comment;
+mod edit;
+
+pub(crate) use edit::locate;
+
+/// Text of an open file together with its parse.
+#[derive(Debug)]
+pub struct Document {
+    pub text: String,
+    pub tree: Tree,
+    pub errors: Vec<Error>,
+    /// The version of TOML the text is read as, also on every edit.
+    pub version: TomlVersion,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        Self::with_version(text, TomlVersion::default())
+    }
+
+    pub fn with_version(text: String, version: TomlVersion) -> Self {
+        let (tree, errors) = Parser::new(&text).with_version(version).parse().tree();
+        Self {
+            text,
+            tree,
+            errors,
+            version,
+        }
+    }
+
+    /// Replaces the bytes in `span` with `text`.
+    ///
+    /// Only the top-level nodes touched by the edit are reparsed, unless that
+    /// could give a different result than parsing the whole text again, in
+    /// which case the whole text is reparsed.
+    pub fn edit(&mut self, span: Span, text: &str) {
+        self.text.replace_range(span.start..span.end, text);
+
+        let delta = text.len() as isize - (span.end - span.start) as isize;
+        if !self.reparse(span, delta) {
+            (self.tree, self.errors) = Parser::new(&self.text)
+                .with_version(self.version)
+                .parse()
+                .tree();
+        }
+    }
+
+    /// Reparses the region around `edit`, already applied to `self.text`, and
+    /// splices it into the tree. Returns `false`, leaving the tree untouched,
+    /// if the region can't be reparsed on its own.
+    fn reparse(&mut self, edit: Span, delta: isize) -> bool {
+        let children = &self.tree.children;
+        let start_of = |child: &Child| match child {
+            Child::Tree(tree) => tree.span.start,
+            Child::Token(token) => token.span.start,
+        };
+
+        // The node the edit starts in, or the one right before it, since its
+        // end depends on the tokens that follow it.
+        let first = children
+            .iter()
+            .rposition(|child| start_of(child) < edit.start)
+            .unwrap_or(0);
+        // The first node the edit doesn't reach, which stays as is.
+        let last = children
+            .iter()
+            .skip(first + 1)
+            .position(|child| start_of(child) >= edit.end)
+            .map_or(children.len(), |i| first + 1 + i);
+
+        let start = match first {
+            0 => 0,
+            _ => start_of(&children[first]),
+        };
+        let old_end = children
+            .get(last)
+            .map_or(self.text.len().wrapping_add_signed(-delta), start_of);
+        let end = old_end.wrapping_add_signed(delta);
+
+        // Errors a node reports on the token that follows it land on the
+        // boundary, where we can't tell which side they belong to. The node
+        // before the region may also have been left open, ending only where
+        // the region starts, with nothing said about it there if it already
+        // reported an error.
+        let previous = first
+            .checked_sub(1)
+            .map_or(start, |previous| start_of(&children[previous]));
+        if self.errors.iter().any(|error| {
+            (start > 0 && error.span.start == start)
+                || error.span.start == old_end
+                || (previous..start).contains(&error.span.start)
+        }) {
+            return false;
+        }
+
+        let region = &self.text[start..end];
+        let (mut fragment, errors) = Parser::new(region)
+            .with_version(self.version)
+            .parse()
+            .tree();
+        if fragment.children.is_empty() {
+            return false;
+        }
+
+        if let Some(Child::Tree(next)) = children.get(last) {
+            // Whatever is still open at the end of the region would carry on
+            // into the next node in a full parse.
+            // Errors on the last newline may be the ones reported at `Eof`,
+            // and any error in the last node may be all there is of one
+            // saying it's still open, since a construct reports one error.
+            let closed = region.trim_end_matches([' ', '\t']);
+            let settled = closed.trim_end_matches('\n').len();
+            let last_node = fragment.children.last().map_or(0, start_of);
+            if settled == closed.len()
+                || errors
+                    .iter()
+                    .any(|e| e.span.end > settled || e.span.start >= last_node)
+            {
+                return false;
+            }
+
+            // A table at the end of the region would own the key-values of a
+            // following top-level `KeyVal`. What an `Error` skipped depends on
+            // what came before it, like a header that was the rest of a line.
+            let ends_in_table = matches!(
+                fragment.children.last(),
+                Some(Child::Tree(tree))
+                    if matches!(tree.kind, tree::Kind::Table | tree::Kind::TableArray)
+            );
+            if next.kind == tree::Kind::Error || (next.kind == tree::Kind::KeyVal && ends_in_table)
+            {
+                return false;
+            }
+        }
+
+        fragment.shift(start as isize);
+
+        let mut tail = self.tree.children.split_off(last);
+        for child in &mut tail {
+            if let Child::Tree(tree) = child {
+                tree.shift(delta);
+            }
+        }
+        self.tree.children.truncate(first);
+
+        let mut root = if start == 0 {
+            fragment.span
+        } else {
+            Span::from(self.tree.span.start..self.tree.span.start)
+        };
+        for child in self
+            .tree
+            .children
+            .iter()
+            .chain(&fragment.children)
+            .chain(&tail)
+        {
+            if let Child::Tree(tree) = child {
+                root.start = root.start.min(tree.span.start);
+                root.end = root.end.max(tree.span.end);
+            }
+        }
+
+        let added = fragment.children.len();
+        self.tree.children.append(&mut fragment.children);
+        self.tree.children.append(&mut tail);
+        self.tree.span = root;
+        // Comments above the region, or above the node after it, belong to
+        // nodes the fragment didn't know about
+        crate::comment::attach_children(
+            &self.text,
+            &mut self.tree,
+            first.saturating_sub(1)..first + added + 1,
+        );
+
+        self.errors.retain_mut(|error| {
+            if error.span.start >= old_end {
+                error.span = error.span.shift(delta);
+                true
+            } else {
+                error.span.start < start
+            }
+        });
+        self.errors.extend(errors.into_iter().map(|error| Error {
+            span: error.span.shift(start as isize),
+            ..error
+        }));
+
+        true
+    }
+}
@@ -0,0 +1,150 @@
+//! Reading and changing the comments of key-value pairs and tables by key
+//! path.
+
+use super::{
+    edit::{pairs, parse, sections, unsupported, value_of},
+    Document,
+};
+use crate::{
+    comment::{self, Placement},
+    span::{line_end, line_start, Span},
+    tree::{self, Child, Tree},
+    value::{self, path::Segment, PathError, Value},
+};
+
+/// The key-value pair at `inner` inside the value `child`.
+fn inner_pair<'t>(text: &str, child: &'t Child, inner: &[Segment]) -> Option<&'t Tree> {
+    let Child::Tree(tree) = child else {
+        return None;
+    };
+    match (inner.first()?, tree.kind) {
+        (Segment::Key(_), tree::Kind::InlineTable) => {
+            pairs(text, tree).into_iter().find_map(|pair| {
+                if pair.keys == inner {
+                    Some(pair.tree)
+                } else {
+                    let deeper = inner.strip_prefix(pair.keys.as_slice())?;
+                    inner_pair(text, value_of(pair.tree)?, deeper)
+                }
+            })
+        }
+        (Segment::Index(index), tree::Kind::Array) => {
+            inner_pair(text, tree.children.get(*index)?, &inner[1..])
+        }
+        _ => None,
+    }
+}
+
+/// The key-value pair or the header that writes the value at `keys`.
+fn node<'t>(text: &str, root: &'t Tree, keys: &[Segment]) -> Option<&'t Tree> {
+    for section in sections(text, root) {
+        if section.path == keys {
+            return section.header;
+        }
+        for (path, pair) in section.pair_paths() {
+            if path == keys {
+                return Some(pair.tree);
+            }
+            if let Some(inner) = keys.strip_prefix(path.as_slice()) {
+                return inner_pair(text, value_of(pair.tree)?, inner);
+            }
+        }
+    }
+    None
+}
+
+impl Document {
+    /// The node at `path`, written as for [`Value::get`], that comments
+    /// can belong to.
+    fn commented(&self, path: &str) -> Result<&Tree, PathError> {
+        let keys = parse(path)?;
+        let (table, _) = value::lower_with(&self.text, &self.tree, self.version);
+        Value::Table(table).get(path)?;
+        node(&self.text, &self.tree, &keys).ok_or_else(|| {
+            unsupported(path, "only key-value pairs and table headers have comments")
+        })
+    }
+
+    /// The comment of the key-value pair or the table header at `path`,
+    /// written as for [`Value::get`], without its `#`. Leading comments of
+    /// several lines are joined with `\n`.
+    ///
+    /// An element of an array of tables is named with its index, like
+    /// `bin[0]`.
+    pub fn comment(&self, path: &str, placement: Placement) -> Result<Option<String>, PathError> {
+        let comments = &self.commented(path)?.comments;
+        Ok(match placement {
+            Placement::Leading => comments.leading_text(&self.text),
+            Placement::Trailing => comments.trailing_text(&self.text).map(str::to_string),
+        })
+    }
+
+    /// Replaces the comment of the key-value pair or the table header at
+    /// `path` with `comment`, or removes it for `None`.
+    ///
+    /// Leading comments are written one line each, indented like the key.
+    /// A trailing comment must fit on one line, and can only be added when
+    /// nothing else follows on the line. The rest of the text is left as it
+    /// was.
+    pub fn set_comment(
+        &mut self,
+        path: &str,
+        placement: Placement,
+        comment: Option<&str>,
+    ) -> Result<(), PathError> {
+        let text = &self.text;
+        let node = self.commented(path)?;
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let line = |comment: &str| match comment {
+            "" => "#".to_string(),
+            comment => format!("# {comment}"),
+        };
+
+        let (span, new) = match placement {
+            Placement::Leading => {
+                let start = line_start(text, node.span.start);
+                let indent = &text[start..node.span.start];
+                if !indent.trim().is_empty() {
+                    return Err(unsupported(path, "the key doesn't start its line"));
+                }
+                let top = node
+                    .comments
+                    .leading
+                    .first()
+                    .map_or(start, |first| line_start(text, first.start));
+                let lines = comment.map_or(String::new(), |comment| {
+                    comment
+                        .lines()
+                        .map(|comment| format!("{indent}{}{newline}", line(comment)))
+                        .collect()
+                });
+                (Span::from(top..start), lines)
+            }
+            Placement::Trailing => {
+                if comment.is_some_and(|comment| comment.contains('\n')) {
+                    return Err(unsupported(path, "a trailing comment must fit on one line"));
+                }
+                match (node.comments.trailing, comment) {
+                    (Some(span), Some(comment)) => (span, line(comment)),
+                    (Some(span), None) => {
+                        let start = text[..span.start].trim_end_matches([' ', '\t']).len();
+                        (Span::from(start..span.end), String::new())
+                    }
+                    (None, Some(comment)) => {
+                        let end = comment::end_of(text, node);
+                        let rest = &text[end..line_end(text, end)];
+                        let after = rest.trim_start_matches([' ', '\t']);
+                        if !after.strip_prefix(',').unwrap_or(after).trim().is_empty() {
+                            return Err(unsupported(path, "something else follows on its line"));
+                        }
+                        let at = end + rest.trim_end().len();
+                        (Span::from(at..at), format!(" {}", line(comment)))
+                    }
+                    (None, None) => return Ok(()),
+                }
+            }
+        };
+        self.edit(span, &new);
+        Ok(())
+    }
+}
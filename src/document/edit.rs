@@ -0,0 +1,653 @@
+//! Edits by key path that leave the rest of the document, comments and
+//! layout included, exactly as it was.
+
+use std::collections::HashMap;
+
+use super::Document;
+use crate::{
+    span::{line_end, line_start, next_line, Span},
+    tree::{self, Child, Tree},
+    value::{
+        self,
+        path::{self, Segment},
+        write, PathError, PathErrorKind, Value,
+    },
+};
+
+/// Bytes to replace and what to replace them with.
+type Splice = (Span, String);
+
+/// A table written as a run of key-value pairs: the top level, a `[table]`
+/// or an element of an array of tables.
+pub(super) struct Section<'t> {
+    /// Where the table is, with the index of each array of tables on the way.
+    pub path: Vec<Segment>,
+    /// `None` for the top level.
+    pub header: Option<&'t Tree>,
+    pairs: Vec<Pair<'t>>,
+}
+
+/// A key-value pair, with its dotted key split into parts.
+pub(super) struct Pair<'t> {
+    pub keys: Vec<Segment>,
+    pub tree: &'t Tree,
+}
+
+impl<'t> Section<'t> {
+    /// Where the last line of the section ends, before its line ending.
+    fn end(&self, text: &str) -> Option<usize> {
+        let last = match self.pairs.last() {
+            Some(pair) => pair.tree.span.end,
+            None => key_of(self.header?)?.span.end,
+        };
+        Some(line_end(text, last))
+    }
+
+    /// The path of every key-value pair, from the top level.
+    pub fn pair_paths(&self) -> impl Iterator<Item = (Vec<Segment>, &Pair<'t>)> {
+        self.pairs
+            .iter()
+            .map(|pair| ([self.path.as_slice(), &pair.keys].concat(), pair))
+    }
+}
+
+fn key_of(tree: &Tree) -> Option<&Tree> {
+    tree.children.iter().find_map(|child| match child {
+        Child::Tree(key) if key.kind == tree::Kind::Key => Some(key),
+        _ => None,
+    })
+}
+
+pub(super) fn value_of(pair: &Tree) -> Option<&Child> {
+    pair.children
+        .iter()
+        .find(|child| !matches!(child, Child::Tree(key) if key.kind == tree::Kind::Key))
+}
+
+fn span_of(child: &Child) -> Span {
+    match child {
+        Child::Tree(tree) => tree.span,
+        Child::Token(token) => token.span,
+    }
+}
+
+fn keys(text: &str, key: &Tree) -> Vec<Segment> {
+    value::key_names(text, key)
+        .into_iter()
+        .map(Segment::Key)
+        .collect()
+}
+
+pub(super) fn pairs<'t>(text: &str, tree: &'t Tree) -> Vec<Pair<'t>> {
+    tree.children
+        .iter()
+        .filter_map(|child| match child {
+            Child::Tree(pair) if pair.kind == tree::Kind::KeyVal => Some(Pair {
+                keys: keys(text, key_of(pair)?),
+                tree: pair,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The tables of the document in order, starting with the top level.
+pub(super) fn sections<'t>(text: &str, root: &'t Tree) -> Vec<Section<'t>> {
+    let mut sections = vec![Section {
+        path: Vec::new(),
+        header: None,
+        pairs: pairs(text, root),
+    }];
+    // How many elements each array of tables has so far
+    let mut arrays: HashMap<Vec<Segment>, usize> = HashMap::new();
+
+    for child in &root.children {
+        let Child::Tree(header) = child else {
+            continue;
+        };
+        if !matches!(header.kind, tree::Kind::Table | tree::Kind::TableArray) {
+            continue;
+        }
+        let Some(key) = key_of(header) else {
+            continue;
+        };
+
+        let names = keys(text, key);
+        let len = names.len();
+        let mut path = Vec::new();
+        for (i, name) in names.into_iter().enumerate() {
+            path.push(name);
+            if i + 1 == len && header.kind == tree::Kind::TableArray {
+                let count = arrays.entry(path.clone()).or_default();
+                path.push(Segment::Index(*count));
+                *count += 1;
+            } else if let Some(count) = arrays.get(&path) {
+                path.push(Segment::Index(count - 1));
+            }
+        }
+
+        sections.push(Section {
+            path,
+            header: Some(header),
+            pairs: pairs(text, header),
+        });
+    }
+
+    sections
+}
+
+/// Where the comment lines right above the line starting at `start` begin.
+fn above(text: &str, mut start: usize) -> usize {
+    while start > 0 {
+        let previous = line_start(text, start - 1);
+        if !text[previous..start].trim_start().starts_with('#') {
+            break;
+        }
+        start = previous;
+    }
+    start
+}
+
+/// The whole lines `span` is on, with the comments right above them and the
+/// last line ending.
+fn lines(text: &str, span: Span) -> Span {
+    let start = above(text, line_start(text, span.start));
+    let end = next_line(text, span.end);
+    Span::from(start..end)
+}
+
+/// The lines of the `index`th section, up to the next one.
+fn region(text: &str, sections: &[Section], index: usize) -> Option<Span> {
+    let start_of = |section: &Section| {
+        section
+            .header
+            .map(|header| above(text, line_start(text, header.span.start)))
+    };
+    let start = start_of(&sections[index])?;
+    let end = match sections.get(index + 1) {
+        Some(next) => start_of(next)?,
+        None => text.len(),
+    };
+    Some(Span::from(start..end))
+}
+
+fn common(a: &[Segment], b: &[Segment]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// `keys` as a dotted key, leaving out indices.
+fn dotted(keys: &[Segment]) -> String {
+    keys.iter()
+        .filter_map(|segment| match segment {
+            Segment::Key(name) => Some(write::key(name)),
+            Segment::Index(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Whether a header can name the table at `path`: it can't when the path
+/// goes through an element of an array of tables other than the last one.
+fn nameable(sections: &[Section], path: &[Segment]) -> bool {
+    path.iter().enumerate().all(|(i, segment)| match segment {
+        Segment::Index(index) => {
+            let elements = sections
+                .iter()
+                .filter(|section| {
+                    section.path.len() == i + 1
+                        && section.path.starts_with(&path[..i])
+                        && matches!(section.path[i], Segment::Index(_))
+                })
+                .count();
+            index + 1 == elements
+        }
+        Segment::Key(_) => true,
+    })
+}
+
+/// Whether a key-value pair writes the value at `keys` or one it's inside.
+fn in_pair(sections: &[Section], keys: &[Segment]) -> bool {
+    sections
+        .iter()
+        .flat_map(Section::pair_paths)
+        .any(|(path, _)| keys.starts_with(&path))
+}
+
+/// Adds `item` after the last of `items`, inside the array or inline table
+/// `container`.
+fn append_item(container: &Tree, items: &[Span], item: &str, open: &str, close: &str) -> Splice {
+    match items.last() {
+        Some(last) => (Span::from(last.end..last.end), format!(", {item}")),
+        None => (container.span, format!("{open}{item}{close}")),
+    }
+}
+
+/// Removes the `index`th of `items`, with the comma next to it.
+fn remove_item(container: &Tree, items: &[Span], index: usize, empty: &str) -> Splice {
+    let span = if items.len() == 1 {
+        return (container.span, empty.to_string());
+    } else if index + 1 < items.len() {
+        Span::from(items[index].start..items[index + 1].start)
+    } else {
+        Span::from(items[index - 1].end..items[index].end)
+    };
+    (span, String::new())
+}
+
+/// Sets the value at `inner` inside the value `child` to `new`.
+fn set_inner(text: &str, child: &Child, inner: &[Segment], new: &str) -> Option<Splice> {
+    let Some(first) = inner.first() else {
+        return Some((span_of(child), new.to_string()));
+    };
+    let Child::Tree(tree) = child else {
+        return None;
+    };
+    match (first, tree.kind) {
+        (Segment::Key(_), tree::Kind::InlineTable) => {
+            let pairs = pairs(text, tree);
+            for pair in &pairs {
+                if let Some(deeper) = inner.strip_prefix(pair.keys.as_slice()) {
+                    return set_inner(text, value_of(pair.tree)?, deeper, new);
+                }
+            }
+            // A new key names no array to put an element in
+            if inner
+                .iter()
+                .any(|segment| matches!(segment, Segment::Index(_)))
+            {
+                return None;
+            }
+            let items = pairs.iter().map(|pair| pair.tree.span).collect::<Vec<_>>();
+            let item = format!("{} = {new}", dotted(inner));
+            Some(append_item(tree, &items, &item, "{ ", " }"))
+        }
+        (Segment::Index(index), tree::Kind::Array) => match tree.children.get(*index) {
+            Some(item) => set_inner(text, item, &inner[1..], new),
+            None if *index == tree.children.len() && inner.len() == 1 => {
+                let items = tree.children.iter().map(span_of).collect::<Vec<_>>();
+                Some(append_item(tree, &items, new, "[", "]"))
+            }
+            None => None,
+        },
+        _ => None,
+    }
+}
+
+/// Removes the value at `inner` inside the value `child`.
+fn unset_inner(text: &str, child: &Child, inner: &[Segment]) -> Option<Splice> {
+    let Child::Tree(tree) = child else {
+        return None;
+    };
+    match (inner.first()?, tree.kind) {
+        (Segment::Key(_), tree::Kind::InlineTable) => {
+            let pairs = pairs(text, tree);
+            let removed = pairs
+                .iter()
+                .enumerate()
+                .filter(|(_, pair)| pair.keys.starts_with(inner))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            match removed[..] {
+                [] => {
+                    let pair = pairs.iter().find(|pair| inner.starts_with(&pair.keys))?;
+                    unset_inner(text, value_of(pair.tree)?, &inner[pair.keys.len()..])
+                }
+                [index] => {
+                    let items = pairs.iter().map(|pair| pair.tree.span).collect::<Vec<_>>();
+                    Some(remove_item(tree, &items, index, "{}"))
+                }
+                // Dotted keys spread over the table, like `{ a.b = 1, a.c = 2 }`
+                _ => {
+                    let kept = pairs
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| !removed.contains(i))
+                        .map(|(_, pair)| &text[pair.tree.span.start..pair.tree.span.end])
+                        .collect::<Vec<_>>();
+                    let rewritten = if kept.is_empty() {
+                        "{}".to_string()
+                    } else {
+                        format!("{{ {} }}", kept.join(", "))
+                    };
+                    Some((tree.span, rewritten))
+                }
+            }
+        }
+        (Segment::Index(index), tree::Kind::Array) => {
+            if inner.len() == 1 && *index < tree.children.len() {
+                let items = tree.children.iter().map(span_of).collect::<Vec<_>>();
+                Some(remove_item(tree, &items, *index, "[]"))
+            } else {
+                unset_inner(text, tree.children.get(*index)?, &inner[1..])
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The line ending to add after text inserted at `end`, if that's the end
+/// of a last line that has none, which would end a table without a newline.
+fn final_newline(text: &str, end: usize) -> &'static str {
+    if end == text.len() && !text.is_empty() {
+        "\n"
+    } else {
+        ""
+    }
+}
+
+/// Adds the line `line` as the last key-value pair of the `index`th section.
+fn append_pair(text: &str, sections: &[Section], index: usize, line: &str) -> Splice {
+    if let Some(end) = sections[index].end(text) {
+        return (
+            Span::from(end..end),
+            format!("\n{line}{}", final_newline(text, end)),
+        );
+    }
+    // The top level without pairs: before the first header and its comments
+    match sections.get(1).and_then(|section| section.header) {
+        Some(header) => {
+            let start = above(text, line_start(text, header.span.start));
+            (Span::from(start..start), format!("{line}\n\n"))
+        }
+        None => {
+            let end = text.len();
+            let newline = if text.is_empty() || text.ends_with('\n') {
+                ""
+            } else {
+                "\n"
+            };
+            (Span::from(end..end), format!("{newline}{line}\n"))
+        }
+    }
+}
+
+/// Adds `section`, a header and its first pair, after `after`.
+fn insert_section(text: &str, after: &Section, section: &str) -> Splice {
+    match after.end(text) {
+        Some(end) => (
+            Span::from(end..end),
+            format!("\n\n{section}{}", final_newline(text, end)),
+        ),
+        None => {
+            let end = text.len();
+            let separator = if text.trim().is_empty() {
+                ""
+            } else if text.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            };
+            (Span::from(end..end), format!("{separator}{section}\n"))
+        }
+    }
+}
+
+/// The splice that sets the value at `keys` to `new`, which isn't written
+/// under a header.
+fn set_splice(text: &str, root: &Tree, keys: &[Segment], new: &str) -> Option<Splice> {
+    let sections = sections(text, root);
+
+    // A key-value pair holds the value or the one it's inside
+    for section in &sections {
+        for (path, pair) in section.pair_paths() {
+            if let Some(inner) = keys.strip_prefix(path.as_slice()) {
+                return set_inner(text, value_of(pair.tree)?, inner, new);
+            }
+        }
+    }
+
+    let (index, section) = sections
+        .iter()
+        .enumerate()
+        .filter(|(_, section)| keys.starts_with(&section.path))
+        .max_by_key(|(_, section)| section.path.len())?;
+    let rest = &keys[section.path.len()..];
+    // The closest relative of a new table, to put it after
+    let after = |path: &[Segment]| {
+        sections
+            .iter()
+            .filter(|other| other.path.starts_with(&section.path))
+            .max_by_key(|other| common(&other.path, path))
+    };
+
+    // A new element of an array of tables
+    if let Some(at) = rest
+        .iter()
+        .position(|segment| matches!(segment, Segment::Index(_)))
+    {
+        let array = &keys[..section.path.len() + at];
+        let Segment::Index(element) = rest[at] else {
+            return None;
+        };
+        let pair = &rest[at + 1..];
+        let elements = sections
+            .iter()
+            .filter(|other| {
+                other.path.len() == array.len() + 1
+                    && other.path.starts_with(array)
+                    && matches!(other.path[array.len()], Segment::Index(_))
+            })
+            .count();
+        if element != elements
+            || pair.is_empty()
+            || pair
+                .iter()
+                .any(|segment| matches!(segment, Segment::Index(_)))
+            || !nameable(&sections, array)
+        {
+            return None;
+        }
+        let header = format!("[[{}]]\n{} = {new}", dotted(array), dotted(pair));
+        return Some(insert_section(text, after(array)?, &header));
+    }
+
+    // Other keys starting the same way are written as dotted keys
+    let sibling = section
+        .pairs
+        .iter()
+        .filter(|pair| pair.keys.len() > 1 && pair.keys[0] == rest[0])
+        .max_by_key(|pair| common(&pair.keys, rest));
+    if let Some(pair) = sibling {
+        let end = line_end(text, pair.tree.span.end);
+        return Some((Span::from(end..end), format!("\n{} = {new}", dotted(rest))));
+    }
+
+    let (table, last) = keys.split_at(keys.len() - 1);
+    if rest.len() == 1 || !nameable(&sections, table) {
+        let line = format!("{} = {new}", dotted(rest));
+        return Some(append_pair(text, &sections, index, &line));
+    }
+
+    let header = format!("[{}]\n{} = {new}", dotted(table), dotted(last));
+    Some(insert_section(text, after(table)?, &header))
+}
+
+/// Where the value at `keys` is written: the value of a key-value pair, an
+/// element of an array or the key of a table's header. Falls back to the
+/// closest enclosing value written out, if the value isn't.
+pub(crate) fn locate(text: &str, root: &Tree, keys: &[Segment]) -> Option<Span> {
+    let sections = sections(text, root);
+    for section in &sections {
+        for (path, pair) in section.pair_paths() {
+            if let Some(inner) = keys.strip_prefix(path.as_slice()) {
+                return Some(locate_inner(text, value_of(pair.tree)?, inner));
+            }
+        }
+    }
+
+    let header = |exact: bool| {
+        sections
+            .iter()
+            .filter(|section| keys.starts_with(&section.path))
+            .filter(|section| !exact || section.path.len() == keys.len())
+            .max_by_key(|section| section.path.len())
+            .and_then(|section| key_of(section.header?))
+    };
+    // A table only written through dotted keys
+    let dotted = || {
+        sections
+            .iter()
+            .flat_map(Section::pair_paths)
+            .find(|(path, _)| path.starts_with(keys))
+            .and_then(|(_, pair)| key_of(pair.tree))
+    };
+    header(true)
+        .or_else(dotted)
+        .or_else(|| header(false))
+        .map(|key| key.span)
+}
+
+fn locate_inner(text: &str, child: &Child, inner: &[Segment]) -> Span {
+    let span = span_of(child);
+    let (Some(first), Child::Tree(tree)) = (inner.first(), child) else {
+        return span;
+    };
+    match (first, tree.kind) {
+        (Segment::Key(_), tree::Kind::InlineTable) => pairs(text, tree)
+            .iter()
+            .find_map(|pair| {
+                let deeper = inner.strip_prefix(pair.keys.as_slice())?;
+                Some(locate_inner(text, value_of(pair.tree)?, deeper))
+            })
+            .unwrap_or(span),
+        (Segment::Index(index), tree::Kind::Array) => tree
+            .children
+            .get(*index)
+            .map_or(span, |item| locate_inner(text, item, &inner[1..])),
+        _ => span,
+    }
+}
+
+/// Parses `path`, which must name something below the top level.
+pub(super) fn parse(path: &str) -> Result<Vec<Segment>, PathError> {
+    let segments = path::parse(path)?;
+    if segments.is_empty() {
+        return Err(PathError {
+            kind: PathErrorKind::Invalid("expected a key"),
+            span: Span::from(0..path.len()),
+        });
+    }
+    Ok(segments.into_iter().map(|(segment, _)| segment).collect())
+}
+
+pub(super) fn unsupported(path: &str, message: &'static str) -> PathError {
+    PathError {
+        kind: PathErrorKind::Unsupported(message),
+        span: Span::from(0..path.len()),
+    }
+}
+
+impl Document {
+    /// Sets the value at `path`, written as for [`Value::get`], to `value`.
+    ///
+    /// An existing key keeps its place and only its value is replaced. A new
+    /// key goes after the last key-value pair of its table, and a missing
+    /// table gets a `[header]` after its closest relative, or at the end.
+    /// An index one past the end of an array, or of an array of tables,
+    /// appends to it. A table written under headers is replaced by a
+    /// key-value pair. The rest of the text is left as it was.
+    pub fn set(&mut self, path: &str, value: &Value) -> Result<(), PathError> {
+        let keys = parse(path)?;
+        let (table, _) = value::lower_with(&self.text, &self.tree, self.version);
+        let root = Value::Table(table);
+        let found = root.get(path);
+
+        match &found {
+            Ok(Value::Table(_) | Value::Array(_))
+                if !in_pair(&sections(&self.text, &self.tree), &keys) =>
+            {
+                if matches!(keys.last(), Some(Segment::Index(_))) {
+                    return Err(unsupported(
+                        path,
+                        "an element of an array of tables can't be replaced, set its keys instead",
+                    ));
+                }
+                self.unset(path)?;
+            }
+            Ok(_)
+            | Err(PathError {
+                kind: PathErrorKind::MissingKey { .. },
+                ..
+            }) => {}
+            Err(PathError {
+                kind: PathErrorKind::OutOfBounds { index, len, .. },
+                ..
+            }) if index == len => {}
+            Err(error) => return Err(error.clone()),
+        }
+
+        let Some((span, mut text)) = set_splice(&self.text, &self.tree, &keys, &value.to_string())
+        else {
+            return Err(found
+                .err()
+                .unwrap_or_else(|| unsupported(path, "the value can't be set in place")));
+        };
+        // Values are written on one line, so any line ending is ours
+        if self.text.contains("\r\n") {
+            text = text.replace('\n', "\r\n");
+        }
+        self.edit(span, &text);
+        Ok(())
+    }
+
+    /// Removes the value at `path`, written as for [`Value::get`], with the
+    /// lines that define it and the comments right above them.
+    ///
+    /// Removing a table removes its header, its key-value pairs and all of
+    /// its sub-tables. The rest of the text is left as it was.
+    pub fn unset(&mut self, path: &str) -> Result<(), PathError> {
+        let keys = parse(path)?;
+        let (table, _) = value::lower_with(&self.text, &self.tree, self.version);
+        Value::Table(table).get(path)?;
+
+        let text = &self.text;
+        let sections = sections(text, &self.tree);
+        let mut splices = Vec::new();
+
+        for (index, section) in sections.iter().enumerate() {
+            if section.header.is_some() && section.path.starts_with(&keys) {
+                splices.extend(region(text, &sections, index).map(|span| (span, String::new())));
+                continue;
+            }
+            for (path, pair) in section.pair_paths() {
+                if path.starts_with(&keys) {
+                    splices.push((lines(text, pair.tree.span), String::new()));
+                } else if let Some(inner) = keys.strip_prefix(path.as_slice()) {
+                    let value = value_of(pair.tree);
+                    splices.extend(value.and_then(|value| unset_inner(text, value, inner)));
+                }
+            }
+        }
+
+        if splices.is_empty() {
+            return Err(unsupported(path, "the value can't be removed in place"));
+        }
+        splices.sort_by_key(|(span, _)| span.start);
+        // The byte order mark stays where it is, and removals that touch
+        // are one, so that they can't overlap once extended below
+        let bom = if text.starts_with('\u{feff}') { 3 } else { 0 };
+        let mut merged: Vec<Splice> = Vec::with_capacity(splices.len());
+        for (mut span, new) in splices {
+            span.start = span.start.max(bom);
+            match merged.last_mut() {
+                Some((last, old)) if span.start <= last.end && old.is_empty() && new.is_empty() => {
+                    last.end = last.end.max(span.end);
+                }
+                _ => merged.push((span, new)),
+            }
+        }
+        // Don't leave the blank lines before the last table behind
+        if let Some((last, _)) = merged.last_mut()
+            && last.end == text.len()
+        {
+            let kept = text[..last.start].trim_end().len();
+            if kept > bom {
+                last.start = next_line(text, kept).min(last.start);
+            }
+        }
+        for (span, text) in merged.into_iter().rev() {
+            self.edit(span, &text);
+        }
+        Ok(())
+    }
+}
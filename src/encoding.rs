@@ -0,0 +1,49 @@
+use tower_lsp::lsp_types::{ClientCapabilities, PositionEncodingKind};
+
+/// Unit in which the `character` field of an LSP `Position` is counted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Picks the encoding to use from the ones offered by the client.
+    ///
+    /// Clients that do not send `general.positionEncodings` only understand
+    /// UTF-16, which is the mandatory default of the protocol.
+    pub fn negotiate(capabilities: &ClientCapabilities) -> Self {
+        let Some(offered) = capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_ref())
+        else {
+            return Self::default();
+        };
+
+        // Cheapest conversions first
+        [Self::Utf8, Self::Utf32, Self::Utf16]
+            .into_iter()
+            .find(|encoding| offered.contains(&encoding.kind()))
+            .unwrap_or_default()
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Number of code units `c` takes in this encoding.
+    pub fn width(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
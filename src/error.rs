@@ -0,0 +1,219 @@
+use crate::{config::Severity, lint::LINTS_URL, span::Span, token};
+
+/// Where the error catalogue lives; each code has an anchor of its own.
+pub const ERRORS_URL: &str = "https://github.com/AOx0/toml-lsp/blob/main/docs/errors.md";
+
+/// A problem found in the source, with everything needed to explain it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub span: Span,
+    pub kind: ErrorKind,
+    /// Extra facts about the error, shown after the message.
+    pub notes: Vec<String>,
+    /// A suggestion on how to fix the error.
+    pub help: Option<String>,
+    /// Secondary spans that give context, like where a bracket was opened.
+    pub labels: Vec<Label>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnexpectedCharacters(String),
+    UnclosedString {
+        delimiter: &'static str,
+    },
+    /// Any of the tokens in `expected` would do there. `after` describes
+    /// what came right before, like "key `name`".
+    Expected {
+        expected: &'static [token::Kind],
+        after: Option<String>,
+    },
+    /// A closing delimiter is missing; `what` is the construct it closes.
+    Unclosed {
+        delimiter: token::Kind,
+        what: &'static str,
+    },
+    MissingKey,
+    MissingValue,
+    MissingKeyValue,
+    NewlineInInlineTable,
+    InvalidNumber,
+    UnexpectedEof,
+    UnexpectedToken(token::Kind),
+    Stuck,
+    /// A key, or table, defined twice.
+    DuplicateKey(String),
+    /// A key used as a table, like `a` in `a.b` when `a` is a value.
+    NotATable(String),
+    InvalidEscape(String),
+    /// A bare key with characters outside ASCII, which must be quoted.
+    NonAsciiKey(String),
+    /// A control character where TOML doesn't allow one.
+    ControlCharacter(char),
+    /// A `\r` that isn't followed by `\n`.
+    BareCarriageReturn,
+    /// A value that doesn't fit the Rust type it's deserialized into.
+    Deserialize(String),
+    /// A setting of a project file that isn't valid, described by the
+    /// message.
+    InvalidSetting(String),
+    /// A setting of a project file that is reserved, and has no effect
+    /// yet.
+    UnusedSetting(String),
+    /// Something a lint rule found, rather than a mistake in the TOML.
+    Lint {
+        rule: &'static str,
+        severity: Severity,
+        message: String,
+    },
+}
+
+impl ErrorKind {
+    /// Stable identifier of the error, also used as its anchor in
+    /// [`ERRORS_URL`]. Codes are never reused once published. Lints are
+    /// identified by the id of their rule instead.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedCharacters(_) => "E0001",
+            Self::UnclosedString { .. } => "E0002",
+            Self::Expected { .. } => "E0003",
+            Self::Unclosed { .. } => "E0004",
+            Self::MissingKey => "E0005",
+            Self::MissingValue => "E0006",
+            Self::MissingKeyValue => "E0007",
+            Self::NewlineInInlineTable => "E0008",
+            Self::InvalidNumber => "E0009",
+            Self::UnexpectedEof => "E0010",
+            Self::UnexpectedToken(_) => "E0011",
+            Self::Stuck => "E0012",
+            Self::DuplicateKey(_) => "E0013",
+            Self::NotATable(_) => "E0014",
+            Self::InvalidEscape(_) => "E0015",
+            Self::Deserialize(_) => "E0016",
+            Self::InvalidSetting(_) => "E0017",
+            Self::NonAsciiKey(_) => "E0018",
+            Self::ControlCharacter(_) => "E0019",
+            Self::BareCarriageReturn => "E0020",
+            Self::UnusedSetting(_) => "E0021",
+            Self::Lint { rule, .. } => rule,
+        }
+    }
+}
+
+/// `kinds` as a choice, like "`=`, `.` or a newline".
+fn one_of(kinds: &[token::Kind]) -> String {
+    match kinds {
+        [] => "something else".to_string(),
+        [kind] => kind.to_string(),
+        [rest @ .., last] => {
+            let rest = rest.iter().map(ToString::to_string).collect::<Vec<_>>();
+            format!("{} or {last}", rest.join(", "))
+        }
+    }
+}
+
+impl core::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::UnexpectedCharacters(text) => write!(f, "unexpected characters `{text}`"),
+            Self::UnclosedString { delimiter } => {
+                write!(f, "unclosed string, expected a closing `{delimiter}`")
+            }
+            Self::Expected {
+                expected,
+                after: Some(after),
+            } => write!(f, "expected {} after {after}", one_of(expected)),
+            Self::Expected {
+                expected,
+                after: None,
+            } => write!(f, "expected {}", one_of(expected)),
+            Self::Unclosed { delimiter, what } => write!(f, "expected {delimiter} to close {what}"),
+            Self::MissingKey => write!(f, "expected a key"),
+            Self::MissingValue => write!(f, "expected a value"),
+            Self::MissingKeyValue => write!(f, "expected a key-value pair"),
+            Self::NewlineInInlineTable => write!(f, "newlines are not allowed in inline tables"),
+            Self::InvalidNumber => write!(f, "invalid number"),
+            Self::UnexpectedEof => write!(f, "unexpected end of file"),
+            Self::UnexpectedToken(kind) => write!(f, "unexpected {kind}"),
+            Self::Stuck => write!(f, "the parser could not make progress"),
+            Self::DuplicateKey(key) => write!(f, "`{key}` is defined more than once"),
+            Self::NotATable(key) => write!(f, "`{key}` is not a table"),
+            Self::InvalidEscape(escape) => write!(f, "invalid escape `{escape}`"),
+            Self::Deserialize(message) => write!(f, "{message}"),
+            Self::InvalidSetting(message) => write!(f, "invalid setting {message}"),
+            Self::UnusedSetting(setting) => write!(f, "setting `{setting}` has no effect yet"),
+            Self::NonAsciiKey(key) => write!(f, "`{key}` must be quoted to be a key"),
+            Self::ControlCharacter(c) => {
+                write!(f, "control character U+{:04X} is not allowed", *c as u32)
+            }
+            Self::BareCarriageReturn => write!(f, "carriage return without a line feed"),
+            Self::Lint { message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    pub fn new(span: Span, kind: ErrorKind) -> Self {
+        Self {
+            span,
+            kind,
+            notes: Vec::new(),
+            help: None,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    /// How bad the error is. Only lints and settings that have no effect
+    /// can be less than errors.
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            ErrorKind::Lint { severity, .. } => severity,
+            ErrorKind::UnusedSetting(_) => Severity::Warn,
+            _ => Severity::Error,
+        }
+    }
+
+    /// Link to the explanation of this error's code.
+    pub fn url(&self) -> String {
+        match self.kind {
+            ErrorKind::Lint { rule, .. } => format!("{LINTS_URL}#{rule}"),
+            _ => format!("{ERRORS_URL}#{}", self.code().to_lowercase()),
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+}
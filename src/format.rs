@@ -0,0 +1,227 @@
+//! Canonical layout for TOML documents.
+//!
+//! The formatter walks every token of the source, comments included, and
+//! only decides the whitespace between them, so whatever the input no
+//! content is ever dropped or reordered.
+
+use crate::{
+    lexer::Lexer,
+    token::{self, Token},
+};
+
+/// Choices the canonical layout leaves open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Spaces per level of indentation inside arrays and inline tables.
+    pub indent_width: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self { indent_width: 4 }
+    }
+}
+
+/// Bracket the formatter is inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Open {
+    Header,
+    Array,
+    InlineTable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Blank,
+    Comment,
+    Header,
+    Other,
+}
+
+struct Line {
+    kind: LineKind,
+    text: String,
+}
+
+/// Lays out `source` the canonical way:
+///
+/// - one space around `=`, after `,` and inside `{ }`, none inside `[ ]`
+///   or around the `.` of dotted keys,
+/// - no indentation at the top level and four spaces per level inside
+///   arrays and inline tables spanning several lines, or as many as
+///   [`Style::indent_width`] says with [`format_with`],
+/// - at most one blank line in a row, one before each table header and its
+///   comments, and a single newline at the end of the file.
+///
+/// Line endings follow the source: `\r\n` if it uses them, `\n` otherwise.
+pub fn format(source: &str) -> String {
+    format_with(source, Style::default())
+}
+
+/// Lays out `source` like [`format`] does, with the choices of `style`.
+pub fn format_with(source: &str, style: Style) -> String {
+    let newline = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let lines = layout(source, &" ".repeat(style.indent_width));
+
+    let mut out = String::with_capacity(source.len());
+    // The byte order mark lexes as whitespace, but belongs to the file
+    if source.starts_with('\u{feff}') {
+        out.push('\u{feff}');
+    }
+    for line in clean(lines) {
+        out.push_str(&line.text);
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Splits the tokens of `source` into lines, spacing the tokens of each.
+fn layout(source: &str, indent: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut stack: Vec<Open> = Vec::new();
+
+    let mut text = String::new();
+    let mut kind = LineKind::Other;
+    let mut previous: Option<(token::Kind, Option<Open>)> = None;
+
+    for Token { kind: token, span } in Lexer::tokens(source) {
+        let raw = &source[span.start..span.end];
+
+        match token {
+            token::Kind::Space => continue,
+            token::Kind::Newline => {
+                // Each line ending is a token of its own
+                lines.push(Line {
+                    kind: if text.is_empty() {
+                        LineKind::Blank
+                    } else {
+                        kind
+                    },
+                    text: core::mem::take(&mut text),
+                });
+                previous = None;
+                continue;
+            }
+            _ => {}
+        }
+
+        let inside = stack.last().copied();
+        let opened = match token {
+            token::Kind::LBracket if text.is_empty() && stack.is_empty() => Some(Open::Header),
+            token::Kind::LBracket if inside == Some(Open::Header) => Some(Open::Header),
+            token::Kind::LBracket => Some(Open::Array),
+            token::Kind::LCurly => Some(Open::InlineTable),
+            _ => None,
+        };
+
+        match previous {
+            None => {
+                let closes = matches!(token, token::Kind::RBracket | token::Kind::RCurly);
+                let depth = stack.iter().filter(|open| **open != Open::Header).count();
+                let depth = depth.saturating_sub(usize::from(closes));
+                text.push_str(&indent.repeat(depth));
+
+                kind = match (token, opened) {
+                    (token::Kind::Comment, _) => LineKind::Comment,
+                    (_, Some(Open::Header)) => LineKind::Header,
+                    _ => LineKind::Other,
+                };
+            }
+            Some((previous, previous_opened)) => {
+                if space_between(previous, previous_opened, token, inside) {
+                    text.push(' ');
+                }
+            }
+        }
+
+        match token {
+            token::Kind::Comment => text.push_str(raw.trim_end()),
+            _ => text.push_str(raw),
+        }
+
+        match (token, inside) {
+            (token::Kind::RBracket, Some(Open::Header | Open::Array))
+            | (token::Kind::RCurly, Some(Open::InlineTable)) => {
+                stack.pop();
+            }
+            _ => {}
+        }
+        if let Some(open) = opened {
+            stack.push(open);
+        }
+
+        previous = Some((token, opened));
+    }
+
+    if !text.is_empty() {
+        lines.push(Line { kind, text });
+    }
+
+    lines
+}
+
+/// Whether a space goes between two tokens on the same line. `opened` is
+/// the bracket `previous` opened, if any, and `inside` the innermost bracket
+/// around `next`.
+fn space_between(
+    previous: token::Kind,
+    opened: Option<Open>,
+    next: token::Kind,
+    inside: Option<Open>,
+) -> bool {
+    use token::Kind::*;
+
+    match (previous, next) {
+        (_, Comment) => true,
+        _ if inside == Some(Open::Header) => false,
+        (Dot, _) | (_, Dot) | (_, Comma) => false,
+        (Comma, _) => true,
+        (LBracket, _) if opened == Some(Open::Array) => false,
+        (_, RBracket) if inside == Some(Open::Array) => false,
+        (LCurly, RCurly) => false,
+        _ => true,
+    }
+}
+
+/// Separates table headers from what comes before them and collapses runs
+/// of blank lines.
+fn clean(lines: Vec<Line>) -> Vec<Line> {
+    let mut out: Vec<Line> = Vec::with_capacity(lines.len());
+
+    for line in lines {
+        if line.kind == LineKind::Header {
+            // Comments right above a header belong to it
+            let group = out
+                .iter()
+                .rev()
+                .take_while(|line| line.kind == LineKind::Comment)
+                .count();
+            let at = out.len() - group;
+            if at > 0 && out[at - 1].kind != LineKind::Blank {
+                out.insert(
+                    at,
+                    Line {
+                        kind: LineKind::Blank,
+                        text: String::new(),
+                    },
+                );
+            }
+        }
+
+        let blank = line.kind == LineKind::Blank;
+        let after_blank = out.last().is_none_or(|last| last.kind == LineKind::Blank);
+        if !(blank && after_blank) {
+            out.push(line);
+        }
+    }
+
+    while out.last().is_some_and(|last| last.kind == LineKind::Blank) {
+        out.pop();
+    }
+
+    out
+}
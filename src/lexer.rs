@@ -2,6 +2,7 @@ use const_str::to_char_array;
 
 use crate::{
     cursor::Cursor,
+    error::{Error, ErrorKind},
     span::Span,
     token::{self, Token},
 };
@@ -18,27 +19,58 @@ pub struct Lexer<'src, const LOOK: usize = 3> {
     current_kind: [token::Kind; LOOK],
     current_span: [Span; LOOK],
     last_span: Span,
+    /// Whether the last significant token ended a line, or there was none
+    /// yet, so that the next line ending ends a blank line.
+    line_start: bool,
+    /// Errors found while filling the lookahead in [`Lexer::new`].
+    pending: Vec<Error>,
+}
+
+impl<'src> Lexer<'src> {
+    /// Every token in `source` in order, including whitespace, comments and
+    /// malformed tokens, which the parser never sees.
+    pub fn tokens(source: &'src str) -> impl Iterator<Item = Token> + 'src {
+        let mut lexer = Lexer::<1>::bare(source);
+        core::iter::from_fn(move || {
+            let token = lexer.next_impl();
+            (token.kind != token::Kind::Eof).then_some(token)
+        })
+    }
 }
 
 impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
-    pub fn new(source: &'src str) -> Self {
-        let mut res = Self {
+    fn bare(source: &'src str) -> Self {
+        Self {
             cursor: Cursor::new(source),
             current_kind: [token::Kind::Eof; LOOK],
             current_span: [Span::from(0..0); LOOK],
             last_span: Span { start: 0, end: 0 },
-        };
+            line_start: true,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn new(source: &'src str) -> Self {
+        let mut res = Self::bare(source);
 
+        let mut pending = Vec::new();
         for i in 0..LOOK {
-            let token = res.next_impl();
+            let token = res.next_significant(Some(&mut pending));
             res.current_kind[i] = token.kind;
             res.current_span[i] = token.span;
         }
+        res.pending = pending;
 
         res
     }
 
-    pub fn source(&'src self) -> &'src str {
+    /// Takes the errors of the tokens lexed by [`Lexer::new`], which had no
+    /// error list to report them to.
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        core::mem::take(&mut self.pending)
+    }
+
+    pub fn source(&self) -> &'src str {
         self.cursor.source()
     }
 
@@ -58,7 +90,7 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
 
     pub fn peek_kind_array<const N: usize>(&self) -> [token::Kind; N] {
         const {
-            assert!(N < LOOK);
+            assert!(N <= LOOK);
         };
         let mut res = [token::Kind::Eof; N];
         res.copy_from_slice(&self.current_kind[0..N]);
@@ -66,58 +98,162 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
         res
     }
 
-    pub fn next_token(&mut self, mut errors: Option<&mut Vec<crate::parser::Error>>) -> Token {
+    pub fn next_token(&mut self, errors: Option<&mut Vec<Error>>) -> Token {
         let token = Token {
             span: self.current_span[0],
             kind: self.current_kind[0],
         };
 
-        let new = 'a: loop {
+        let new = self.next_significant(errors);
+
+        self.current_kind.rotate_left(1);
+        self.current_span.rotate_left(1);
+        self.current_kind[const { LOOK - 1 }] = new.kind;
+        self.current_span[const { LOOK - 1 }] = new.span;
+
+        token
+    }
+
+    /// Lexes up to the next token the parser cares about, skipping trivia and
+    /// reporting error tokens. The line endings of blank lines, and of lines
+    /// with only a comment, are trivia too: the parser sees a single
+    /// newline between two lines with tokens on them.
+    fn next_significant(&mut self, mut errors: Option<&mut Vec<Error>>) -> Token {
+        'a: loop {
             let new = self.next_impl();
+            if let Some(errors) = errors.as_deref_mut()
+                && matches!(
+                    new.kind,
+                    token::Kind::Comment | token::Kind::StringOrKey | token::Kind::StringMultiline
+                )
+            {
+                self.control_characters(new, errors);
+            }
+            if new.kind == token::Kind::Newline {
+                if self.line_start {
+                    continue;
+                }
+                self.line_start = true;
+                break 'a new;
+            }
             if new.kind != token::Kind::Space
                 && new.kind != token::Kind::Comment
                 && !new.kind.is_error()
             {
+                self.line_start = false;
                 break 'a new;
             }
 
             if new.kind.is_error() {
-                match new.kind {
-                    token::Kind::NonClosingString | token::Kind::NonClosingMultilineString => {
+                let recovered = match new.kind {
+                    token::Kind::NonClosingString => Some(token::Kind::StringOrKey),
+                    token::Kind::NonClosingMultilineString => Some(token::Kind::StringMultiline),
+                    token::Kind::NonAsciiKey => Some(token::Kind::Key),
+                    _ => None,
+                };
+                match recovered {
+                    Some(kind) => {
+                        self.line_start = false;
                         if let Some(errors) = errors {
-                            errors.push(crate::parser::Error {
-                                kind: crate::tree::Kind::UnclosedString,
-                                span: new.span,
+                            errors.push(match kind {
+                                token::Kind::Key => self.non_ascii_key(new.span),
+                                _ => self.unclosed_string(new.span),
                             });
                         }
-                        let kind = match new.kind {
-                            token::Kind::NonClosingString => token::Kind::StringOrKey,
-                            token::Kind::NonClosingMultilineString => token::Kind::StringMultiline,
-                            _ => panic!(),
-                        };
                         break 'a Token {
                             kind,
                             span: new.span,
                         };
                     }
-                    _ => {
+                    None => {
                         if let Some(ref mut errors) = errors {
-                            errors.push(crate::parser::Error {
-                                kind: crate::tree::Kind::Unknown,
-                                span: new.span,
-                            });
+                            errors.push(self.unknown(new));
                         }
                     }
                 }
             }
-        };
+        }
+    }
 
-        self.current_kind.rotate_left(1);
-        self.current_span.rotate_left(1);
-        self.current_kind[const { LOOK - 1 }] = new.kind;
-        self.current_span[const { LOOK - 1 }] = new.span;
+    fn unclosed_string(&self, span: Span) -> Error {
+        let text = &self.cursor.source()[span.start..span.end];
+        let delimiter = [r#"""""#, "'''", "\"", "'"]
+            .into_iter()
+            .find(|delimiter| text.starts_with(delimiter))
+            .unwrap_or("\"");
+
+        let error = Error::new(span, ErrorKind::UnclosedString { delimiter });
+        if delimiter.len() == 1 {
+            error.with_help(format!(
+                "add a closing `{delimiter}` before the end of the line, \
+                 or use `{delimiter}{delimiter}{delimiter}` for a multi-line string"
+            ))
+        } else {
+            error.with_help(format!("add a closing `{delimiter}`"))
+        }
+    }
 
-        token
+    fn non_ascii_key(&self, span: Span) -> Error {
+        let text = &self.cursor.source()[span.start..span.end];
+        Error::new(span, ErrorKind::NonAsciiKey(text.to_string()))
+            .with_note("bare keys may only contain ASCII letters, digits, `_` and `-`")
+            .with_help(format!("quote the key, like `\"{text}\"`"))
+    }
+
+    fn unknown(&self, token: Token) -> Error {
+        let text = &self.cursor.source()[token.span.start..token.span.end];
+        match token.kind {
+            token::Kind::InvalidFloat => {
+                let note = if text.matches('.').count() > 1 {
+                    "a float has a single `.` between its integer and fractional parts"
+                } else if leading_dot(text) {
+                    "a float has digits before its `.`, like `0.5`"
+                } else if text.contains(['e', 'E']) {
+                    "an exponent is `e` followed by an integer, like in `1e6` or `6.626e-34`"
+                } else {
+                    "a float is an integer part followed by a fractional part, an exponent or both"
+                };
+                Error::new(token.span, ErrorKind::InvalidNumber).with_note(note)
+            }
+            _ => match text.parse::<char>() {
+                Ok(c) if is_control(c) => control_character(token.span, c),
+                _ => {
+                    let error = Error::new(
+                        token.span,
+                        ErrorKind::UnexpectedCharacters(text.to_string()),
+                    );
+                    if text.contains(BOM) {
+                        error
+                            .with_note("a byte order mark is only allowed at the start of the file")
+                    } else {
+                        error
+                    }
+                }
+            },
+        }
+    }
+
+    /// Reports the control characters of a comment or a string. Only tabs
+    /// are allowed as they are, and line endings in multi-line strings.
+    fn control_characters(&self, token: Token, errors: &mut Vec<Error>) {
+        let multiline = token.kind == token::Kind::StringMultiline;
+        let text = &self.cursor.source()[token.span.start..token.span.end];
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let allowed = match c {
+                '\t' => true,
+                '\n' => multiline,
+                '\r' => multiline && chars.peek().is_some_and(|(_, next)| *next == '\n'),
+                c => !is_control(c),
+            };
+            if !allowed {
+                let start = token.span.start + i;
+                errors.push(control_character(
+                    Span::from(start..start + c.len_utf8()),
+                    c,
+                ));
+            }
+        }
     }
 
     fn next_impl(&mut self) -> Token {
@@ -130,14 +266,19 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
         let kind = match peek {
             ' ' | '\t' => token::Kind::Space,
             // '\t' => token::Kind::Tab,
-            c @ ('\n' | '\r') => {
-                self.consume_matching(c);
+            BOM if start == 0 => token::Kind::Space,
+            '\n' => token::Kind::Newline,
+            // A carriage return only ends a line before a line feed, and
+            // the two make a single line ending
+            '\r' if self.cursor.matches('\n') => {
+                self.cursor.bump();
                 token::Kind::Newline
             }
-            '-' | '+' => self.consume_number_or_key(),
-            '0'..='9' => self.consume_number_or_key(),
+            c if is_control(c) => token::Kind::Unknown,
+            '-' | '+' => self.consume_number_or_key(start),
+            '0'..='9' => self.consume_number_or_key(start),
             '\'' if self.matches(to_char_array!("''")) => {
-                self.cursor.bump_n(3);
+                self.cursor.bump_n(2);
                 self.consume_delimited(MultiLine::Yes, to_char_array!("'''"))
                     .unwrap_or(token::Kind::NonClosingMultilineString)
             }
@@ -145,7 +286,7 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
                 .consume_delimited(MultiLine::No, to_char_array!("'"))
                 .unwrap_or(token::Kind::NonClosingString),
             '"' if self.matches(to_char_array!(r#""""#)) => {
-                self.cursor.bump_n(3);
+                self.cursor.bump_n(2);
                 self.consume_delimited(MultiLine::Yes, to_char_array!(r#"""""#))
                     .unwrap_or(token::Kind::NonClosingMultilineString)
             }
@@ -161,6 +302,7 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
             '#' => self.consume_comment(),
             '.' => token::Kind::Dot,
             'a'..='z' | 'A'..='Z' | '_' => self.consume_key(start),
+            c if is_non_ascii_key(c) => self.consume_key(start),
             _ => self.consume_unknown(),
         };
 
@@ -194,6 +336,7 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
                 | '_' => {
                     break;
                 }
+                c if is_control(c) || is_non_ascii_key(c) => break,
                 _ => {
                     self.cursor.bump();
                 }
@@ -203,18 +346,11 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
         token::Kind::Unknown
     }
 
-    fn consume_matching(&mut self, matching: char) {
-        while let Some(peek) = self.cursor.peek()
-            && peek == matching
-        {
-            self.cursor.bump();
-        }
-    }
-
     fn consume_comment(&mut self) -> token::Kind {
         while let Some(peek) = self.cursor.peek() {
             match peek {
                 '\n' => break,
+                '\r' if self.cursor.peek_ahead(1) == Some('\n') => break,
                 _ => {
                     self.cursor.bump();
                 }
@@ -230,6 +366,9 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
                 'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => {
                     self.cursor.bump();
                 }
+                c if is_non_ascii_key(c) => {
+                    self.cursor.bump();
+                }
                 _ => {
                     break;
                 }
@@ -237,13 +376,14 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
         }
 
         match &self.cursor.source()[start..self.cursor.cursor()] {
-            "true" | "false" => return token::Kind::Bool,
-            "nan" | "inf" => return token::Kind::Float,
+            "true" | "false" => token::Kind::Bool,
+            "nan" | "inf" => token::Kind::Float,
+            key if !key.is_ascii() => token::Kind::NonAsciiKey,
             _ => token::Kind::Key,
         }
     }
 
-    fn consume_number_or_key(&mut self) -> token::Kind {
+    fn consume_number_or_key(&mut self, start: usize) -> token::Kind {
         if let Some(chunk) = self.cursor.peek_chunk::<3>()
             && (chunk == to_char_array!("nan") || chunk == to_char_array!("inf"))
         {
@@ -262,6 +402,14 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
                     seen_chars = true;
                     self.cursor.bump();
                 }
+                // The sign of an exponent, like in `1.5e+3`
+                '+' if self.cursor.source()[start..self.cursor.cursor()].ends_with(['e', 'E']) => {
+                    self.cursor.bump();
+                }
+                c if is_non_ascii_key(c) => {
+                    seen_chars = true;
+                    self.cursor.bump();
+                }
                 '.' if !seen_chars
                     && self
                         .cursor
@@ -277,12 +425,20 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
             }
         }
 
+        let text = &self.cursor.source()[start..self.cursor.cursor()];
         match (dots, seen_chars) {
+            (0, true) if has_radix(text) => token::Kind::Integer,
+            (0 | 1, true) if has_exponent(text) => token::Kind::Float,
+            // A `+` only goes in an exponent, which this isn't
+            (_, true) if text[1..].contains('+') => token::Kind::InvalidFloat,
+            (0, true) if !text.is_ascii() => token::Kind::NonAsciiKey,
             (2.., false) => token::Kind::InvalidFloat,
             (0, false) => token::Kind::Integer,
+            // Like `-.5`, with no digit before the `.`
+            (1, false) if leading_dot(text) => token::Kind::InvalidFloat,
             (1, false) => token::Kind::Float,
             (0, true) => token::Kind::Key,
-            (1.., true) => panic!("When a charater is found we dont match dots"),
+            (1.., true) => token::Kind::InvalidFloat,
         }
     }
 
@@ -298,8 +454,24 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
         while let Some(char) = self.cursor.peek() {
             match char {
                 '\n' if multiline == MultiLine::No => return None,
+                // Basic strings escape their delimiter as `\"`
+                '\\' if delimiter[0] == '"' => {
+                    self.cursor.bump();
+                    if self.cursor.peek().is_some_and(|c| c != '\n') {
+                        self.cursor.bump();
+                    }
+                }
                 c if delimiter.starts_with(&[c]) && self.matches(delimiter) => {
-                    self.cursor.bump_n(delimiter.len());
+                    // Up to two quotes right before the closing delimiter
+                    // belong to the string, like in `quoted`
+                    let mut len = delimiter.len();
+                    while multiline == MultiLine::Yes
+                        && len < delimiter.len() + 2
+                        && self.cursor.peek_ahead(len) == Some(c)
+                    {
+                        len += 1;
+                    }
+                    self.cursor.bump_n(len);
                     return Some(if multiline == MultiLine::Yes {
                         token::Kind::StringMultiline
                     } else {
@@ -315,3 +487,112 @@ impl<'src, const LOOK: usize> Lexer<'src, LOOK> {
         None
     }
 }
+
+/// The byte order mark, allowed at the start of a file and ignored there.
+const BOM: char = '\u{feff}';
+
+/// Whether `c` is a letter or digit outside ASCII, which people may write
+/// in a bare key although TOML only allows them in quoted keys.
+fn is_non_ascii_key(c: char) -> bool {
+    !c.is_ascii() && c.is_alphanumeric()
+}
+
+/// Whether `c` is one of the control characters TOML forbids outside
+/// escapes. Tabs and line endings are handled where they're allowed.
+fn is_control(c: char) -> bool {
+    matches!(c, '\0'..='\u{8}' | '\u{a}'..='\u{1f}' | '\u{7f}')
+}
+
+fn control_character(span: Span, c: char) -> Error {
+    match c {
+        '\r' => Error::new(span, ErrorKind::BareCarriageReturn)
+            .with_note("lines end with `\\n` or `\\r\\n`"),
+        c => Error::new(span, ErrorKind::ControlCharacter(c)).with_help(format!(
+            "write it as `\\u{:04X}` in a basic string",
+            c as u32
+        )),
+    }
+}
+
+/// Whether `number` is a float with an exponent, like `1e6` or
+/// `6.626e-34`.
+/// Whether `number` is a sign followed by a `.`, like in `-.5`.
+fn leading_dot(number: &str) -> bool {
+    number
+        .strip_prefix(['+', '-'])
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Whether `number` is a hexadecimal, octal or binary integer, like
+/// `0xff`, its digits maybe split by `_`.
+fn has_radix(number: &str) -> bool {
+    let radix = match number.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => return false,
+    };
+    let digits = &number[2..];
+    !digits.is_empty() && digits.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+fn has_exponent(number: &str) -> bool {
+    let Some((mantissa, exponent)) = number.split_once(['e', 'E']) else {
+        return false;
+    };
+    let mantissa = mantissa.strip_prefix(['+', '-']).unwrap_or(mantissa);
+    let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+    mantissa.starts_with(|c: char| c.is_ascii_digit())
+        && mantissa
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b == b'_' || b == b'.')
+        && !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit() || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lexer;
+    use crate::token;
+
+    /// Characters the lexer reads to get through `source`, counting a
+    /// character each time it's read again.
+    fn reads(source: &str) -> usize {
+        let mut lexer = Lexer::<3>::new(source);
+        while lexer.peek_kind::<0>() != token::Kind::Eof {
+            lexer.next_token(None);
+        }
+        lexer.cursor.reads()
+    }
+
+    #[test]
+    fn lexing_is_linear() {
+        // Each case grown to four times its size should take about four
+        // times the reads, whether it's many tokens or one long one
+        type Source = fn(usize) -> String;
+        let cases: [(&str, Source); 8] = [
+            ("entries", |n| {
+                "[a.b]\nk = \"é\" # c\nv = [1, 2.5e+3, { x = true }]\n".repeat(n)
+            }),
+            ("string", |n| format!("a = \"{}\"\n", "x\\t".repeat(n))),
+            ("multiline string", |n| {
+                format!("a = \"\"\"{}\"\"\"\n", "x\"\"\n".repeat(n))
+            }),
+            ("literal string", |n| {
+                format!("a = '''{}'''\n", "x''\n".repeat(n))
+            }),
+            ("comment", |n| format!("#{}\n", "x ".repeat(n))),
+            ("blank lines", |n| "  \n\r\n".repeat(n)),
+            ("number", |n| format!("a = 1{}\n", "_0.".repeat(n))),
+            ("unclosed string", |n| format!("a = \"{}", "x".repeat(n))),
+        ];
+        for (name, case) in cases {
+            let small = reads(&case(1000));
+            let large = reads(&case(4000));
+            assert!(
+                large <= small * 9 / 2,
+                "{name}: {small} reads at 1000, {large} at 4000"
+            );
+        }
+    }
+}
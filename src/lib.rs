@@ -2,19 +2,39 @@
 #![feature(let_chains)]
 
 pub mod args;
+pub mod cli;
+pub mod comment;
+pub mod config;
 pub mod cursor;
+pub mod document;
+pub mod encoding;
+pub mod error;
+pub mod format;
 pub mod lexer;
+pub mod lint;
 pub mod parser;
+pub mod report;
+pub mod sort;
 pub mod span;
 pub mod token;
 pub mod tree;
+pub mod value;
+pub mod workspace;
 
+/// Random access over a source by offset.
+///
+/// Offsets are in the slice's own units (elements for `&[T]`, bytes for
+/// `&str`), so an item may span several offsets; see [`Slice::width`].
 pub trait Slice {
     type Item;
 
     fn length(&self) -> usize;
+    /// Item starting at offset `idx`.
     fn get_idx(&self, idx: usize) -> Option<Self::Item>;
+    /// `N` consecutive items, the first one starting at offset `idx`.
     fn get_chunk<const N: usize>(&self, idx: usize) -> Option<[Self::Item; N]>;
+    /// Number of offsets `item` occupies.
+    fn width(item: &Self::Item) -> usize;
 }
 
 impl<T: Copy> Slice for &[T] {
@@ -33,6 +53,10 @@ impl<T: Copy> Slice for &[T] {
             .map(|s| s.iter().copied().next_chunk::<N>().ok())
             .flatten()
     }
+
+    fn width(_: &Self::Item) -> usize {
+        1
+    }
 }
 
 impl Slice for &str {
@@ -43,7 +67,7 @@ impl Slice for &str {
     }
 
     fn get_idx(&self, idx: usize) -> Option<Self::Item> {
-        self.chars().nth(idx)
+        self.get(idx..).and_then(|s| s.chars().next())
     }
 
     fn get_chunk<const N: usize>(&self, idx: usize) -> Option<[Self::Item; N]> {
@@ -51,4 +75,8 @@ impl Slice for &str {
             .map(|s| s.chars().next_chunk::<N>().ok())
             .flatten()
     }
+
+    fn width(item: &Self::Item) -> usize {
+        item.len_utf8()
+    }
 }
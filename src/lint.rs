@@ -0,0 +1,633 @@
+//! Checks of how a TOML file is written, beyond what the spec requires.
+//!
+//! Each rule of [`RULES`] has an id, used to set its severity in the `lint`
+//! table of the settings and to silence it, and a default severity; rules
+//! that are allowed don't run. What a rule finds can be silenced with a
+//! comment:
+//!
+//! ```toml
+//! # aoxo-toml: allow-file(table-order)
+//! "name" = 1 # aoxo-toml: allow(quoted-key)
+//! # aoxo-toml: allow(unsorted-keys, quoted-key)
+//! "b" = 2
+//! ```
+//!
+//! `allow` covers the line it's on or, alone on its line, the line after
+//! it. `allow-file` covers the whole file.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::{Config, Severity},
+    error::{Error, ErrorKind},
+    lexer::Lexer,
+    parser::Parser,
+    sort,
+    span::{Lines, Span},
+    token::{self, Token},
+    tree::{self, Child, Tree},
+    value::{self, write},
+};
+
+/// Where the rule catalogue lives; each rule has an anchor of its own.
+pub const LINTS_URL: &str = "https://github.com/AOx0/toml-lsp/blob/main/docs/lints.md";
+
+/// What comments silencing rules start with, after the `#`.
+const DIRECTIVE: &str = "aoxo-toml:";
+
+/// Most times [`fix_all`] lints the source again, in case a fix uncovers
+/// something else to fix.
+const MAX_PASSES: usize = 8;
+
+pub struct Rule {
+    pub id: &'static str,
+    pub default: Severity,
+    /// What the rule checks, in a line.
+    pub summary: &'static str,
+    check: fn(&Context, &mut Vec<Finding>),
+}
+
+/// Every rule, in the order their lints are reported for the same span.
+pub static RULES: &[Rule] = &[
+    Rule {
+        id: "quoted-key",
+        default: Severity::Warn,
+        summary: "keys quoted even though they could be bare",
+        check: quoted_key,
+    },
+    Rule {
+        id: "mixed-quotes",
+        default: Severity::Warn,
+        summary: "strings quoted unlike most others in the file",
+        check: mixed_quotes,
+    },
+    Rule {
+        id: "empty-table",
+        default: Severity::Warn,
+        summary: "tables without key-value pairs or subtables",
+        check: empty_table,
+    },
+    Rule {
+        id: "unsorted-keys",
+        default: Severity::Allow,
+        summary: "keys of a table out of alphabetical order",
+        check: unsorted_keys,
+    },
+    Rule {
+        id: "table-order",
+        default: Severity::Warn,
+        summary: "subtables separated from their parent by other tables",
+        check: table_order,
+    },
+    Rule {
+        id: "trailing-whitespace",
+        default: Severity::Warn,
+        summary: "spaces or tabs at the end of a line",
+        check: trailing_whitespace,
+    },
+    Rule {
+        id: "inconsistent-indentation",
+        default: Severity::Warn,
+        summary: "lines indented with tabs in a file indented with spaces, or the other way around",
+        check: inconsistent_indentation,
+    },
+];
+
+/// The rule with the id `id`.
+pub fn rule(id: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.id == id)
+}
+
+/// Something a rule found, with a way to fix it if the rule knows one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// An [`ErrorKind::Lint`] error.
+    pub error: Error,
+    pub fix: Option<Fix>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// What the fix does, like "remove the quotes".
+    pub title: String,
+    /// Edits to the source, none of them overlapping.
+    pub edits: Vec<Edit>,
+    /// Whether the fix leaves the data as it was, so that [`fix_all`] can
+    /// apply it unasked. Others are only offered one at a time.
+    pub safe: bool,
+}
+
+/// Replaces the text at `span` with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl Fix {
+    fn replace(title: &str, span: Span, text: impl Into<String>) -> Self {
+        Self {
+            title: title.to_string(),
+            edits: vec![Edit {
+                span,
+                text: text.into(),
+            }],
+            safe: true,
+        }
+    }
+}
+
+/// What a rule found, before its severity and the comments silencing it
+/// are known.
+struct Finding {
+    span: Span,
+    message: String,
+    fix: Option<Fix>,
+}
+
+struct Context<'a> {
+    source: &'a str,
+    tree: &'a Tree,
+    tokens: Vec<Token>,
+    config: &'a Config,
+    lines: Lines,
+    /// The spans of the multiline strings, in order.
+    multiline_strings: Vec<Span>,
+}
+
+impl<'a> Context<'a> {
+    fn new(source: &'a str, tree: &'a Tree, config: &'a Config) -> Self {
+        let tokens = Lexer::tokens(source).collect::<Vec<Token>>();
+        let multiline_strings = tokens
+            .iter()
+            .filter(|token| {
+                matches!(
+                    token.kind,
+                    token::Kind::StringMultiline | token::Kind::NonClosingMultilineString
+                )
+            })
+            .map(|token| token.span)
+            .collect();
+        Self {
+            source,
+            tree,
+            tokens,
+            config,
+            lines: Lines::new(source),
+            multiline_strings,
+        }
+    }
+
+    fn text(&self, span: Span) -> &'a str {
+        self.source.get(span.start..span.end).unwrap_or_default()
+    }
+
+    /// Span of `line` without its line ending.
+    fn line_span(&self, line: usize) -> Span {
+        self.lines.span(self.source, line)
+    }
+
+    /// Whether byte `offset` is inside a multiline string, where
+    /// whitespace is part of the value.
+    fn in_multiline_string(&self, offset: usize) -> bool {
+        // The spans are in order and don't overlap, so only the last one
+        // starting before `offset` can hold it
+        let i = self
+            .multiline_strings
+            .partition_point(|span| span.start < offset);
+        i > 0 && offset < self.multiline_strings[i - 1].end
+    }
+
+    /// Every tree of the file, parents before their children.
+    fn trees(&self) -> Vec<&'a Tree> {
+        fn walk<'t>(tree: &'t Tree, out: &mut Vec<&'t Tree>) {
+            out.push(tree);
+            for child in &tree.children {
+                if let Child::Tree(tree) = child {
+                    walk(tree, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(self.tree, &mut out);
+        out
+    }
+
+    /// The `[header]` and `[[header]]` trees, with their key and the names
+    /// in it.
+    fn headers(&self) -> Vec<(&'a Tree, &'a Tree, Vec<String>)> {
+        subtrees(self.tree)
+            .filter(|tree| matches!(tree.kind, tree::Kind::Table | tree::Kind::TableArray))
+            .filter_map(|header| {
+                let key = key_of(header)?;
+                Some((header, key, value::key_names(self.source, key)))
+            })
+            .collect()
+    }
+}
+
+fn subtrees(tree: &Tree) -> impl Iterator<Item = &Tree> {
+    tree.children.iter().filter_map(|child| match child {
+        Child::Tree(tree) => Some(tree),
+        Child::Token(_) => None,
+    })
+}
+
+fn tokens(tree: &Tree) -> impl Iterator<Item = Token> + '_ {
+    tree.children.iter().filter_map(|child| match child {
+        Child::Token(token) => Some(*token),
+        Child::Tree(_) => None,
+    })
+}
+
+fn key_of(tree: &Tree) -> Option<&Tree> {
+    subtrees(tree).find(|tree| tree.kind == tree::Kind::Key)
+}
+
+/// `names` written as a dotted key.
+fn dotted(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| write::key(name))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Rules silenced by comments.
+#[derive(Default)]
+struct Allowed<'a> {
+    file: HashSet<&'a str>,
+    /// By 0-based line.
+    lines: HashMap<usize, HashSet<&'a str>>,
+}
+
+impl<'a> Allowed<'a> {
+    fn read(context: &Context<'a>) -> Self {
+        let mut allowed = Self::default();
+        for token in &context.tokens {
+            if token.kind != token::Kind::Comment {
+                continue;
+            }
+            let text = context.text(token.span).trim_start_matches('#').trim();
+            let Some(directive) = text.strip_prefix(DIRECTIVE).map(str::trim_start) else {
+                continue;
+            };
+            let (file, list) = if let Some(list) = directive.strip_prefix("allow-file(") {
+                (true, list)
+            } else if let Some(list) = directive.strip_prefix("allow(") {
+                (false, list)
+            } else {
+                continue;
+            };
+            let Some(list) = list.strip_suffix(')') else {
+                continue;
+            };
+            let ids = list.split(',').map(str::trim).filter(|id| !id.is_empty());
+
+            if file {
+                allowed.file.extend(ids);
+            } else {
+                let line = context.lines.line(token.span.start);
+                let before = &context.source[context.lines.start(line)..token.span.start];
+                let line = if before.trim().is_empty() {
+                    line + 1
+                } else {
+                    line
+                };
+                allowed.lines.entry(line).or_default().extend(ids);
+            }
+        }
+        allowed
+    }
+
+    fn allows(&self, rule: &str, line: usize) -> bool {
+        self.file.contains(rule) || self.lines.get(&line).is_some_and(|ids| ids.contains(rule))
+    }
+}
+
+/// What the rules that aren't allowed find in `source`, which parses to
+/// `tree`, sorted by where they are.
+///
+/// The rules expect a tree without syntax errors; on one with errors they
+/// may miss things or find nonsense.
+pub fn lint(source: &str, tree: &Tree, config: &Config) -> Vec<Lint> {
+    let context = Context::new(source, tree, config);
+    let allowed = Allowed::read(&context);
+
+    let mut lints = Vec::new();
+    for rule in RULES {
+        let severity = config.lints.get(rule.id).copied().unwrap_or(rule.default);
+        if severity == Severity::Allow || allowed.file.contains(rule.id) {
+            continue;
+        }
+        let mut findings = Vec::new();
+        (rule.check)(&context, &mut findings);
+        for finding in findings {
+            if allowed.allows(rule.id, context.lines.line(finding.span.start)) {
+                continue;
+            }
+            let kind = ErrorKind::Lint {
+                rule: rule.id,
+                severity,
+                message: finding.message,
+            };
+            lints.push(Lint {
+                error: Error::new(finding.span, kind),
+                fix: finding.fix,
+            });
+        }
+    }
+    lints.sort_by_key(|lint| (lint.error.span.start, lint.error.span.end));
+    lints
+}
+
+/// `source` with the [`Fix::safe`] fixes of `lints` applied. A fix
+/// overlapping one applied before is left out.
+pub fn apply(source: &str, lints: &[Lint]) -> String {
+    let mut edits: Vec<&Edit> = Vec::new();
+    let fixes = lints.iter().filter_map(|lint| lint.fix.as_ref());
+    for fix in fixes.filter(|fix| fix.safe) {
+        let overlaps = fix.edits.iter().any(|edit| {
+            edits
+                .iter()
+                .any(|other| edit.span.start < other.span.end && other.span.start < edit.span.end)
+        });
+        if !overlaps {
+            edits.extend(&fix.edits);
+        }
+    }
+    edits.sort_by_key(|edit| edit.span.start);
+
+    let mut out = String::with_capacity(source.len());
+    let mut at = 0;
+    for edit in edits {
+        out.push_str(&source[at..edit.span.start]);
+        out.push_str(&edit.text);
+        at = edit.span.end;
+    }
+    out.push_str(&source[at..]);
+    out
+}
+
+/// `source` with every lint that has a safe fix fixed, or `None` if it has
+/// syntax errors.
+pub fn fix_all(source: &str, config: &Config) -> Option<String> {
+    let mut source = source.to_string();
+    for _ in 0..MAX_PASSES {
+        let (tree, errors) = Parser::new(&source)
+            .with_version(config.toml_version)
+            .parse()
+            .tree();
+        if !errors.is_empty() {
+            return None;
+        }
+        let lints = lint(&source, &tree, config);
+        let fixed = apply(&source, &lints);
+        if fixed == source {
+            break;
+        }
+        source = fixed;
+    }
+    Some(source)
+}
+
+fn quoted_key(context: &Context, findings: &mut Vec<Finding>) {
+    let keys = context
+        .trees()
+        .into_iter()
+        .filter(|tree| tree.kind == tree::Kind::Key);
+    for token in keys.flat_map(tokens) {
+        let text = context.text(token.span);
+        if token.kind != token::Kind::StringOrKey || !text.starts_with(['"', '\'']) {
+            continue;
+        }
+        let name = value::unescape(context.source, token.span);
+        if write::key(&name) == name {
+            findings.push(Finding {
+                span: token.span,
+                message: format!("`{text}` doesn't need quotes"),
+                fix: Some(Fix::replace("remove the quotes", token.span, name)),
+            });
+        }
+    }
+}
+
+/// `text` as a literal string, if it can be written as one.
+fn literal(text: &str) -> Option<String> {
+    let fits = !text
+        .chars()
+        .any(|c| c == '\'' || (c.is_control() && c != '\t'));
+    fits.then(|| format!("'{text}'"))
+}
+
+fn mixed_quotes(context: &Context, findings: &mut Vec<Finding>) {
+    // Strings that are values, keys are left to `quoted-key`
+    let strings = context
+        .trees()
+        .into_iter()
+        .filter(|tree| tree.kind != tree::Kind::Key)
+        .flat_map(tokens)
+        .filter(|token| token.kind == token::Kind::StringOrKey)
+        .filter(|token| context.text(token.span).starts_with(['"', '\'']))
+        .collect::<Vec<_>>();
+    let basic = strings
+        .iter()
+        .filter(|token| context.text(token.span).starts_with('"'))
+        .count();
+    let prefer_basic = basic * 2 >= strings.len();
+
+    for token in strings {
+        let text = context.text(token.span);
+        if text.starts_with('"') == prefer_basic {
+            continue;
+        }
+        let value = value::unescape(context.source, token.span);
+        let (message, fix) = if prefer_basic {
+            let fix = Fix::replace("use double quotes", token.span, write::quote(&value));
+            (
+                "single quotes, while most strings in this file use double quotes",
+                Some(fix),
+            )
+        } else {
+            let fix = literal(&value)
+                .map(|literal| Fix::replace("use single quotes", token.span, literal));
+            (
+                "double quotes, while most strings in this file use single quotes",
+                fix,
+            )
+        };
+        findings.push(Finding {
+            span: token.span,
+            message: format!("`{text}` uses {message}"),
+            fix,
+        });
+    }
+}
+
+fn empty_table(context: &Context, findings: &mut Vec<Finding>) {
+    let headers = context.headers();
+    // A table that only introduces its subtables isn't empty. Going from
+    // the last header up, a table is a parent if a header after it has its
+    // path as a prefix
+    let mut prefixes = HashSet::new();
+    let mut parents = vec![false; headers.len()];
+    for (i, (_, _, path)) in headers.iter().enumerate().rev() {
+        parents[i] = prefixes.contains(&path.as_slice());
+        prefixes.extend((1..path.len()).map(|len| &path[..len]));
+    }
+
+    for ((header, key, path), parent) in headers.iter().zip(parents) {
+        let empty = header.kind == tree::Kind::Table
+            && !subtrees(header).any(|tree| tree.kind == tree::Kind::KeyVal);
+        if !empty || parent {
+            continue;
+        }
+
+        let first = context.lines.line(header.span.start);
+        let end = context.lines.next(context.lines.line(key.span.end));
+        findings.push(Finding {
+            span: key.span,
+            message: format!("table `{}` is empty", dotted(path)),
+            // `[a]` alone is `{ a = {} }`, which may mean something
+            fix: Some(Fix {
+                safe: false,
+                ..Fix::replace(
+                    "remove the table",
+                    Span::from(context.lines.start(first)..end),
+                    "",
+                )
+            }),
+        });
+    }
+}
+
+fn unsorted_keys(context: &Context, findings: &mut Vec<Finding>) {
+    // Only the first key out of order of each group is reported
+    for group in sort::groups(context.source, context.tree) {
+        let entries = &group.entries;
+        let Some(i) = (1..entries.len()).find(|&i| entries[i].names < entries[i - 1].names) else {
+            continue;
+        };
+        findings.push(Finding {
+            span: entries[i].key,
+            message: format!(
+                "`{}` should come before `{}`",
+                dotted(&entries[i].names),
+                dotted(&entries[i - 1].names)
+            ),
+            fix: Some(Fix::replace(
+                "sort the keys",
+                group.span,
+                group.sorted(context.source),
+            )),
+        });
+    }
+}
+
+fn table_order(context: &Context, findings: &mut Vec<Finding>) {
+    // Prefixes whose tables ended, as another table came after them
+    let mut closed: HashSet<Vec<String>> = HashSet::new();
+    let mut previous: Option<Vec<String>> = None;
+    for (_, key, path) in context.headers() {
+        if let Some(previous) = previous {
+            for i in 1..=previous.len() {
+                if !path.starts_with(&previous[..i]) {
+                    closed.insert(previous[..i].to_vec());
+                }
+            }
+        }
+        if let Some(parent) = (1..=path.len())
+            .map(|i| &path[..i])
+            .find(|prefix| closed.contains(*prefix))
+        {
+            findings.push(Finding {
+                span: key.span,
+                message: format!(
+                    "table `{}` is apart from the other tables of `{}`",
+                    dotted(&path),
+                    dotted(parent)
+                ),
+                fix: None,
+            });
+        }
+        previous = Some(path);
+    }
+}
+
+fn trailing_whitespace(context: &Context, findings: &mut Vec<Finding>) {
+    for line in 0..context.lines.count() {
+        let span = context.line_span(line);
+        let text = context.text(span);
+        let trimmed = text.trim_end_matches([' ', '\t']).len();
+        if trimmed == text.len() || context.in_multiline_string(span.start + trimmed) {
+            continue;
+        }
+        let whitespace = Span::from(span.start + trimmed..span.end);
+        findings.push(Finding {
+            span: whitespace,
+            message: "trailing whitespace".to_string(),
+            fix: Some(Fix::replace("remove the whitespace", whitespace, "")),
+        });
+    }
+}
+
+fn inconsistent_indentation(context: &Context, findings: &mut Vec<Finding>) {
+    // The first indented line sets how the file is indented
+    let mut tabs = None;
+    let width = context.config.format.indent_width;
+    for line in 0..context.lines.count() {
+        let span = context.line_span(line);
+        let text = context.text(span);
+        let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+        if indent == 0 || indent == text.len() || context.in_multiline_string(span.start) {
+            continue;
+        }
+        let indent_text = &text[..indent];
+        let has_tabs = indent_text.contains('\t');
+        let has_spaces = indent_text.contains(' ');
+        let Some(tabs) = tabs else {
+            if has_tabs != has_spaces {
+                tabs = Some(has_tabs);
+            }
+            continue;
+        };
+        if (has_tabs, has_spaces) == (tabs, !tabs) {
+            continue;
+        }
+
+        let indent_span = Span::from(span.start..span.start + indent);
+        let columns: usize = indent_text
+            .chars()
+            .map(|c| if c == '\t' { width } else { 1 })
+            .sum();
+        let (fix, expected) = if tabs {
+            let fix = (width > 0 && columns.is_multiple_of(width)).then(|| {
+                Fix::replace(
+                    "indent with tabs",
+                    indent_span,
+                    "\t".repeat(columns / width),
+                )
+            });
+            (fix, "tabs")
+        } else {
+            (
+                Some(Fix::replace(
+                    "indent with spaces",
+                    indent_span,
+                    " ".repeat(columns),
+                )),
+                "spaces",
+            )
+        };
+        let found = match (has_tabs, has_spaces) {
+            (true, true) => "tabs and spaces",
+            (true, false) => "tabs",
+            _ => "spaces",
+        };
+        findings.push(Finding {
+            span: indent_span,
+            message: format!("line indented with {found} in a file indented with {expected}"),
+            fix,
+        });
+    }
+}
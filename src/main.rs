@@ -1,57 +1,755 @@
 #![feature(allocator_api)]
 
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
 use aoxo_toml::{
-    args::Args,
-    parser::{Error, Parser},
-    tree::Tree,
+    args::{Args, Command, Listen},
+    cli,
+    config::{self, Config, Severity},
+    document::Document,
+    encoding::PositionEncoding,
+    error::{Error, ErrorKind},
+    format::format_with,
+    lint::{self, Fix, Lint},
+    parser::Parser,
+    sort::{self, sort},
+    span::{offset_at, Span},
+    tree::{self, Child, Tree},
+    value::{self, Value},
+    workspace::Workspace,
 };
 use clap::Parser as _;
-use tower_lsp::jsonrpc::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_lsp::jsonrpc::{self, Result};
 use tower_lsp::{lsp_types::*, LanguageServer};
-use tower_lsp::{Client, LspService, Server};
+use tower_lsp::{Client, ClientSocket, LspService, Server};
+
+/// A document the client has open.
+#[derive(Debug)]
+struct Open {
+    /// The version the client gave the text, which grows with every change.
+    version: i32,
+    document: Document,
+    /// Errors of the checks that only run when the document is opened or
+    /// saved, for its text at the time.
+    checked: Vec<Error>,
+    /// The settings of the document, read when it's opened and when the
+    /// settings change.
+    config: Config,
+    /// What the lint rules find in the text, while it has no syntax errors.
+    lints: Vec<Lint>,
+    /// Identifies the diagnostics of the document, for clients that pull
+    /// them.
+    result_id: String,
+}
+
+impl Open {
+    fn new(version: i32, document: Document, checked: Vec<Error>, config: Config) -> Self {
+        let lints = lints(&document, &config);
+        Self {
+            version,
+            document,
+            checked,
+            config,
+            lints,
+            result_id: result_id(),
+        }
+    }
+
+    /// Every error of the document, lints included.
+    fn errors(&self) -> impl Iterator<Item = &Error> {
+        let lints = self.lints.iter().map(|lint| &lint.error);
+        self.document
+            .errors
+            .iter()
+            .chain(&self.checked)
+            .chain(lints)
+    }
+}
+
+/// The diagnostics of a workspace file that isn't open, checked from disk.
+#[derive(Debug)]
+struct Scanned {
+    result_id: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A new id for a set of diagnostics. Clients pulling diagnostics send back
+/// the id of those they have, and get an unchanged report if it's current.
+fn result_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed).to_string()
+}
 
 #[derive(Debug)]
 struct Backend {
     client: Client,
-    trees: Arc<Mutex<HashMap<Url, Tree>>>,
+    documents: Arc<Mutex<HashMap<Url, Open>>>,
+    encoding: OnceLock<PositionEncoding>,
+    /// Whether to exit with the process id the client gives on initialize,
+    /// which only makes sense when the server has a single client.
+    exit_with_client: bool,
+    /// Whether to run the checks beyond syntax, like duplicate keys, when
+    /// documents are opened and saved. Clients turn them off with the
+    /// `checkOnSave` initialization option.
+    check_on_save: AtomicBool,
+    /// The folders of the workspace, whose files are checked even when
+    /// they're not open.
+    folders: OnceLock<Vec<PathBuf>>,
+    /// Patterns of workspace files not to check, from the `exclude`
+    /// initialization option.
+    exclude: OnceLock<Vec<String>>,
+    workspaces: OnceLock<Vec<Workspace>>,
+    /// Workspace files that aren't open, with diagnostics checked from
+    /// their text on disk.
+    scanned: Arc<Mutex<HashMap<Url, Scanned>>>,
+    /// Whether the client pulls diagnostics with `textDocument/diagnostic`
+    /// and `workspace/diagnostic`, rather than have them published.
+    pull: AtomicBool,
+    /// Whether the client pulls diagnostics again when asked to with
+    /// `workspace/diagnostic/refresh`.
+    refresh: AtomicBool,
+    /// Whether the client shows `$/progress` reports the server starts.
+    work_done_progress: AtomicBool,
+    /// Whether the client lets the server watch files on its own.
+    watch_files: AtomicBool,
+    /// Whether the client answers `workspace/configuration` requests.
+    configuration: AtomicBool,
+    /// The settings the client sent, over the defaults. Those of project
+    /// files override them.
+    settings: Mutex<Config>,
+}
+
+impl Backend {
+    fn encoding(&self) -> PositionEncoding {
+        self.encoding.get().copied().unwrap_or_default()
+    }
+
+    /// The errors of the checks that run on save, if they're enabled.
+    fn check(&self, uri: &Url, document: &Document) -> Vec<Error> {
+        if self.check_on_save.load(Ordering::Relaxed) {
+            checks(uri.to_file_path().ok().as_deref(), document)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// The settings for the document at `uri`: those of the client,
+    /// overridden by those of the closest project file. A project file
+    /// that can't be used is left out, its errors are shown on it.
+    fn config(&self, uri: &Url) -> Config {
+        let settings = self.settings.lock().unwrap().clone();
+        match uri.to_file_path() {
+            Ok(path) => settings.for_file(&path).unwrap_or(settings),
+            Err(()) => settings,
+        }
+    }
+
+    /// Asks the client for its settings, if it answers such requests.
+    async fn load_settings(&self) {
+        if !self.configuration.load(Ordering::Relaxed) {
+            return;
+        }
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some(SECTION.to_string()),
+        };
+        match self.client.configuration(vec![item]).await {
+            Ok(settings) => {
+                let settings = settings.into_iter().next().unwrap_or_default();
+                self.apply_settings(&settings).await;
+            }
+            Err(error) => {
+                let message = format!("could not get the settings: {error}");
+                self.client.log_message(MessageType::WARNING, message).await;
+            }
+        }
+    }
+
+    /// Takes `settings` from the client, logging those that aren't valid.
+    async fn apply_settings(&self, settings: &serde_json::Value) {
+        let table = match Value::from_json(settings) {
+            Ok(Value::Table(table)) => table,
+            // Clients send `null` when there are no settings
+            _ => Default::default(),
+        };
+        let mut config = Config::default();
+        let errors = config.apply(&table);
+        *self.settings.lock().unwrap() = config;
+        for error in errors {
+            let message = format!("invalid setting {error}");
+            self.client.log_message(MessageType::WARNING, message).await;
+        }
+    }
+
+    /// Checks every document again after the settings changed.
+    async fn revalidate(&self) {
+        let versions = {
+            let mut documents = self.documents.lock().unwrap();
+            documents
+                .iter_mut()
+                .map(|(uri, open)| {
+                    open.config = self.config(uri);
+                    // A new version of TOML reads the text differently
+                    if open.document.version != open.config.toml_version {
+                        let text = core::mem::take(&mut open.document.text);
+                        open.document = Document::with_version(text, open.config.toml_version);
+                    }
+                    open.checked = self.check(uri, &open.document);
+                    open.lints = lints(&open.document, &open.config);
+                    open.result_id = result_id();
+                    (uri.clone(), open.version)
+                })
+                .collect::<Vec<_>>()
+        };
+        for (uri, version) in versions {
+            self.publish(uri, version).await;
+        }
+
+        let workspaces = self.workspaces().to_vec();
+        if workspaces.is_empty() {
+            self.files().changed().await;
+        } else {
+            let progress = self.work_done_progress.load(Ordering::Relaxed);
+            tokio::spawn(self.files().scan(workspaces, progress));
+        }
+    }
+
+    fn workspaces(&self) -> &[Workspace] {
+        self.workspaces.get().map_or(&[], Vec::as_slice)
+    }
+
+    /// Checks files outside the editor, with the settings the client chose.
+    fn files(&self) -> Files {
+        Files {
+            client: self.client.clone(),
+            documents: self.documents.clone(),
+            scanned: self.scanned.clone(),
+            encoding: self.encoding(),
+            settings: self.settings.lock().unwrap().clone(),
+            check: self.check_on_save.load(Ordering::Relaxed),
+            pull: self.pull.load(Ordering::Relaxed),
+            refresh: self.refresh.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Builds the workspaces from the folders the client gave, logging
+    /// exclude patterns that aren't valid.
+    async fn load_workspaces(&self) -> Vec<Workspace> {
+        let mut exclude = Vec::new();
+        for pattern in self.exclude.get().into_iter().flatten() {
+            match glob::Pattern::new(pattern) {
+                Ok(pattern) => exclude.push(pattern),
+                Err(error) => {
+                    let message = format!("ignoring exclude pattern `{pattern}`: {error}");
+                    self.client.log_message(MessageType::WARNING, message).await;
+                }
+            }
+        }
+        self.folders
+            .get()
+            .into_iter()
+            .flatten()
+            .map(|folder| Workspace::new(folder.clone(), exclude.clone()))
+            .collect()
+    }
+
+    /// Asks the client to tell the server about changes to TOML files made
+    /// outside the editor.
+    async fn watch_files(&self) {
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.toml".to_string()),
+                kind: None,
+            }],
+        };
+        let registration = Registration {
+            id: "aoxo-toml/watch".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(error) = self.client.register_capability(vec![registration]).await {
+            let message = format!("could not watch files: {error}");
+            self.client.log_message(MessageType::WARNING, message).await;
+        }
+    }
+
+    /// The diagnostics of the document at `uri`, with the version they're for.
+    fn diagnostics(&self, uri: &Url) -> Option<(i32, Vec<Diagnostic>)> {
+        let documents = self.documents.lock().unwrap();
+        let open = documents.get(uri)?;
+        let diagnostics = diagnostics(uri, &open.document.text, open.errors(), self.encoding());
+        Some((open.version, diagnostics))
+    }
+
+    /// Publishes the diagnostics of the document at `uri`, unless it has
+    /// changed since `version`, in which case newer ones are on their way,
+    /// or the client pulls diagnostics instead.
+    async fn publish(&self, uri: Url, version: i32) {
+        if self.pull.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some((current, diagnostics)) = self.diagnostics(&uri) else {
+            return;
+        };
+        if current == version {
+            self.client
+                .publish_diagnostics(uri, diagnostics, Some(version))
+                .await;
+        }
+    }
+
+    /// The diagnostics of `uri` for a client holding those with the result
+    /// id `previous`, or `None` if nothing is known about `uri`.
+    fn pull(&self, uri: &Url, previous: Option<&str>) -> Option<Pulled> {
+        let documents = self.documents.lock().unwrap();
+        if let Some(open) = documents.get(uri) {
+            let diagnostics = (previous != Some(&open.result_id))
+                .then(|| diagnostics(uri, &open.document.text, open.errors(), self.encoding()));
+            return Some(Pulled {
+                version: Some(open.version),
+                result_id: open.result_id.clone(),
+                diagnostics,
+            });
+        }
+        drop(documents);
+
+        let scanned = self.scanned.lock().unwrap();
+        let scanned = scanned.get(uri)?;
+        Some(Pulled {
+            version: None,
+            result_id: scanned.result_id.clone(),
+            diagnostics: (previous != Some(&scanned.result_id))
+                .then(|| scanned.diagnostics.clone()),
+        })
+    }
+}
+
+/// The diagnostics of a file for a client that pulls them.
+struct Pulled {
+    /// The version of the document, if it's open.
+    version: Option<i32>,
+    result_id: String,
+    /// `None` if the client already has them.
+    diagnostics: Option<Vec<Diagnostic>>,
+}
+
+/// Checks workspace files that aren't open, from their text on disk.
+#[derive(Debug, Clone)]
+struct Files {
+    client: Client,
+    documents: Arc<Mutex<HashMap<Url, Open>>>,
+    scanned: Arc<Mutex<HashMap<Url, Scanned>>>,
+    encoding: PositionEncoding,
+    /// The settings of the client, which project files override.
+    settings: Config,
+    /// Whether to run the checks beyond syntax too.
+    check: bool,
+    pull: bool,
+    refresh: bool,
 }
 
-async fn publish_diagnostics(client: &Client, uri: Url, contents: &str, errors: Vec<Error>) {
-    let diagnostics = errors
-        .iter()
+/// How many files are checked between progress reports.
+const BATCH: usize = 64;
+
+impl Files {
+    /// The diagnostics of the file at `path`, or `None` if it can't be read.
+    fn check(&self, path: &Path) -> Option<(Url, Vec<Diagnostic>)> {
+        let uri = Url::from_file_path(path).ok()?;
+        let text = std::fs::read_to_string(path).ok()?;
+        let config = self
+            .settings
+            .for_file(path)
+            .unwrap_or(self.settings.clone());
+        let document = Document::with_version(text, config.toml_version);
+        let checked = if self.check {
+            checks(Some(path), &document)
+        } else {
+            Vec::new()
+        };
+        let lints = lints(&document, &config);
+        let errors = document.errors.iter().chain(&checked);
+        let errors = errors.chain(lints.iter().map(|lint| &lint.error));
+        let diagnostics = diagnostics(&uri, &document.text, errors, self.encoding);
+        Some((uri, diagnostics))
+    }
+
+    /// Keeps diagnostics checked from disk and publishes them, unless the
+    /// file was opened since, in which case the editor's text is what
+    /// counts.
+    async fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>) {
+        if self.documents.lock().unwrap().contains_key(&uri) {
+            return;
+        }
+        let scanned = Scanned {
+            result_id: result_id(),
+            diagnostics: diagnostics.clone(),
+        };
+        self.scanned.lock().unwrap().insert(uri.clone(), scanned);
+        if !self.pull {
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
+
+    /// Checks the file at `path` again, or clears its diagnostics if it's
+    /// gone.
+    async fn refresh(&self, path: &Path) {
+        match self.check(path) {
+            Some((uri, diagnostics)) => self.publish(uri, diagnostics).await,
+            None => {
+                if let Ok(uri) = Url::from_file_path(path) {
+                    self.forget(uri).await;
+                }
+            }
+        }
+    }
+
+    /// Clears the diagnostics of a file that's no longer checked from disk.
+    async fn forget(&self, uri: Url) {
+        if self.scanned.lock().unwrap().remove(&uri).is_some() && !self.pull {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
+    }
+
+    /// Asks a client that pulls diagnostics to pull them again, after files
+    /// were checked from disk.
+    async fn changed(&self) {
+        if self.pull && self.refresh {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
+
+    /// Checks every file of `workspaces` in the background, reporting
+    /// progress if `progress` is set.
+    async fn scan(self, workspaces: Vec<Workspace>, progress: bool) {
+        let progress = match progress {
+            true => Progress::begin(&self.client, "Checking TOML files").await,
+            false => None,
+        };
+
+        let paths = tokio::task::spawn_blocking(move || {
+            workspaces
+                .iter()
+                .flat_map(Workspace::scan)
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        let mut failed = 0;
+        for (batch, chunk) in paths.chunks(BATCH).enumerate() {
+            let files = self.clone();
+            let done = batch * BATCH + chunk.len();
+            let chunk = chunk.to_vec();
+            let checked = tokio::task::spawn_blocking(move || {
+                cli::parallel_map(&chunk, |path| files.check(path))
+            })
+            .await
+            .unwrap_or_default();
+
+            for (uri, diagnostics) in checked.into_iter().flatten() {
+                failed += usize::from(!diagnostics.is_empty());
+                self.publish(uri, diagnostics).await;
+            }
+            if let Some(progress) = &progress {
+                progress.report(done, paths.len()).await;
+            }
+        }
+
+        if let Some(progress) = progress {
+            let message = format!("{} files, {failed} with errors", paths.len());
+            progress.end(message).await;
+        }
+        self.changed().await;
+    }
+}
+
+/// A `$/progress` report the client shows while the server works.
+struct Progress<'c> {
+    client: &'c Client,
+    token: NumberOrString,
+}
+
+impl<'c> Progress<'c> {
+    /// Starts a report, if the client accepts it.
+    async fn begin(client: &'c Client, title: &str) -> Option<Self> {
+        let token = NumberOrString::String(format!("aoxo-toml/{title}"));
+        client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .ok()?;
+        let progress = Self { client, token };
+        progress
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            }))
+            .await;
+        Some(progress)
+    }
+
+    async fn report(&self, done: usize, total: usize) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(format!("{done}/{total}")),
+            percentage: Some((done * 100 / total.max(1)) as u32),
+        }))
+        .await;
+    }
+
+    async fn end(self, message: String) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message),
+        }))
+        .await;
+    }
+
+    async fn send(&self, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+}
+
+/// Section of the client's settings the server reads.
+const SECTION: &str = "aoxo-toml";
+
+/// Command sorting the keys of a document, run with
+/// `workspace/executeCommand` and the arguments
+/// `[{ "uri": "file:///...", "tables": false }]`.
+const SORT: &str = "aoxo-toml.sort";
+
+/// The errors of the checks beyond syntax for `document`, read from `path`:
+/// duplicate keys and, in project files, settings that aren't valid.
+fn checks(path: Option<&Path>, document: &Document) -> Vec<Error> {
+    let (table, errors) = value::lower_with(&document.text, &document.tree, document.version);
+    if errors.is_empty() && path.is_some_and(config::is_project_file) {
+        return config::check(&document.text, &document.tree, &table);
+    }
+    errors
+}
+
+/// The fix of a syntax error that has an obvious one.
+fn quick_fix(error: &Error) -> Option<Fix> {
+    match &error.kind {
+        ErrorKind::NonAsciiKey(key) => Some(Fix {
+            title: "Quote the key".to_string(),
+            edits: vec![lint::Edit {
+                span: error.span,
+                text: format!("\"{key}\""),
+            }],
+            safe: true,
+        }),
+        _ => None,
+    }
+}
+
+/// The key-value pair or table whose key `offset` is in, with the key, the
+/// innermost one for pairs in inline tables.
+fn keyed(tree: &Tree, offset: usize) -> Option<(&Tree, &Tree)> {
+    tree.children.iter().find_map(|child| {
+        let Child::Tree(tree) = child else {
+            return None;
+        };
+        if offset < tree.span.start || tree.span.end < offset {
+            return None;
+        }
+        keyed(tree, offset).or_else(|| {
+            let key = tree.children.iter().find_map(|child| match child {
+                Child::Tree(key) if key.kind == tree::Kind::Key => Some(key),
+                _ => None,
+            })?;
+            let commented = matches!(
+                tree.kind,
+                tree::Kind::KeyVal | tree::Kind::Table | tree::Kind::TableArray
+            );
+            (commented && key.span.start <= offset && offset <= key.span.end).then_some((tree, key))
+        })
+    })
+}
+
+/// What the lint rules find in `document` with `config`, unless it has
+/// syntax errors.
+fn lints(document: &Document, config: &Config) -> Vec<Lint> {
+    if document.errors.is_empty() {
+        lint::lint(&document.text, &document.tree, config)
+    } else {
+        Vec::new()
+    }
+}
+
+fn diagnostics<'e>(
+    uri: &Url,
+    text: &str,
+    errors: impl Iterator<Item = &'e Error>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    errors
         .map(|error| {
-            let start = error.span.start_location(contents).into();
-            let end = error.span.end_location(contents).into();
+            let mut message = error.to_string();
+            for note in &error.notes {
+                message.push_str("\nnote: ");
+                message.push_str(note);
+            }
+            if let Some(help) = &error.help {
+                message.push_str("\nhelp: ");
+                message.push_str(help);
+            }
+
+            let related_information = error
+                .labels
+                .iter()
+                .map(|label| DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: label.span.to_range(text, encoding),
+                    },
+                    message: label.message.clone(),
+                })
+                .collect::<Vec<_>>();
 
             Diagnostic {
-                range: Range { start, end },
-                severity: Some(DiagnosticSeverity::ERROR),
-                code: None,
-                code_description: None,
+                range: error.span.to_range(text, encoding),
+                severity: Some(match error.severity() {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warn | Severity::Allow => DiagnosticSeverity::WARNING,
+                }),
+                code: Some(NumberOrString::String(error.code().to_string())),
+                code_description: Url::parse(&error.url())
+                    .ok()
+                    .map(|href| CodeDescription { href }),
                 source: Some("aoxo-toml".to_string()),
-                message: format!("{:?}", error.kind),
-                related_information: None,
+                message,
+                related_information: (!related_information.is_empty())
+                    .then_some(related_information),
                 tags: None,
                 data: None,
             }
         })
-        .collect();
-
-    client.publish_diagnostics(uri, diagnostics, None).await
+        .collect()
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let encoding = PositionEncoding::negotiate(&params.capabilities);
+        let _ = self.encoding.set(encoding);
+
+        if self.exit_with_client
+            && let Some(pid) = params.process_id
+        {
+            exit_with(pid);
+        }
+
+        let options = params.initialization_options.as_ref();
+        if let Some(check_on_save) = options
+            .and_then(|options| options.get("checkOnSave"))
+            .and_then(serde_json::Value::as_bool)
+        {
+            self.check_on_save.store(check_on_save, Ordering::Relaxed);
+        }
+        let exclude = options
+            .and_then(|options| options.get("exclude"))
+            .and_then(serde_json::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|pattern| Some(pattern.as_str()?.to_string()))
+            .collect();
+        let _ = self.exclude.set(exclude);
+
+        #[allow(deprecated)]
+        let folders = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        let folders = folders
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+        let _ = self.folders.set(folders);
+
+        let capabilities = &params.capabilities;
+        let work_done_progress = capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.work_done_progress
+            .store(work_done_progress, Ordering::Relaxed);
+        let watch_files = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        self.watch_files.store(watch_files, Ordering::Relaxed);
+        let pull = capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|text_document| text_document.diagnostic.is_some());
+        self.pull.store(pull, Ordering::Relaxed);
+        let refresh = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false);
+        self.refresh.store(refresh, Ordering::Relaxed);
+        let configuration = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        self.configuration.store(configuration, Ordering::Relaxed);
+
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                position_encoding: Some(encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
                 )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![SORT.to_string()],
+                    ..Default::default()
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::SOURCE_FIX_ALL,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                diagnostic_provider: pull.then(|| {
+                    DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                        identifier: Some("aoxo-toml".to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: true,
+                        ..Default::default()
+                    })
+                }),
                 ..Default::default()
             },
         })
@@ -61,57 +759,427 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+        self.load_settings().await;
+
+        let workspaces = self.load_workspaces().await;
+        let _ = self.workspaces.set(workspaces.clone());
+        if workspaces.is_empty() {
+            return;
+        }
+        if self.watch_files.load(Ordering::Relaxed) {
+            self.watch_files().await;
+        }
+        let progress = self.work_done_progress.load(Ordering::Relaxed);
+        tokio::spawn(self.files().scan(workspaces, progress));
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file changed!")
-            .await;
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        let encoding = self.encoding();
+        {
+            let mut documents = self.documents.lock().unwrap();
+            let Some(open) = documents.get_mut(&uri) else {
+                return;
+            };
+            for change in params.content_changes {
+                match change.range {
+                    Some(range) => {
+                        let start = offset_at(&open.document.text, range.start, encoding);
+                        let end = offset_at(&open.document.text, range.end, encoding);
+                        open.document.edit(Span::from(start..end), &change.text);
+                    }
+                    None => {
+                        open.document =
+                            Document::with_version(change.text, open.config.toml_version)
+                    }
+                }
+            }
+            open.version = version;
+            // Their spans no longer match the text, they're back on save
+            open.checked.clear();
+            open.lints = lints(&open.document, &open.config);
+            open.result_id = result_id();
+        }
 
-        let contents = params
-            .content_changes
-            .into_iter()
-            .map(|a| a.text)
-            .collect::<Vec<_>>()
-            .join("");
-        let parser = Parser::new(&contents).parse();
-        let tree = parser.tree();
+        self.publish(uri, version).await;
+    }
 
-        publish_diagnostics(
-            &self.client,
-            params.text_document.uri.clone(),
-            &contents,
-            tree.1,
-        )
-        .await;
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        let config = self.config(&uri);
+        let document = Document::with_version(params.text_document.text, config.toml_version);
+        let checked = self.check(&uri, &document);
+        self.scanned.lock().unwrap().remove(&uri);
 
-        self.trees
+        self.documents
             .lock()
             .unwrap()
-            .insert(params.text_document.uri, tree.0);
+            .insert(uri.clone(), Open::new(version, document, checked, config));
+
+        self.publish(uri, version).await;
     }
 
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file opened!")
-            .await;
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let version = {
+            let mut documents = self.documents.lock().unwrap();
+            let Some(open) = documents.get_mut(&uri) else {
+                return;
+            };
+            open.checked = self.check(&uri, &open.document);
+            open.result_id = result_id();
+            open.version
+        };
 
-        let contents = params.text_document.text;
-        let parser = Parser::new(&contents).parse();
-        let tree = parser.tree();
+        // Saving a project file changes the settings of the files under it
+        if uri
+            .to_file_path()
+            .is_ok_and(|path| config::is_project_file(&path))
+        {
+            self.revalidate().await;
+        } else {
+            self.publish(uri, version).await;
+        }
+    }
 
-        publish_diagnostics(
-            &self.client,
-            params.text_document.uri.clone(),
-            &contents,
-            tree.1,
-        )
-        .await;
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.lock().unwrap().remove(&uri);
+
+        // Workspace files go back to being checked from disk, other closed
+        // files are no longer checked, so their errors would go stale
+        if let Ok(path) = uri.to_file_path()
+            && self
+                .workspaces()
+                .iter()
+                .any(|workspace| workspace.contains(&path))
+        {
+            let files = self.files();
+            files.refresh(&path).await;
+            files.changed().await;
+        } else if !self.pull.load(Ordering::Relaxed) {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        // Clients either send the settings or ask to pull them
+        match params.settings.get(SECTION) {
+            Some(settings) => self.apply_settings(settings).await,
+            None => self.load_settings().await,
+        }
+        self.revalidate().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let files = self.files();
+        let mut settings_changed = false;
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            settings_changed |= config::is_project_file(&path);
+            if change.typ == FileChangeType::DELETED {
+                files.forget(change.uri).await;
+            } else if self
+                .workspaces()
+                .iter()
+                .any(|workspace| workspace.contains(&path))
+            {
+                files.refresh(&path).await;
+            }
+        }
+        if settings_changed {
+            self.revalidate().await;
+        } else {
+            files.changed().await;
+        }
+    }
 
-        self.trees
+    /// Shows the comments right above a key as its documentation.
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let encoding = self.encoding();
+        let documents = self.documents.lock().unwrap();
+        let Some(open) = documents.get(&position.text_document.uri) else {
+            return Ok(None);
+        };
+        let text = &open.document.text;
+        let offset = offset_at(text, position.position, encoding);
+
+        let Some((node, key)) = keyed(&open.document.tree, offset) else {
+            return Ok(None);
+        };
+        let Some(documentation) = node.comments.leading_text(text) else {
+            return Ok(None);
+        };
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: documentation,
+            }),
+            range: Some(key.span.to_range(text, encoding)),
+        }))
+    }
+
+    /// Formats with the style of the document's settings. Documents with
+    /// syntax errors are left alone, like `aoxo-toml fmt` does.
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        let style = self.config(&uri).format;
+        let documents = self.documents.lock().unwrap();
+        let Some(open) = documents.get(&uri) else {
+            return Ok(None);
+        };
+        if !open.document.errors.is_empty() {
+            return Ok(None);
+        }
+
+        let text = &open.document.text;
+        let formatted = format_with(text, style);
+        if &formatted == text {
+            return Ok(Some(Vec::new()));
+        }
+        let range = Span::from(0..text.len()).to_range(text, self.encoding());
+        Ok(Some(vec![TextEdit {
+            range,
+            new_text: formatted,
+        }]))
+    }
+
+    /// Sorts the keys of an open document, and its tables if `tables` is
+    /// set, by asking the client to apply the edit.
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        if params.command != SORT {
+            let message = format!("unknown command `{}`", params.command);
+            return Err(jsonrpc::Error::invalid_params(message));
+        }
+        let arguments = params.arguments.first();
+        let Some(uri) = arguments
+            .and_then(|arguments| arguments.get("uri")?.as_str())
+            .and_then(|uri| Url::parse(uri).ok())
+        else {
+            return Err(jsonrpc::Error::invalid_params("expected a document `uri`"));
+        };
+        let tables = arguments
+            .and_then(|arguments| arguments.get("tables")?.as_bool())
+            .unwrap_or(false);
+
+        let sorted = {
+            let documents = self.documents.lock().unwrap();
+            let Some(open) = documents.get(&uri) else {
+                return Err(jsonrpc::Error::invalid_params("the document is not open"));
+            };
+            let text = &open.document.text;
+            let options = sort::Options {
+                tables,
+                version: open.document.version,
+            };
+            sort(text, options).map(|sorted| {
+                (&sorted != text).then(|| TextEdit {
+                    range: Span::from(0..text.len()).to_range(text, self.encoding()),
+                    new_text: sorted,
+                })
+            })
+        };
+        let Some(edit) = sorted else {
+            let message = "Not sorting a document with syntax errors";
+            self.client
+                .show_message(MessageType::WARNING, message)
+                .await;
+            return Ok(None);
+        };
+        if let Some(edit) = edit {
+            let edit = WorkspaceEdit {
+                changes: Some(HashMap::from([(uri, vec![edit])])),
+                ..Default::default()
+            };
+            if let Err(error) = self.client.apply_edit(edit).await {
+                let message = format!("could not sort the document: {error}");
+                self.client.log_message(MessageType::WARNING, message).await;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Offers the fixes of the lints in the range, one by one, and a
+    /// `source.fixAll` action fixing every lint of the document.
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let encoding = self.encoding();
+        let documents = self.documents.lock().unwrap();
+        let Some(open) = documents.get(&uri) else {
+            return Ok(None);
+        };
+        let text = &open.document.text;
+        let start = offset_at(text, params.range.start, encoding);
+        let end = offset_at(text, params.range.end, encoding);
+
+        let edit = |edits: Vec<TextEdit>| WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        };
+        let mut actions = Vec::new();
+        let lints = open
+            .lints
+            .iter()
+            .filter_map(|lint| Some((&lint.error, lint.fix.clone()?)));
+        let errors = open
+            .document
+            .errors
+            .iter()
+            .filter_map(|error| Some((error, quick_fix(error)?)));
+        for (error, fix) in lints.chain(errors) {
+            let span = error.span;
+            if span.end < start || end < span.start {
+                continue;
+            }
+            let edits = fix
+                .edits
+                .iter()
+                .map(|edit| TextEdit {
+                    range: edit.span.to_range(text, encoding),
+                    new_text: edit.text.clone(),
+                })
+                .collect();
+            actions.push(CodeAction {
+                title: fix.title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(diagnostics(&uri, text, std::iter::once(error), encoding)),
+                edit: Some(edit(edits)),
+                is_preferred: Some(fix.safe),
+                ..Default::default()
+            });
+        }
+        if open
+            .lints
+            .iter()
+            .any(|lint| lint.fix.as_ref().is_some_and(|fix| fix.safe))
+            && let Some(fixed) = lint::fix_all(text, &open.config)
+        {
+            let range = Span::from(0..text.len()).to_range(text, encoding);
+            actions.push(CodeAction {
+                title: "Fix all lints".to_string(),
+                kind: Some(CodeActionKind::SOURCE_FIX_ALL),
+                edit: Some(edit(vec![TextEdit {
+                    range,
+                    new_text: fixed,
+                }])),
+                ..Default::default()
+            });
+        }
+
+        // Clients asking for some kinds of actions only want those
+        if let Some(only) = params.context.only {
+            actions.retain(|action| {
+                let kind = action.kind.as_ref().map_or("", |kind| kind.as_str());
+                only.iter().any(|only| {
+                    kind == only.as_str() || kind.starts_with(&format!("{}.", only.as_str()))
+                })
+            });
+        }
+        Ok(Some(
+            actions
+                .into_iter()
+                .map(CodeActionOrCommand::CodeAction)
+                .collect(),
+        ))
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri;
+        let pulled = self.pull(&uri, params.previous_result_id.as_deref());
+        let report = match pulled {
+            Some(Pulled {
+                result_id,
+                diagnostics: None,
+                ..
+            }) => DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            }),
+            pulled => {
+                let (result_id, items) = match pulled {
+                    Some(pulled) => (
+                        Some(pulled.result_id),
+                        pulled.diagnostics.unwrap_or_default(),
+                    ),
+                    None => (None, Vec::new()),
+                };
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id,
+                        items,
+                    },
+                })
+            }
+        };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let previous = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri, previous.value))
+            .collect::<HashMap<_, _>>();
+        let mut uris = self
+            .documents
             .lock()
             .unwrap()
-            .insert(params.text_document.uri, tree.0);
+            .keys()
+            .chain(self.scanned.lock().unwrap().keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        uris.sort();
+        uris.dedup();
+
+        let items = uris
+            .into_iter()
+            .filter_map(|uri| {
+                let pulled = self.pull(&uri, previous.get(&uri).map(String::as_str))?;
+                let version = pulled.version.map(i64::from);
+                Some(match pulled.diagnostics {
+                    None => WorkspaceDocumentDiagnosticReport::Unchanged(
+                        WorkspaceUnchangedDocumentDiagnosticReport {
+                            uri,
+                            version,
+                            unchanged_document_diagnostic_report:
+                                UnchangedDocumentDiagnosticReport {
+                                    result_id: pulled.result_id,
+                                },
+                        },
+                    ),
+                    Some(items) => WorkspaceDocumentDiagnosticReport::Full(
+                        WorkspaceFullDocumentDiagnosticReport {
+                            uri,
+                            version,
+                            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                                result_id: Some(pulled.result_id),
+                                items,
+                            },
+                        },
+                    ),
+                })
+            })
+            .collect();
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -119,23 +1187,136 @@ impl LanguageServer for Backend {
     }
 }
 
+fn service(exit_with_client: bool) -> (LspService<Backend>, ClientSocket) {
+    LspService::new(|client| Backend {
+        client,
+        documents: Arc::default(),
+        encoding: OnceLock::new(),
+        exit_with_client,
+        check_on_save: AtomicBool::new(true),
+        folders: OnceLock::new(),
+        exclude: OnceLock::new(),
+        workspaces: OnceLock::new(),
+        scanned: Arc::default(),
+        pull: AtomicBool::new(false),
+        refresh: AtomicBool::new(false),
+        work_done_progress: AtomicBool::new(false),
+        watch_files: AtomicBool::new(false),
+        configuration: AtomicBool::new(false),
+        settings: Mutex::default(),
+    })
+}
+
+/// Serves one client over `stream` until it disconnects or asks to exit.
+async fn serve_connection(stream: impl AsyncRead + AsyncWrite) {
+    let (read, write) = tokio::io::split(stream);
+    let (service, socket) = service(false);
+    Server::new(read, write, socket).serve(service).await;
+}
+
+/// Serves every client that connects to `listen`, each on its own task.
+async fn listen(listen: Listen) -> std::io::Result<()> {
+    match listen {
+        Listen::Tcp(address) => {
+            let listener = tokio::net::TcpListener::bind(&address).await?;
+            eprintln!("listening on tcp:{}", listener.local_addr()?);
+            loop {
+                let (stream, _) = listener.accept().await?;
+                tokio::spawn(serve_connection(stream));
+            }
+        }
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            // A socket file nobody listens on is left over from a server
+            // that didn't shut down cleanly
+            if path.exists() && std::os::unix::net::UnixStream::connect(&path).is_err() {
+                std::fs::remove_file(&path)?;
+            }
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            eprintln!("listening on unix:{}", path.display());
+            loop {
+                let (stream, _) = listener.accept().await?;
+                tokio::spawn(serve_connection(stream));
+            }
+        }
+        #[cfg(not(unix))]
+        Listen::Unix(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "unix sockets are not supported on this platform",
+        )),
+    }
+}
+
+/// Exits the server once the process `pid` is gone.
+fn exit_with(pid: u32) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            if !is_running(pid) {
+                std::process::exit(0);
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 checks that the process exists without signalling it
+    // SAFETY: `kill` has no memory safety requirements
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Args::parse();
 
+    if let Some(command) = args.command {
+        return match command {
+            Command::Check(check) => cli::check::run(&check),
+            Command::Fmt(fmt) => cli::fmt::run(&fmt),
+            Command::Lint(lint) => cli::lint::run(&lint),
+            Command::Sort(sort) => cli::sort::run(&sort),
+            Command::ToJson(convert) => cli::json::to_json(&convert),
+            Command::FromJson(convert) => cli::json::from_json(&convert),
+            Command::Get(get) => cli::get::run(&get),
+            Command::Set(set) => cli::edit::set(&set),
+            Command::Unset(unset) => cli::edit::unset(&unset),
+        };
+    }
+
     if let Some(file) = args.parse {
         let contents = std::fs::read_to_string(file).unwrap();
         let parser = Parser::new(&contents).parse();
         let tree = parser.tree();
         println!("{:?}", tree);
     } else {
-        let stdin = tokio::io::stdin();
-        let stdout = tokio::io::stdout();
+        if let Some(pid) = args.client_process_id {
+            exit_with(pid);
+        }
 
-        let (service, socket) = LspService::new(|client| Backend {
-            client,
-            trees: Arc::default(),
-        });
-        Server::new(stdin, stdout, socket).serve(service).await;
+        if let Some(address) = args.listen {
+            if let Err(error) = listen(address).await {
+                eprintln!("error: {error}");
+                return cli::exit(cli::ERROR);
+            }
+        } else {
+            let stdin = tokio::io::stdin();
+            let stdout = tokio::io::stdout();
+
+            let (service, socket) = service(args.client_process_id.is_none());
+            Server::new(stdin, stdout, socket).serve(service).await;
+        }
     }
+
+    ExitCode::SUCCESS
 }
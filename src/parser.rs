@@ -1,22 +1,25 @@
+use crate::comment;
+use crate::config::TomlVersion;
+use crate::error::ErrorKind;
 use crate::tree;
 use crate::{lexer::Lexer, span::Span};
 use std::{cell::Cell, u8};
 
-mod grammar;
+pub use crate::error::Error;
 
-#[derive(Debug)]
-pub struct Error {
-    pub span: Span,
-    pub kind: tree::Kind,
-}
+mod grammar;
 
 #[derive(Debug)]
 pub struct Parser<'src> {
     lexer: Lexer<'src>,
     events: Vec<Event>,
-    #[cfg(debug_assertions)]
+    /// How many more times the next token can be looked at before it's
+    /// consumed, see [`Parser::stuck`].
     fuel: Cell<u8>,
     errors: Vec<Error>,
+    /// Span of the last token consumed.
+    last: Span,
+    version: TomlVersion,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -58,15 +61,24 @@ impl Status {
 
 impl<'src> Parser<'src> {
     pub fn new(source: &'src str) -> Self {
+        let mut lexer = Lexer::new(source);
+        let errors = lexer.take_errors();
         Self {
-            lexer: Lexer::new(source),
+            lexer,
             events: Vec::with_capacity(15),
-            #[cfg(debug_assertions)]
             fuel: Cell::new(u8::MAX),
-            errors: Vec::new(),
+            errors,
+            last: Span::from(0..0),
+            version: TomlVersion::default(),
         }
     }
 
+    /// Reads the source as `version` of TOML, instead of 1.0.
+    pub fn with_version(mut self, version: TomlVersion) -> Self {
+        self.version = version;
+        self
+    }
+
     fn open(&mut self) -> MarkOpen {
         self.events.push(Event::Open {
             kind: tree::Kind::Unknown,
@@ -79,87 +91,212 @@ impl<'src> Parser<'src> {
 
     fn close(&mut self, mark: MarkOpen, kind: tree::Kind) {
         self.events.push(Event::Close);
-        let last = self.events[mark.index];
-        if let Event::Open { span, .. } = last {
-            self.events[mark.index] = Event::Open { kind, span };
-        } else {
-            panic!("invalid mark")
+        if let Some(Event::Open { kind: open, .. }) = self.events.get_mut(mark.index) {
+            *open = kind;
         }
     }
 
-    fn add_error_full(&mut self, kind: tree::Kind) {
-        self.errors.push(Error {
-            span: self.lexer.peek_span::<0>(),
-            kind,
-        })
+    fn add_error_full(&mut self, kind: ErrorKind) {
+        self.errors
+            .push(Error::new(self.lexer.peek_span::<0>(), kind))
     }
 
-    fn add_error(&mut self, kind: tree::Kind) {
-        self.errors.push(Error {
-            span: self.lexer.peek_span::<0>().reduce_to(1),
-            kind,
-        })
+    fn add_error(&mut self, kind: ErrorKind) {
+        self.errors.push(Error::new(self.here(), kind))
+    }
+
+    fn push_error(&mut self, error: Error) {
+        self.errors.push(error)
+    }
+
+    /// Reports the error `error` builds, unless the construct starting at
+    /// `start` already has one: the errors that would follow the first one
+    /// in a malformed construct are mostly caused by it.
+    fn report(&mut self, start: usize, error: impl FnOnce(&Self, Span) -> Error) {
+        let end = self.peek_span().start;
+        let reported = self
+            .errors
+            .iter()
+            .rev()
+            .any(|error| start <= error.span.start && error.span.start <= end);
+        if !reported {
+            let error = error(self, self.here());
+            self.push_error(error);
+        }
+    }
+
+    /// Skips a token of `kind`, or reports the error `error` builds, as
+    /// [`Parser::report`] does, when the next token is something else.
+    fn expect(
+        &mut self,
+        start: usize,
+        kind: crate::token::Kind,
+        error: impl FnOnce(&Self, Span) -> Error,
+    ) {
+        if self.skip_if(kind).failed() {
+            self.report(start, error);
+        }
+    }
+
+    /// Wraps the tokens up to the next one in `recovery`, or the end of the
+    /// input, in an error node, so that the rule that couldn't read them
+    /// goes on from there.
+    fn recover(&mut self, recovery: &[crate::token::Kind]) {
+        if self.eof() || recovery.contains(&self.peek_kind()) {
+            return;
+        }
+        let mark = self.open();
+        while !self.eof() && !recovery.contains(&self.peek_kind()) {
+            self.advance();
+        }
+        self.close(mark, tree::Kind::Error);
+    }
+
+    /// Whether the next token is the first one on its line.
+    fn at_line_start(&self) -> bool {
+        self.last.end == 0 || self.text(self.last).ends_with('\n')
+    }
+
+    /// Where errors about the next token are reported.
+    fn here(&self) -> Span {
+        self.lexer.peek_span::<0>().reduce_to(1)
+    }
+
+    fn peek_span(&self) -> Span {
+        self.lexer.peek_span::<0>()
+    }
+
+    fn last_span(&self) -> Span {
+        self.last
+    }
+
+    fn text(&self, span: Span) -> &str {
+        self.lexer
+            .source()
+            .get(span.start..span.end)
+            .unwrap_or_default()
+    }
+
+    /// Reports that the grammar needed a token where the input ended.
+    fn unexpected_eof(&mut self) -> Status {
+        if !self.stuck() {
+            self.add_error(ErrorKind::UnexpectedEof);
+        }
+        Status::Failure
     }
 
     fn advance(&mut self) -> Status {
-        assert!(!self.eof());
-        #[cfg(debug_assertions)]
+        if self.eof() {
+            return self.unexpected_eof();
+        }
         self.fuel.set(u8::MAX);
-        self.events.push(Event::Advance {
-            token: self.lexer.next_token(Some(&mut self.errors)),
-        });
+        let token = self.lexer.next_token(Some(&mut self.errors));
+        self.last = token.span;
+        self.events.push(Event::Advance { token });
+        Status::Advanced
+    }
+
+    /// Advances over a token the lexer read as a value, like `1.2` or
+    /// `true`, as the parts of the bare key it is where a key is expected.
+    fn advance_key(&mut self) -> Status {
+        if self.eof() {
+            return self.unexpected_eof();
+        }
+        self.fuel.set(u8::MAX);
+        let token = self.lexer.next_token(Some(&mut self.errors));
+        self.last = token.span;
+        let mut start = token.span.start;
+        let parts = self
+            .text(token.span)
+            .split('.')
+            .map(str::len)
+            .collect::<Vec<_>>();
+        for (i, len) in parts.into_iter().enumerate() {
+            if i > 0 {
+                self.events.push(Event::Skip {
+                    span: Span::from(start..start + 1),
+                });
+                start += 1;
+            }
+            let span = Span::from(start..start + len);
+            self.events.push(Event::Advance {
+                token: crate::token::Token::new(span, crate::token::Kind::Key),
+            });
+            start = span.end;
+        }
         Status::Advanced
     }
 
     fn skip(&mut self) -> Status {
-        assert!(!self.eof());
-        #[cfg(debug_assertions)]
+        if self.eof() {
+            return self.unexpected_eof();
+        }
         self.fuel.set(u8::MAX);
         let token = self.lexer.next_token(Some(&mut self.errors));
+        self.last = token.span;
         self.events.push(Event::Skip { span: token.span });
         Status::Advanced
     }
 
     fn ignore(&mut self) -> Status {
-        assert!(!self.eof());
-        #[cfg(debug_assertions)]
+        if self.eof() {
+            return self.unexpected_eof();
+        }
         self.fuel.set(u8::MAX);
         self.events.push(Event::Ignore);
-        self.lexer.next_token(Some(&mut self.errors));
+        self.last = self.lexer.next_token(Some(&mut self.errors)).span;
         Status::Advanced
     }
 
+    /// Whether the grammar looked at the same token too many times without
+    /// consuming it, which is a bug in the grammar. From then on the parser
+    /// acts as if the input ended, so that every rule unwinds, and
+    /// [`Parser::unstick`] gets past the token.
+    fn stuck(&self) -> bool {
+        self.fuel.get() == 0
+    }
+
+    /// Reports where the grammar got stuck and wraps the token it got stuck
+    /// on in an error node, so that parsing goes on after it instead of
+    /// hanging.
+    fn unstick(&mut self) {
+        self.push_error(
+            Error::new(self.here(), ErrorKind::Stuck)
+                .with_help("this is a bug in aoxo-toml, please report it"),
+        );
+        self.fuel.set(u8::MAX);
+        if !self.eof() {
+            let mark = self.open();
+            self.advance();
+            self.close(mark, tree::Kind::Error);
+        }
+    }
+
     fn peek_kind(&self) -> crate::token::Kind {
-        #[cfg(debug_assertions)]
-        if self.fuel.get() == 0 {
-            panic!(
-                "parser is stuck at {} with token {:?}",
-                self.lexer
-                    .peek_span::<0>()
-                    .start_location(self.lexer.source()),
-                self.lexer.peek_kind::<0>()
-            )
+        if self.stuck() {
+            return crate::token::Kind::Eof;
         }
         self.lexer.peek_kind::<0>()
     }
 
     fn next_are<const N: usize>(&self, kinds: [crate::token::Kind; N]) -> bool {
-        #[cfg(debug_assertions)]
-        {
-            assert!(self.fuel.get() > 0);
+        self.fuel.set(
             self.fuel
-                .set(self.fuel.get().saturating_sub(N.try_into().unwrap()));
-        }
+                .get()
+                .saturating_sub(u8::try_from(N).unwrap_or(u8::MAX)),
+        );
 
-        self.lexer.peek_kind_array::<N>() == kinds
+        !self.stuck() && self.lexer.peek_kind_array::<N>() == kinds
+    }
+
+    /// Like [`Parser::next_are`], with nothing between the two tokens, like
+    /// the brackets of `[[`.
+    fn next_joined(&self, kinds: [crate::token::Kind; 2]) -> bool {
+        self.next_are(kinds) && self.lexer.peek_span::<0>().end == self.lexer.peek_span::<1>().start
     }
 
     fn next_is(&self, kind: crate::token::Kind) -> bool {
-        #[cfg(debug_assertions)]
-        {
-            assert!(self.fuel.get() > 0);
-            self.fuel.set(self.fuel.get().saturating_sub(1));
-        }
+        self.fuel.set(self.fuel.get().saturating_sub(1));
         self.peek_kind() == kind
     }
 
@@ -171,9 +308,28 @@ impl<'src> Parser<'src> {
         self.skip_if_any(&[kind])
     }
 
-    fn skip_expect(&mut self, kind: crate::token::Kind) {
-        if self.skip_if(kind).failed() {
-            self.add_error(tree::Kind::Expected(kind));
+    fn skip_expect(&mut self, kinds: &'static [crate::token::Kind]) {
+        self.skip_expect_or(kinds, |_, here| {
+            Error::new(
+                here,
+                ErrorKind::Expected {
+                    expected: kinds,
+                    after: None,
+                },
+            )
+        })
+    }
+
+    /// Like [`Parser::skip_expect`], building the error to report with
+    /// `error` when none of the tokens is there.
+    fn skip_expect_or(
+        &mut self,
+        kinds: &[crate::token::Kind],
+        error: impl FnOnce(&Self, Span) -> Error,
+    ) {
+        if self.skip_if_any(kinds).failed() {
+            let error = error(self, self.here());
+            self.push_error(error);
         }
     }
 
@@ -196,7 +352,7 @@ impl<'src> Parser<'src> {
     }
 
     fn eof(&self) -> bool {
-        self.lexer.peek_kind::<0>() == crate::token::Kind::Eof
+        self.stuck() || self.lexer.peek_kind::<0>() == crate::token::Kind::Eof
     }
 
     pub fn parse(mut self) -> Self {
@@ -207,32 +363,47 @@ impl<'src> Parser<'src> {
     pub fn tree(mut self) -> (tree::Tree, Vec<Error>) {
         let mut stack: Vec<tree::Tree> = Vec::new();
 
-        assert!(matches!(self.events.pop(), Some(Event::Close)));
+        // Whatever the grammar left unconsumed
+        while self.lexer.peek_kind::<0>() != crate::token::Kind::Eof {
+            let token = self.lexer.next_token(Some(&mut self.errors));
+            self.errors.push(Error::new(
+                token.span,
+                ErrorKind::UnexpectedToken(token.kind),
+            ));
+        }
 
         for event in self.events.iter().copied() {
             match event {
                 Event::Open { kind, span } => {
                     stack.push(tree::Tree::new().with_kind(kind).with_span(span));
                 }
-                Event::Close => {
+                Event::Close if stack.len() > 1 => {
                     let tree = stack.pop().unwrap();
                     stack.last_mut().unwrap().span(tree.span);
                     stack.last_mut().unwrap().child(tree::Child::Tree(tree));
                 }
+                Event::Close => {}
                 Event::Advance { token } => {
-                    stack.last_mut().unwrap().span(token.span);
-                    stack.last_mut().unwrap().child(tree::Child::Token(token));
+                    if let Some(parent) = stack.last_mut() {
+                        parent.span(token.span);
+                        parent.child(tree::Child::Token(token));
+                    }
                 }
                 Event::Skip { span } => {
-                    stack.last_mut().unwrap().span(span);
+                    if let Some(parent) = stack.last_mut() {
+                        parent.span(span);
+                    }
                 }
                 Event::Ignore => {}
             }
         }
 
-        assert!(stack.len() == 1, "stack is not empty {:?}", stack);
-        assert_eq!(self.lexer.peek_kind::<0>(), crate::token::Kind::Eof);
+        let mut tree = stack
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| tree::Tree::new().with_kind(tree::Kind::Toml));
+        comment::attach(self.lexer.source(), &mut tree);
 
-        (stack.pop().unwrap(), self.errors)
+        (tree, self.errors)
     }
 }
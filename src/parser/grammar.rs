@@ -1,32 +1,39 @@
-use super::{Parser, Status};
+use super::Parser;
+use crate::config::TomlVersion;
+use crate::error::{Error, ErrorKind};
+use crate::span::Span;
 use crate::token::{self, Kind::*};
 use crate::tree;
 
-const TABLE_FOLLOW: &[token::Kind] = &[StringOrKey, Key, LBracket];
+// Recovery sets: the tokens a rule stops skipping at when it can't read
+// what's next, wrapping the skipped ones in an error node. Rules that read
+// whole lines go on at the next line, where a header or a key can start;
+// rules inside brackets go on at the next separator or the closing bracket.
+//
+// `[` isn't in the line set: the first line ending after the skipped tokens
+// is always a `Newline`, blank lines after it being trivia, so skipping
+// stops before any header. A `[` earlier on the same line can't start one.
+
+/// Where key-value pairs and table headers go on.
+const LINE_RECOVERY: &[token::Kind] = &[Newline];
+/// Where the elements of an array go on.
+const ARRAY_RECOVERY: &[token::Kind] = &[Comma, RBracket, Newline];
+/// Where the key-value pairs of an inline table go on.
+const INLINE_TABLE_RECOVERY: &[token::Kind] = &[Comma, RCurly, Newline];
 
 struct Advanced;
 
-impl From<Status> for Advanced {
-    fn from(value: Status) -> Self {
-        if matches!(value, Status::Advanced) {
-            Self
-        } else {
-            panic!()
-        }
-    }
-}
-
-pub fn ignore_until(p: &mut Parser, matches: &[token::Kind]) {
-    while !p.eof() && !matches.contains(&p.peek_kind()) {
-        p.ignore();
-    }
-}
-
 // Toml = Expr*
 pub fn toml(p: &mut Parser) {
     let mark = p.open();
 
-    while !p.eof() {
+    loop {
+        if p.stuck() {
+            p.unstick();
+        }
+        if p.eof() {
+            break;
+        }
         expr(p);
     }
 
@@ -34,8 +41,20 @@ pub fn toml(p: &mut Parser) {
 }
 
 fn maybe_key(p: &Parser) -> bool {
-    // FIRST(Key) "str_key", "key"
-    p.next_is(StringOrKey) || p.next_is(Key)
+    // FIRST(Key) "str_key", "key", and values that are bare keys too
+    p.next_is(StringOrKey) || p.next_is(Key) || value_as_key(p)
+}
+
+/// Whether the next token is a value, like `1`, `true`, `inf` or `1.2`,
+/// that also reads as a bare key, dotted for floats.
+fn value_as_key(p: &Parser) -> bool {
+    (p.next_is(Integer) || p.next_is(Float) || p.next_is(Bool))
+        && p.text(p.peek_span()).split('.').all(|part| {
+            !part.is_empty()
+                && part
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        })
 }
 
 fn maybe_value(p: &Parser) -> bool {
@@ -49,54 +68,161 @@ fn maybe_value(p: &Parser) -> bool {
         || p.next_is(LCurly)
 }
 
+/// Whether the next token starts a line with a table header, where a
+/// bracket left open on an earlier line ends.
+fn header_ahead(p: &Parser) -> bool {
+    p.at_line_start() && (p.next_are([LBracket, Key]) || p.next_are([LBracket, LBracket, Key]))
+}
+
+/// Whether the next token starts a line with a table header or a key-value
+/// pair, where an array left open on an earlier line ends.
+fn line_ahead(p: &Parser) -> bool {
+    header_ahead(p)
+        || p.at_line_start()
+            && (p.next_are([Key, Equal])
+                || p.next_are([Key, Dot])
+                || p.next_are([StringOrKey, Equal])
+                || p.next_are([StringOrKey, Dot]))
+}
+
 // Expr =
 //       TableArray
 //     | Table
 //     | KeyVal
 fn expr(p: &mut Parser) -> Advanced {
-    if p.next_are([LBracket, LBracket]) {
+    if p.next_joined([LBracket, LBracket]) {
         table_array(p)
     } else if p.next_is(LBracket) {
         table(p)
     } else if maybe_key(p) {
-        key_val(p)
+        let start = p.peek_span().start;
+        key_val(p, LINE_RECOVERY);
+        // The newline itself is left to the next `expr`, outside any node
+        end_of_key_val(p, start);
+        Advanced
+    } else if p.next_is(Newline) {
+        p.ignore();
+        Advanced
     } else {
-        p.ignore().into()
+        unexpected_line(p);
+        Advanced
+    }
+}
+
+/// Skips a line that starts with neither a key nor a header.
+fn unexpected_line(p: &mut Parser) {
+    let start = p.peek_span().start;
+    p.report(start, |p, here| {
+        Error::new(here, ErrorKind::MissingKey)
+            .with_note(format!("found {}", p.peek_kind()))
+            .with_help("a quoted key, like `\"1\" = true`, is always valid")
+    });
+    p.recover(LINE_RECOVERY);
+}
+
+fn missing_key(p: &mut Parser, start: usize, after: &str) {
+    p.report(start, |_, here| {
+        Error::new(here, ErrorKind::MissingKey)
+            .with_note(format!("a key was expected after {after}"))
+            .with_help("keys are either bare, like `name`, or quoted, like `\"my name\"`")
+    });
+}
+
+fn unclosed(here: Span, delimiter: token::Kind, what: &'static str, open: Span) -> Error {
+    Error::new(here, ErrorKind::Unclosed { delimiter, what })
+        .with_label(open, format!("{what} starts here"))
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default().trim_end()
+}
+
+/// Each key-value pair of a table goes on a line of its own, the last one
+/// maybe ending the file instead. The rest of a line with more on it is
+/// skipped.
+fn end_of_key_val(p: &mut Parser, start: usize) {
+    if p.eof() || p.next_is(Newline) || p.at_line_start() {
+        return;
+    }
+    p.report(start, |p, here| {
+        let pair = Span::from(start..p.last_span().end);
+        Error::new(
+            here,
+            ErrorKind::Expected {
+                expected: &[Newline],
+                after: Some(format!("`{}`", first_line(p.text(pair)))),
+            },
+        )
+        .with_help("put each key-value pair on a line of its own")
+    });
+    p.recover(LINE_RECOVERY);
+}
+
+fn end_of_header(p: &mut Parser, start: usize) {
+    if p.eof() || p.next_is(Newline) {
+        return;
+    }
+    p.report(start, |p, here| {
+        let header = Span::from(start..p.last_span().end);
+        Error::new(
+            here,
+            ErrorKind::Expected {
+                expected: &[Newline],
+                after: Some(format!("table header `{}`", first_line(p.text(header)))),
+            },
+        )
+    });
+    p.recover(LINE_RECOVERY);
+}
+
+/// The key-value pairs under a header, each on a line of its own. Lines
+/// that don't start with a key are skipped, so that the pairs after them
+/// still belong to the table.
+fn table_body(p: &mut Parser) {
+    loop {
+        if maybe_key(p) {
+            let start = p.peek_span().start;
+            key_val(p, LINE_RECOVERY);
+            end_of_key_val(p, start);
+        } else if p.next_is(Newline) {
+            p.skip();
+        } else if p.eof() || p.next_is(LBracket) {
+            break;
+        } else {
+            unexpected_line(p);
+        }
     }
 }
 
 // TableArray = '[[' Key ']]' '\n' (KeyVal '\n')*
 fn table_array(p: &mut Parser) -> Advanced {
     let mark = p.open();
+    let open = p.peek_span();
 
-    p.skip_expect(LBracket);
-    p.skip_expect(LBracket);
+    p.skip_expect(&[LBracket]);
+    p.skip_expect(&[LBracket]);
 
     if maybe_key(p) {
-        key(p);
+        key(p, open.start);
     } else {
-        p.add_error(tree::Kind::MissingKey);
-        ignore_until(p, TABLE_FOLLOW); // Recover
-        p.close(mark, tree::Kind::TableArray);
-        return Advanced; // We did advance at least [LBracket, LBracket]
+        missing_key(p, open.start, "`[[`");
     }
 
-    if p.next_are([RBracket, RBracket]) {
-        p.skip_expect(RBracket);
-        p.skip_expect(RBracket);
-    } else if p.next_is(RBracket) {
-        p.add_error(tree::Kind::Expected(RBracket));
-        p.skip();
+    if p.next_joined([RBracket, RBracket]) {
+        p.skip_expect(&[RBracket]);
+        p.skip_expect(&[RBracket]);
     } else {
-        p.add_error(tree::Kind::Expected(DoubleRBracket));
+        let open = Span::from(open.start..open.start + 2);
+        p.report(open.start, |_, here| {
+            unclosed(here, DoubleRBracket, "the array of tables header", open)
+        });
+        if p.next_is(RBracket) {
+            p.skip();
+        }
     }
 
-    p.skip_expect(Newline);
-
-    while maybe_key(p) {
-        key_val(p);
-        p.skip_expect(Newline);
-    }
+    end_of_header(p, open.start);
+    table_body(p);
 
     p.close(mark, tree::Kind::TableArray);
 
@@ -106,26 +232,22 @@ fn table_array(p: &mut Parser) -> Advanced {
 // Table = '[' Key ']' '\n' (KeyVal '\n')*
 fn table(p: &mut Parser) -> Advanced {
     let mark = p.open();
+    let open = p.peek_span();
 
-    p.skip_expect(LBracket);
+    p.skip_expect(&[LBracket]);
 
     if maybe_key(p) {
-        key(p);
+        key(p, open.start);
     } else {
-        p.add_error(tree::Kind::MissingKey);
-        ignore_until(p, TABLE_FOLLOW); // Recover
-        p.close(mark, tree::Kind::Table);
-        return Advanced; // We did advance at least LBracket
+        missing_key(p, open.start, "`[`");
     }
 
-    p.skip_expect(RBracket);
-
-    p.skip_expect(Newline);
+    p.expect(open.start, RBracket, |_, here| {
+        unclosed(here, RBracket, "the table header", open)
+    });
 
-    while maybe_key(p) {
-        key_val(p);
-        p.skip_expect(Newline);
-    }
+    end_of_header(p, open.start);
+    table_body(p);
 
     p.close(mark, tree::Kind::Table);
 
@@ -133,13 +255,34 @@ fn table(p: &mut Parser) -> Advanced {
 }
 
 // KeyVal = Key '=' Value
-fn key_val(p: &mut Parser) -> Advanced {
+fn key_val(p: &mut Parser, recovery: &[token::Kind]) -> Advanced {
     debug_assert!(maybe_key(p));
     let mark = p.open();
 
-    key(p);
-    p.skip_expect(Equal);
-    value(p);
+    let start = p.peek_span().start;
+    key(p, start);
+    if p.next_is(Equal) {
+        p.skip();
+        value(p, start, recovery);
+    } else {
+        p.report(start, |p, here| {
+            let key = Span::from(start..p.last_span().end);
+            Error::new(
+                here,
+                ErrorKind::Expected {
+                    expected: &[Equal, Dot],
+                    after: Some(format!("key `{}`", p.text(key))),
+                },
+            )
+            .with_help("a key is followed by `=` and its value, like `name = \"value\"`")
+        });
+        // Only the `=` is missing, like in `name "value"`
+        if maybe_value(p) {
+            value(p, start, recovery);
+        } else {
+            p.recover(recovery);
+        }
+    }
 
     p.close(mark, tree::Kind::KeyVal);
 
@@ -147,25 +290,29 @@ fn key_val(p: &mut Parser) -> Advanced {
 }
 
 // Key = KeyPart ('.' KeyPart)*
-fn key(p: &mut Parser) {
+fn key(p: &mut Parser, start: usize) {
     let mark = p.open();
 
-    key_part(p);
+    key_part(p, start, None);
 
     while p.next_is(Dot) {
         p.skip();
-        key_part(p);
+        key_part(p, start, Some("`.`"));
     }
 
     p.close(mark, tree::Kind::Key);
 }
 
 // KeyPart = 'str_key' | 'key'
-fn key_part(p: &mut Parser) {
+fn key_part(p: &mut Parser, start: usize, after: Option<&str>) {
     if p.next_is(StringOrKey) || p.next_is(Key) {
         p.advance();
+    } else if value_as_key(p) {
+        p.advance_key();
+    } else if let Some(after) = after {
+        missing_key(p, start, after);
     } else {
-        p.add_error(tree::Kind::MissingKey);
+        p.report(start, |_, here| Error::new(here, ErrorKind::MissingKey));
     }
 }
 
@@ -175,7 +322,7 @@ fn key_part(p: &mut Parser) {
 //     | 'bool'
 //     | Array
 //     | TableInline
-fn value(p: &mut Parser) {
+fn value(p: &mut Parser, start: usize, recovery: &[token::Kind]) {
     if p.next_is(StringOrKey) | p.next_is(StringMultiline) {
         p.advance();
     } else if p.next_is(Integer) | p.next_is(Float) {
@@ -187,52 +334,84 @@ fn value(p: &mut Parser) {
     } else if p.next_is(LCurly) {
         table_inline(p);
     } else {
-        p.add_error(tree::Kind::MissingValue);
+        missing_value(p, start);
+        p.recover(recovery);
     }
 }
 
-// Array = '[' Value? (',' '\n'? Value)* ']'
+fn missing_value(p: &mut Parser, start: usize) {
+    p.report(start, |_, here| {
+        Error::new(here, ErrorKind::MissingValue)
+            .with_help("values are strings, numbers, booleans, arrays or inline tables")
+    });
+}
+
+// Array = '[' (Value (',' Value)* ','?)? ']'
+// Newlines are allowed anywhere between the brackets.
 fn array(p: &mut Parser) {
     let mark = p.open();
+    let open = p.peek_span();
 
-    p.skip_expect(LBracket);
-
-    check_newline(p);
-
-    if maybe_value(p) {
-        value(p);
-    }
+    p.skip_expect(&[LBracket]);
 
-    check_newline(p);
-
-    while maybe_value(p) || p.next_is(Comma) || p.next_is(Newline) {
+    // Whether the last thing read is an element, which needs a `,` before
+    // the next one
+    let mut element = false;
+    loop {
+        check_newline(p);
+        if p.eof() || p.next_is(RBracket) || line_ahead(p) {
+            break;
+        }
         if p.next_is(Comma) {
+            if !element {
+                missing_value(p, open.start);
+            }
             p.skip();
+            element = false;
+        } else if maybe_value(p) {
+            if element {
+                missing_comma(p, open.start, "array elements", &[Comma, RBracket]);
+            }
+            value(p, open.start, ARRAY_RECOVERY);
+            element = true;
         } else {
-            p.add_error(tree::Kind::Expected(Comma));
+            missing_value(p, open.start);
+            p.recover(ARRAY_RECOVERY);
+            element = false;
         }
-
-        check_newline(p);
-
-        if maybe_value(p) {
-            value(p);
-        } else {
-            p.add_error(tree::Kind::MissingValue);
-        }
-
-        check_newline(p);
     }
 
-    p.skip_expect(RBracket);
+    p.expect(open.start, RBracket, |_, here| {
+        unclosed(here, RBracket, "the array", open)
+    });
 
     p.close(mark, tree::Kind::Array);
 }
 
-fn check_forbidden_newline(p: &mut Parser) {
-    if p.next_is(Newline) {
-        p.add_error(tree::Kind::NewlinesForbiddenInContext);
-        p.skip();
+/// Reports the `,` missing between two of `between`, `expected` being it
+/// and the token that closes them.
+fn missing_comma(p: &mut Parser, start: usize, between: &str, expected: &'static [token::Kind]) {
+    p.report(start, |_, here| {
+        Error::new(
+            here,
+            ErrorKind::Expected {
+                expected,
+                after: None,
+            },
+        )
+        .with_help(format!("separate {between} with `,`"))
+    });
+}
+
+/// Skips a newline inside an inline table, returning it. Whether TOML 1.0
+/// allows it depends on whether the table is closed after all.
+fn inline_newline(p: &mut Parser) -> Option<Span> {
+    if !p.next_is(Newline) {
+        return None;
     }
+    let newline = p.peek_span();
+    p.skip();
+    Some(newline)
 }
 
 fn check_newline(p: &mut Parser) {
@@ -241,39 +420,89 @@ fn check_newline(p: &mut Parser) {
     }
 }
 
-// TableInline = '{' KeyVal? (',' KeyVal)* '}'
+// TableInline = '{' (KeyVal (',' KeyVal)*)? '}'
+// TOML 1.1 allows newlines anywhere between the braces, and a trailing comma.
 fn table_inline(p: &mut Parser) {
     let mark = p.open();
-
-    p.skip_expect(LCurly);
-
-    check_forbidden_newline(p);
-
-    if maybe_key(p) {
-        key_val(p);
-    }
-
-    check_forbidden_newline(p);
-
-    while p.next_is(Comma) || maybe_key(p) || p.next_is(Newline) {
+    let open = p.peek_span();
+
+    p.skip_expect(&[LCurly]);
+
+    // Whether the last thing read is a pair, which needs a `,` before the
+    // next one, or a `,`
+    let mut pair = false;
+    let mut comma = false;
+    // The first newline between the braces
+    let mut newline = None;
+    let v1_0 = p.version < TomlVersion::V1_1;
+    loop {
+        let skipped = inline_newline(p);
+        newline = newline.or(skipped);
+        if p.eof() || p.next_is(RCurly) || header_ahead(p) {
+            break;
+        }
+        // A pair on a line of its own, with no `,` before it, is more
+        // likely a table left open than one spanning lines, which 1.0
+        // doesn't allow anyway
+        if v1_0 && pair && skipped.is_some() && line_ahead(p) {
+            break;
+        }
         if p.next_is(Comma) {
+            if !pair {
+                p.report(open.start, |_, here| {
+                    Error::new(here, ErrorKind::MissingKeyValue)
+                });
+            }
             p.skip();
+            (pair, comma) = (false, true);
+        } else if maybe_key(p) {
+            if pair {
+                missing_comma(p, open.start, "key-value pairs", &[Comma, RCurly]);
+            }
+            key_val(p, INLINE_TABLE_RECOVERY);
+            (pair, comma) = (true, false);
         } else {
-            p.add_error(tree::Kind::Expected(Comma));
+            p.report(open.start, |_, here| {
+                Error::new(here, ErrorKind::MissingKeyValue)
+            });
+            p.recover(INLINE_TABLE_RECOVERY);
+            (pair, comma) = (false, false);
         }
-
-        check_forbidden_newline(p);
-
-        if maybe_key(p) {
-            key_val(p);
-        } else {
-            p.add_error(tree::Kind::MissingKeyValue);
-        }
-
-        check_forbidden_newline(p);
     }
 
-    p.skip_expect(RCurly);
+    // A table left open is reported at its `{`, rather than at the newline
+    // 1.0 doesn't allow or wherever the table ends up ending
+    if !p.next_is(RCurly) {
+        p.report(open.start, |_, _| {
+            let error = Error::new(
+                open,
+                ErrorKind::Unclosed {
+                    delimiter: RCurly,
+                    what: "the inline table",
+                },
+            );
+            match newline {
+                Some(newline) if v1_0 => error
+                    .with_label(newline, "the line ends before the `}`")
+                    .with_note("an inline table must fit on a single line in TOML 1.0"),
+                _ => error,
+            }
+        });
+    }
+    if let Some(newline) = newline.filter(|_| v1_0) {
+        p.report(open.start, |_, _| {
+            Error::new(newline, ErrorKind::NewlineInInlineTable)
+                .with_note("an inline table must fit on a single line in TOML 1.0")
+                .with_help("use a regular `[table]` for longer tables, or TOML 1.1")
+        });
+    }
+    if comma && p.next_is(RCurly) && v1_0 {
+        p.report(open.start, |_, here| {
+            Error::new(here, ErrorKind::MissingKeyValue)
+                .with_note("trailing commas are not allowed in inline tables in TOML 1.0")
+        });
+    }
+    p.skip_if(RCurly);
 
     p.close(mark, tree::Kind::InlineTable);
 }
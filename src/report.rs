@@ -0,0 +1,302 @@
+//! Renders errors for terminals and tools: rustc-style snippets, one-line
+//! summaries, JSON and SARIF.
+
+use std::fmt::Write as _;
+
+use serde_json::{json, Value};
+
+use crate::{
+    config::Severity,
+    error::Error,
+    span::{Lines, Span},
+};
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Errors of one file along with what's needed to show them.
+pub struct Report<'a> {
+    pub path: &'a str,
+    pub source: &'a str,
+    pub errors: &'a [Error],
+}
+
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// 1-based line and column, in characters, of byte `offset`, with the
+/// `lines` of `source`.
+fn line_col(source: &str, lines: &Lines, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let line = lines.line(offset);
+    let start = lines.start(line);
+    let col = source[start..]
+        .char_indices()
+        .take_while(|(i, _)| start + i < offset)
+        .count();
+    (line + 1, col + 1)
+}
+
+/// What an error of `severity` is called, like `warning` in
+/// `warning[quoted-key]`.
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warn | Severity::Allow => "warning",
+        Severity::Error => "error",
+    }
+}
+
+/// Width on screen of `text`, counting tabs as four columns.
+fn width(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+impl Report<'_> {
+    /// Byte offset where 1-based line `number` starts, and its text.
+    fn line(&self, lines: &Lines, number: usize) -> (usize, &str) {
+        let span = lines.span(self.source, number.saturating_sub(1));
+        (span.start, &self.source[span.start..span.end])
+    }
+
+    /// Underline for `span` on the line it starts on, as the part before the
+    /// mark and the mark's width.
+    fn underline(&self, lines: &Lines, span: Span) -> (usize, usize) {
+        let (number, _) = line_col(self.source, lines, span.start);
+        let (line_start, line) = self.line(lines, number);
+
+        let start = span.start.saturating_sub(line_start).min(line.len());
+        let end = span.end.saturating_sub(line_start).clamp(start, line.len());
+        let before = line.get(..start).unwrap_or_default();
+        let marked = line.get(start..end).unwrap_or_default();
+
+        (width(before), width(marked).max(1))
+    }
+
+    /// Renders every error with a snippet of the source, the way rustc does.
+    pub fn human(&self, color: bool) -> String {
+        let style = Style { color };
+        let lines = Lines::new(self.source);
+        let mut out = String::new();
+        for error in self.errors {
+            self.human_one(&mut out, error, &style, &lines);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn human_one(&self, out: &mut String, error: &Error, style: &Style, index: &Lines) {
+        let (line, col) = line_col(self.source, index, error.span.start);
+
+        let mut marks = vec![(error.span, None)];
+        marks.extend(
+            error
+                .labels
+                .iter()
+                .map(|label| (label.span, Some(label.message.as_str()))),
+        );
+        let mut lines: Vec<usize> = marks
+            .iter()
+            .map(|(span, _)| line_col(self.source, index, span.start).0)
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let gutter = lines.last().copied().unwrap_or(line).to_string().len();
+        let pad = " ".repeat(gutter);
+        let bar = style.paint(BLUE, "|");
+
+        let severity = error.severity();
+        let color = match severity {
+            Severity::Error => RED,
+            _ => YELLOW,
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            style.paint(color, &format!("{}[{}]", level(severity), error.code())),
+            style.paint(BOLD, &format!(": {error}")),
+        );
+        let _ = writeln!(
+            out,
+            "{pad}{} {}:{line}:{col}",
+            style.paint(BLUE, "-->"),
+            self.path
+        );
+        let _ = writeln!(out, "{pad} {bar}");
+
+        let mut previous = None;
+        for number in lines {
+            if previous.is_some_and(|previous| number > previous + 1) {
+                let _ = writeln!(out, "{}", style.paint(BLUE, "..."));
+            }
+            previous = Some(number);
+
+            let text = self.line(index, number).1.replace('\t', "    ");
+            let _ = writeln!(
+                out,
+                "{} {bar} {text}",
+                style.paint(BLUE, &format!("{number:>gutter$}"))
+            );
+
+            for (span, message) in &marks {
+                if line_col(self.source, index, span.start).0 != number {
+                    continue;
+                }
+                let (offset, len) = self.underline(index, *span);
+                let (mark, color) = match message {
+                    None => ("^", color),
+                    Some(_) => ("-", BLUE),
+                };
+                let mut underline = mark.repeat(len);
+                if let Some(message) = message {
+                    underline.push(' ');
+                    underline.push_str(message);
+                }
+                let _ = writeln!(
+                    out,
+                    "{pad} {bar} {}{}",
+                    " ".repeat(offset),
+                    style.paint(color, &underline)
+                );
+            }
+        }
+
+        if !error.notes.is_empty() || error.help.is_some() {
+            let _ = writeln!(out, "{pad} {bar}");
+        }
+        for note in &error.notes {
+            let _ = writeln!(out, "{pad} {} {note}", style.paint(BOLD, "= note:"));
+        }
+        if let Some(help) = &error.help {
+            let _ = writeln!(out, "{pad} {} {help}", style.paint(BOLD, "= help:"));
+        }
+    }
+
+    /// One line per error: `path:line:col: error[code]: message`, or
+    /// `warning[rule]` for lints that warn.
+    pub fn short(&self) -> String {
+        let lines = Lines::new(self.source);
+        let mut out = String::new();
+        for error in self.errors {
+            let (line, col) = line_col(self.source, &lines, error.span.start);
+            let _ = writeln!(
+                out,
+                "{}:{line}:{col}: {}[{}]: {error}",
+                self.path,
+                level(error.severity()),
+                error.code()
+            );
+        }
+        out
+    }
+
+    /// Errors as JSON objects, with 1-based lines and columns in characters.
+    pub fn json(&self) -> Vec<Value> {
+        let lines = Lines::new(self.source);
+        self.errors
+            .iter()
+            .map(|error| {
+                json!({
+                    "file": self.path,
+                    "severity": level(error.severity()),
+                    "code": error.code(),
+                    "message": error.to_string(),
+                    "span": self.json_span(&lines, error.span),
+                    "notes": error.notes,
+                    "help": error.help,
+                    "labels": error.labels.iter().map(|label| json!({
+                        "message": label.message,
+                        "span": self.json_span(&lines, label.span),
+                    })).collect::<Vec<_>>(),
+                })
+            })
+            .collect()
+    }
+
+    fn json_span(&self, lines: &Lines, span: Span) -> Value {
+        let (start_line, start_col) = line_col(self.source, lines, span.start);
+        let (end_line, end_col) = line_col(self.source, lines, span.end);
+        json!({
+            "offset": { "start": span.start, "end": span.end },
+            "start": { "line": start_line, "column": start_col },
+            "end": { "line": end_line, "column": end_col },
+        })
+    }
+
+    fn sarif_results(&self) -> impl Iterator<Item = Value> + '_ {
+        let lines = Lines::new(self.source);
+        self.errors.iter().map(move |error| {
+            let (start_line, start_col) = line_col(self.source, &lines, error.span.start);
+            let (end_line, end_col) = line_col(self.source, &lines, error.span.end);
+            let mut text = error.to_string();
+            for note in &error.notes {
+                text.push_str(&format!("\nnote: {note}"));
+            }
+            if let Some(help) = &error.help {
+                text.push_str(&format!("\nhelp: {help}"));
+            }
+
+            json!({
+                "ruleId": error.code(),
+                "level": level(error.severity()),
+                "message": { "text": text },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": self.path },
+                        "region": {
+                            "startLine": start_line,
+                            "startColumn": start_col,
+                            "endLine": end_line,
+                            "endColumn": end_col,
+                        },
+                    },
+                }],
+            })
+        })
+    }
+}
+
+/// A SARIF 2.1.0 log with the errors of every report.
+pub fn sarif(reports: &[Report]) -> Value {
+    let mut rules: Vec<(&str, String)> = reports
+        .iter()
+        .flat_map(|report| report.errors.iter())
+        .map(|error| (error.code(), error.url()))
+        .collect();
+    rules.sort_unstable();
+    rules.dedup();
+
+    json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": crate::error::ERRORS_URL,
+                    "rules": rules.iter().map(|(code, url)| json!({
+                        "id": code,
+                        "helpUri": url,
+                    })).collect::<Vec<_>>(),
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": reports.iter().flat_map(Report::sarif_results).collect::<Vec<_>>(),
+        }],
+    })
+}
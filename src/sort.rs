@@ -0,0 +1,278 @@
+//! Sorting the keys of tables, and the tables themselves, alphabetically.
+//!
+//! Keys are compared by their names, unquoted, one part of a dotted key at
+//! a time, so `"a".b` sorts like `a.b` and the dotted keys of a table stay
+//! together. Comments on the lines right above a key-value pair or a table
+//! move with it. Blank lines split the pairs of a table into groups sorted
+//! on their own, so the sections a table is laid out in stay where they
+//! are.
+
+use crate::{
+    config::TomlVersion,
+    parser::Parser,
+    span::{Lines, Span},
+    tree::{self, Child, Tree},
+    value,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Sort the tables too, after the keys of each one. Root key-value
+    /// pairs stay first, and the elements of an array of tables keep
+    /// their order, along with their subtables.
+    pub tables: bool,
+    /// The version of TOML the source is read as.
+    pub version: TomlVersion,
+}
+
+/// `source` with its keys sorted, or `None` if it has syntax errors.
+pub fn sort(source: &str, options: Options) -> Option<String> {
+    // The byte order mark would move with the first key, but belongs to
+    // the file
+    if let Some(rest) = source.strip_prefix('\u{feff}') {
+        return sort(rest, options).map(|sorted| format!("\u{feff}{sorted}"));
+    }
+    let (tree, errors) = Parser::new(source)
+        .with_version(options.version)
+        .parse()
+        .tree();
+    if !errors.is_empty() {
+        return None;
+    }
+    let groups = groups(source, &tree);
+    let mut sorted = replace(
+        source,
+        groups
+            .iter()
+            .map(|group| (group.span, group.sorted(source))),
+    );
+
+    if options.tables {
+        let (tree, _) = Parser::new(&sorted)
+            .with_version(options.version)
+            .parse()
+            .tree();
+        sorted = sort_tables(&sorted, &tree);
+    }
+    Some(sorted)
+}
+
+/// Key-value pairs of a table, one right after the other, sorted together.
+pub(crate) struct Group {
+    /// From the comments above the first pair to the end of the line of
+    /// the last one.
+    pub span: Span,
+    pub entries: Vec<Entry>,
+}
+
+pub(crate) struct Entry {
+    pub names: Vec<String>,
+    /// The key of the pair.
+    pub key: Span,
+    /// The lines of the pair and the comments right above it.
+    pub span: Span,
+}
+
+impl Group {
+    /// The text of the group with its pairs sorted.
+    pub(crate) fn sorted(&self, source: &str) -> String {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.names.cmp(&b.names));
+        let texts = entries
+            .iter()
+            .map(|entry| &source[entry.span.start..entry.span.end]);
+        join(
+            texts,
+            source[self.span.start..self.span.end].ends_with('\n'),
+        )
+    }
+}
+
+/// `texts` one after the other, each on lines of its own, ending with a
+/// newline if `newline` is set. Line endings added are `\r\n` if the texts
+/// use them.
+fn join<'s>(texts: impl Iterator<Item = &'s str>, newline: bool) -> String {
+    let texts = texts.collect::<Vec<_>>();
+    let ending = if texts.iter().any(|text| text.ends_with("\r\n")) {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut out = String::new();
+    for text in texts {
+        out.push_str(text);
+        if !text.ends_with('\n') {
+            out.push_str(ending);
+        }
+    }
+    if !newline {
+        let end = out.strip_suffix('\n').unwrap_or(&out);
+        out.truncate(end.strip_suffix('\r').unwrap_or(end).len());
+    }
+    out
+}
+
+/// `source` with the text of each span replaced, the spans in order.
+fn replace(source: &str, replacements: impl Iterator<Item = (Span, String)>) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut at = 0;
+    for (span, text) in replacements {
+        out.push_str(&source[at..span.start]);
+        out.push_str(&text);
+        at = span.end;
+    }
+    out.push_str(&source[at..]);
+    out
+}
+
+fn subtrees(tree: &Tree) -> impl Iterator<Item = &Tree> {
+    tree.children.iter().filter_map(|child| match child {
+        Child::Tree(tree) => Some(tree),
+        Child::Token(_) => None,
+    })
+}
+
+fn key_of(tree: &Tree) -> Option<&Tree> {
+    subtrees(tree).find(|tree| tree.kind == tree::Kind::Key)
+}
+
+fn is_header(tree: &Tree) -> bool {
+    matches!(tree.kind, tree::Kind::Table | tree::Kind::TableArray)
+}
+
+/// The first line of the comments right above `line`, down to `floor`, or
+/// `line` if there are none.
+fn comments_above(source: &str, lines: &Lines, line: usize, floor: usize) -> usize {
+    let mut top = line;
+    while top > floor {
+        let span = lines.span(source, top - 1);
+        if !source[span.start..span.end].trim_start().starts_with('#') {
+            break;
+        }
+        top -= 1;
+    }
+    top
+}
+
+/// The last line of `tree`.
+fn last_line(lines: &Lines, tree: &Tree) -> usize {
+    lines.line(tree.span.end.saturating_sub(1).max(tree.span.start))
+}
+
+/// The groups of key-value pairs of every table of `tree`, the syntax tree
+/// of `source`, in order.
+pub(crate) fn groups(source: &str, tree: &Tree) -> Vec<Group> {
+    let lines = Lines::new(source);
+    // The byte order mark isn't part of the first pair
+    let bom = if source.starts_with('\u{feff}') { 3 } else { 0 };
+    let mut groups = Vec::new();
+    let sections = core::iter::once(tree).chain(subtrees(tree).filter(|tree| is_header(tree)));
+    for section in sections {
+        // Comments above the first pair of a table can't go past its header
+        let mut floor = match key_of(section) {
+            Some(key) if is_header(section) => lines.line(key.span.end) + 1,
+            _ => 0,
+        };
+        let mut group: Option<Group> = None;
+        for pair in subtrees(section).filter(|tree| tree.kind == tree::Kind::KeyVal) {
+            let Some(key) = key_of(pair) else {
+                continue;
+            };
+            let first = lines.line(pair.span.start);
+            let last = last_line(&lines, pair);
+            let top = comments_above(source, &lines, first, floor);
+            floor = last + 1;
+
+            let entry = Entry {
+                names: value::key_names(source, key),
+                key: key.span,
+                span: Span::from(lines.start(top).max(bom)..lines.next(last)),
+            };
+            match &mut group {
+                // Anything in between, like a blank line, ends the group
+                Some(group) if group.span.end == entry.span.start => {
+                    group.span.end = entry.span.end;
+                    group.entries.push(entry);
+                }
+                _ => {
+                    groups.extend(group.take());
+                    group = Some(Group {
+                        span: entry.span,
+                        entries: vec![entry],
+                    });
+                }
+            }
+        }
+        groups.extend(group);
+    }
+    groups
+}
+
+/// A table with the tables that go with it: the subtables of an element of
+/// an array of tables belong to it, and can't be moved away from it.
+struct Unit {
+    path: Vec<String>,
+    start: usize,
+}
+
+fn sort_tables(source: &str, tree: &Tree) -> String {
+    let lines = Lines::new(source);
+    let mut units: Vec<Unit> = Vec::new();
+    // The array of tables whose last element is being read
+    let mut array: Option<Vec<String>> = None;
+    let mut floor = 0;
+    for child in subtrees(tree) {
+        if !is_header(child) {
+            floor = last_line(&lines, child) + 1;
+            continue;
+        }
+        let Some(key) = key_of(child) else {
+            continue;
+        };
+        let path = value::key_names(source, key);
+        let top = comments_above(source, &lines, lines.line(child.span.start), floor);
+        floor = last_line(&lines, child) + 1;
+
+        let in_element = array
+            .as_ref()
+            .is_some_and(|array| path.len() > array.len() && path.starts_with(array));
+        if in_element {
+            continue;
+        }
+        array = (child.kind == tree::Kind::TableArray).then(|| path.clone());
+        units.push(Unit {
+            path,
+            start: lines.start(top),
+        });
+    }
+    let Some(first) = units.first() else {
+        return source.to_string();
+    };
+
+    // Each unit keeps its content, the blank lines between units stay
+    // where they are
+    let ends = units.iter().skip(1).map(|unit| unit.start);
+    let texts = units
+        .iter()
+        .zip(ends.chain([source.len()]))
+        .map(|(unit, end)| {
+            let text = &source[unit.start..end];
+            let content = text.trim_end();
+            let content = text[content.len()..]
+                .find('\n')
+                .map_or(text.len(), |i| content.len() + i + 1);
+            (unit, &text[..content], &text[content..])
+        })
+        .collect::<Vec<_>>();
+    let mut sorted = texts.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.path.cmp(&b.0.path));
+
+    let mut out = source[..first.start].to_string();
+    for (i, ((_, content, _), (_, _, gap))) in sorted.iter().copied().zip(&texts).enumerate() {
+        let last = i + 1 == texts.len();
+        let newline = !last || source.ends_with('\n');
+        out.push_str(&join(core::iter::once(*content), newline));
+        out.push_str(gap);
+    }
+    out
+}
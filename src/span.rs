@@ -1,5 +1,8 @@
-use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::{Position, Range};
 
+use crate::encoding::PositionEncoding;
+
+/// Byte range into the source.
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub struct Span {
     pub start: usize,
@@ -33,15 +36,6 @@ impl core::fmt::Display for Location {
     }
 }
 
-impl Into<Position> for Location {
-    fn into(self) -> Position {
-        Position {
-            line: (self.line - 1) as u32,
-            character: (self.col - 1) as u32,
-        }
-    }
-}
-
 impl Span {
     pub fn reduce_to(&self, len: usize) -> Span {
         Span {
@@ -50,11 +44,19 @@ impl Span {
         }
     }
 
+    /// Moves the span `delta` bytes.
+    pub fn shift(&self, delta: isize) -> Span {
+        Span {
+            start: self.start.wrapping_add_signed(delta),
+            end: self.end.wrapping_add_signed(delta),
+        }
+    }
+
     pub fn end_location(&self, source: &str) -> Location {
         let mut line = 1;
         let mut col = 1;
-        for (i, c) in source.chars().enumerate() {
-            if i == self.end {
+        for (i, c) in source.char_indices() {
+            if i >= self.end {
                 return Location { line, col };
             }
             if c == '\n' {
@@ -71,8 +73,8 @@ impl Span {
     pub fn start_location(&self, source: &str) -> Location {
         let mut line = 1;
         let mut col = 1;
-        for (i, c) in source.chars().enumerate() {
-            if i == self.start {
+        for (i, c) in source.char_indices() {
+            if i >= self.start {
                 return Location { line, col };
             }
             if c == '\n' {
@@ -85,4 +87,244 @@ impl Span {
 
         Location { line, col }
     }
+
+    pub fn to_range(&self, source: &str, encoding: PositionEncoding) -> Range {
+        Range {
+            start: position_at(source, self.start, encoding),
+            end: position_at(source, self.end, encoding),
+        }
+    }
+}
+
+/// Converts a byte offset into `source` to an LSP position whose `character`
+/// is counted in `encoding` code units.
+pub fn position_at(source: &str, offset: usize, encoding: PositionEncoding) -> Position {
+    let mut line = 0;
+    let mut character = 0;
+    for (i, c) in source.char_indices() {
+        if i >= offset {
+            break;
+        }
+        if c == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += encoding.width(c);
+        }
+    }
+
+    Position {
+        line: line as u32,
+        character: character as u32,
+    }
+}
+
+/// Inverse of [`position_at`], returning a byte offset. Positions past the end
+/// of a line clamp to the line end, before a `\r\n` too, and positions past
+/// the last line clamp to the end of `source`.
+pub fn offset_at(source: &str, position: Position, encoding: PositionEncoding) -> usize {
+    let mut line = 0;
+    let mut character = 0;
+    for (i, c) in source.char_indices() {
+        let ending = c == '\n' || (c == '\r' && source[i + 1..].starts_with('\n'));
+        if line == position.line as usize && (character >= position.character as usize || ending) {
+            return i;
+        }
+        if c == '\n' {
+            line += 1;
+            character = 0;
+        } else if line == position.line as usize {
+            character += encoding.width(c);
+        }
+    }
+
+    source.len()
+}
+
+/// Where the line `offset` is on starts.
+pub(crate) fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// Where the line `offset` is on ends, before its line ending.
+pub(crate) fn line_end(source: &str, offset: usize) -> usize {
+    let end = next_line(source, offset);
+    without_ending(&source[..end]).len()
+}
+
+/// Where the line after the one `offset` is on starts, or the end of
+/// `source` for the last one.
+pub(crate) fn next_line(source: &str, offset: usize) -> usize {
+    source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i + 1)
+}
+
+/// `text` without the line ending it ends in, if any. A `\r` alone isn't
+/// one, as for the lexer.
+fn without_ending(text: &str) -> &str {
+    text.strip_suffix('\n')
+        .map_or(text, |text| text.strip_suffix('\r').unwrap_or(text))
+}
+
+/// Where each line of a source starts, to find the line of an offset
+/// without going through the source again. Lines are 0-based.
+#[derive(Debug, Clone)]
+pub(crate) struct Lines {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl Lines {
+    pub(crate) fn new(source: &str) -> Self {
+        let starts = core::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            starts,
+            len: source.len(),
+        }
+    }
+
+    /// Number of lines, counting the one after a final newline.
+    pub(crate) fn count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The line of byte `offset`.
+    pub(crate) fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    pub(crate) fn start(&self, line: usize) -> usize {
+        self.starts[line]
+    }
+
+    /// Where the line after `line` starts, or the end of the source for the
+    /// last one.
+    pub(crate) fn next(&self, line: usize) -> usize {
+        self.starts.get(line + 1).copied().unwrap_or(self.len)
+    }
+
+    /// Span of `line` without its line ending, in `source`.
+    pub(crate) fn span(&self, source: &str, line: usize) -> Span {
+        let start = self.start(line);
+        let text = without_ending(&source[start..self.next(line)]);
+        Span::from(start..start + text.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        ClientCapabilities, GeneralClientCapabilities, Position, PositionEncodingKind,
+    };
+
+    use super::{line_end, line_start, next_line, offset_at, position_at, Lines, Span};
+    use crate::encoding::PositionEncoding::{self, Utf16, Utf32, Utf8};
+
+    /// An emoji, a surrogate pair in UTF-16, and a CJK character, a single
+    /// code unit but three bytes.
+    const SOURCE: &str = "a = \"😀東\"\r\nb = 1\n";
+
+    fn position(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn positions_count_code_units() {
+        // After `東`, at byte 12
+        assert_eq!(position_at(SOURCE, 12, Utf8), position(0, 12));
+        assert_eq!(position_at(SOURCE, 12, Utf16), position(0, 8));
+        assert_eq!(position_at(SOURCE, 12, Utf32), position(0, 7));
+        assert_eq!(position_at(SOURCE, 15, Utf16), position(1, 0));
+        assert_eq!(position_at(SOURCE, SOURCE.len(), Utf16), position(2, 0));
+
+        // Every character boundary goes there and back, but for the one
+        // inside a line ending
+        for encoding in [Utf8, Utf16, Utf32] {
+            for (offset, _) in SOURCE.char_indices().filter(|&(i, _)| i != 14) {
+                let position = position_at(SOURCE, offset, encoding);
+                assert_eq!(
+                    offset_at(SOURCE, position, encoding),
+                    offset,
+                    "{encoding:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn positions_inside_characters_move_past_them() {
+        // A byte inside the emoji, and a position between its surrogates
+        assert_eq!(position_at(SOURCE, 6, Utf16), position(0, 7));
+        assert_eq!(offset_at(SOURCE, position(0, 6), Utf16), 9);
+        assert_eq!(offset_at(SOURCE, position(0, 6), Utf8), 9);
+    }
+
+    #[test]
+    fn positions_past_the_end_clamp() {
+        // To the end of the line, before its `\r\n`
+        assert_eq!(offset_at(SOURCE, position(0, 100), Utf16), 13);
+        assert_eq!(offset_at(SOURCE, position(1, 100), Utf16), 20);
+        assert_eq!(offset_at(SOURCE, position(9, 0), Utf16), SOURCE.len());
+    }
+
+    #[test]
+    fn negotiates_the_cheapest_encoding_offered() {
+        let offering = |kinds: Option<Vec<PositionEncodingKind>>| {
+            PositionEncoding::negotiate(&ClientCapabilities {
+                general: Some(GeneralClientCapabilities {
+                    position_encodings: kinds,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            PositionEncoding::negotiate(&ClientCapabilities::default()),
+            Utf16
+        );
+        assert_eq!(offering(None), Utf16);
+        assert_eq!(
+            offering(Some(vec![
+                PositionEncodingKind::UTF16,
+                PositionEncodingKind::UTF32
+            ])),
+            Utf32
+        );
+        assert_eq!(
+            offering(Some(vec![
+                PositionEncodingKind::UTF16,
+                PositionEncodingKind::UTF8
+            ])),
+            Utf8
+        );
+        assert_eq!(
+            offering(Some(vec![PositionEncodingKind::new("utf-7")])),
+            Utf16
+        );
+    }
+
+    #[test]
+    fn lines_end_before_a_newline_only() {
+        assert_eq!(line_start(SOURCE, 17), 15);
+        assert_eq!(line_end(SOURCE, 3), 13);
+        assert_eq!(next_line(SOURCE, 3), 15);
+        assert_eq!(next_line(SOURCE, 15), SOURCE.len());
+        // A lone `\r` doesn't end a line
+        assert_eq!(line_end("['\r", 3), 3);
+        assert_eq!(Lines::new("a\r").span("a\r", 0), Span::from(0..2));
+    }
+
+    #[test]
+    fn lines_leave_out_their_endings() {
+        let lines = Lines::new(SOURCE);
+        assert_eq!(lines.count(), 3);
+        assert_eq!(lines.line(13), 0);
+        assert_eq!(lines.line(15), 1);
+        assert_eq!(lines.span(SOURCE, 0), Span::from(0..13));
+        assert_eq!(lines.span(SOURCE, 1), Span::from(15..20));
+        assert_eq!(lines.next(2), SOURCE.len());
+    }
 }
@@ -65,6 +65,8 @@ pub enum Kind {
     // Errors
     NonClosingString,
     NonClosingMultilineString,
+    /// A bare key with letters or digits outside ASCII.
+    NonAsciiKey,
     Unknown,
     InvalidFloat,
 }
@@ -95,6 +97,7 @@ impl std::fmt::Debug for Kind {
             Self::Eof => "Eof",
             Self::NonClosingString => "NonClosingString",
             Self::NonClosingMultilineString => "NonClosingMultilineString",
+            Self::NonAsciiKey => "NonAsciiKey",
             Self::Unknown => "Unknown",
             Self::InvalidFloat => "InvalidFloat",
         };
@@ -102,6 +105,32 @@ impl std::fmt::Debug for Kind {
     }
 }
 
+/// How the token is named in messages meant for people.
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Key => "a key",
+            Self::StringOrKey => "a string",
+            Self::StringMultiline => "a multi-line string",
+            Self::Integer => "an integer",
+            Self::Float => "a float",
+            Self::Bool => "a boolean",
+            Self::Datetime => "a datetime",
+            Self::Newline => "a newline",
+            Self::Space | Self::Tab => "whitespace",
+            Self::Comment => "a comment",
+            Self::Eof => "the end of the file",
+            Self::NonClosingString | Self::NonClosingMultilineString => "an unclosed string",
+            Self::NonAsciiKey => "a key",
+            Self::Unknown => "unknown characters",
+            Self::InvalidFloat => "an invalid float",
+            // Punctuation reads the same either way
+            _ => return write!(f, "{self:?}"),
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl Kind {
     pub fn is_error(&self) -> bool {
         matches!(
@@ -110,6 +139,7 @@ impl Kind {
                 | Self::InvalidFloat
                 | Self::NonClosingString
                 | Self::NonClosingMultilineString
+                | Self::NonAsciiKey
         )
     }
 }
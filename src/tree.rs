@@ -1,25 +1,24 @@
-use crate::{span::Span, token};
+use crate::{comment::Comments, span::Span, token};
 
 pub struct Tree {
     pub kind: Kind,
     pub span: Span,
     pub children: Vec<Child>,
+    /// The comments of a key-value pair or a table, empty for other nodes.
+    pub comments: Comments,
 }
 
 impl core::fmt::Debug for Tree {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("Tree");
+        debug.field("kind", &self.kind).field("span", &self.span);
+        if !self.comments.is_empty() {
+            debug.field("comments", &self.comments);
+        }
         if !self.children.is_empty() {
-            f.debug_struct("Tree")
-                .field("kind", &self.kind)
-                .field("span", &self.span)
-                .field("children", &self.children)
-                .finish()
-        } else {
-            f.debug_struct("Tree")
-                .field("kind", &self.kind)
-                .field("span", &self.span)
-                .finish()
+            debug.field("children", &self.children);
         }
+        debug.finish()
     }
 }
 
@@ -29,6 +28,7 @@ impl Tree {
             kind: Kind::Unknown,
             span: Span::from(0..0),
             children: Vec::new(),
+            comments: Comments::default(),
         }
     }
 
@@ -50,6 +50,18 @@ impl Tree {
         self.span.start = core::cmp::min(self.span.start, span.start);
         self.span.end = core::cmp::max(self.span.end, span.end);
     }
+
+    /// Moves the tree and all of its descendants `delta` bytes.
+    pub fn shift(&mut self, delta: isize) {
+        self.span = self.span.shift(delta);
+        self.comments.shift(delta);
+        for child in &mut self.children {
+            match child {
+                Child::Tree(tree) => tree.shift(delta),
+                Child::Token(token) => token.span = token.span.shift(delta),
+            }
+        }
+    }
 }
 
 pub enum Child {
@@ -84,39 +96,12 @@ pub enum Kind {
     // Collections
     KeyValList,
 
-    // Errors
-    MissingKeyValue,
-    MissingKey,
-    MissingValue,
-    Extra(token::Kind),
-    Guard,
+    /// Tokens skipped to recover from a syntax error.
+    Error,
     Unknown,
-    Expected(token::Kind),
-    ExpectedAny(&'static [token::Kind]),
-    UnclosedString,
-    InvalidToken,
-    NewlinesForbiddenInContext,
 }
 
 impl Kind {
-    pub fn is_missing(&self) -> bool {
-        matches!(self, Self::MissingKey | Self::MissingValue)
-    }
-
-    pub fn is_error(&self) -> bool {
-        matches!(
-            self,
-            Self::MissingKey
-                | Self::MissingValue
-                | Self::Extra(_)
-                | Self::Guard
-                | Self::Unknown
-                | Self::Expected(_)
-                | Self::ExpectedAny(_)
-                | Self::UnclosedString
-        )
-    }
-
     pub fn is_value(&self) -> bool {
         matches!(
             self,
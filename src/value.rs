@@ -0,0 +1,59 @@
+//! The data a TOML document describes, as opposed to how it's written.
+//!
+//! [`lower`] builds it from a syntax tree, checking the rules the grammar
+//! alone can't, like keys being defined only once.
+
+use indexmap::IndexMap;
+
+use crate::{config::TomlVersion, error::Error, parser::Parser};
+
+#[cfg(feature = "serde")]
+mod de;
+mod json;
+mod lower;
+pub(crate) mod path;
+#[cfg(feature = "serde")]
+mod ser;
+pub(crate) mod write;
+
+#[cfg(feature = "serde")]
+pub use de::from_str;
+pub(crate) use lower::{key_names, unescape};
+pub use lower::{lower, lower_with};
+pub use path::{PathError, PathErrorKind};
+#[cfg(feature = "serde")]
+pub use ser::{to_string, to_string_with, Layout, SerializeError, Serializer};
+pub use write::{to_toml, to_toml_with_depth};
+
+/// Keys of a table in the order they were defined.
+pub type Table = IndexMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+/// Writes the value the way it's written after `=`, with tables inline.
+impl core::fmt::Display for Value {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(&write::inline(self))
+    }
+}
+
+/// Parses and lowers `source`, with the errors of both steps.
+pub fn parse(source: &str) -> (Table, Vec<Error>) {
+    parse_with(source, TomlVersion::default())
+}
+
+/// Like [`parse`], reading `source` as `version` of TOML.
+pub fn parse_with(source: &str, version: TomlVersion) -> (Table, Vec<Error>) {
+    let (tree, mut errors) = Parser::new(source).with_version(version).parse().tree();
+    let (table, lowering) = lower_with(source, &tree, version);
+    errors.extend(lowering);
+    (table, errors)
+}
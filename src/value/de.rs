@@ -0,0 +1,283 @@
+//! Deserializing Rust types from TOML documents with serde.
+
+use serde::de::{
+    self, value::StrDeserializer, DeserializeOwned, DeserializeSeed, Unexpected, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use super::{path::Segment, Value};
+use crate::{
+    document,
+    error::{Error, ErrorKind},
+    parser::Parser,
+    span::Span,
+};
+
+/// Deserializes a `T` from the TOML document `source`.
+///
+/// Fails with the errors of `source` if it has any, or else with an error
+/// on the first value that doesn't fit `T`, spanning where that value is
+/// written so it can be shown like any other [`Error`].
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T, Vec<Error>> {
+    let (tree, mut errors) = Parser::new(source).parse().tree();
+    let (table, lowering) = super::lower(source, &tree);
+    errors.extend(lowering);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let root = Value::Table(table);
+    T::deserialize(Deserializer {
+        value: &root,
+        path: Vec::new(),
+    })
+    .map_err(|error| {
+        let path = error.path.unwrap_or_default();
+        let span = document::locate(source, &tree, &path).unwrap_or(Span::from(0..0));
+        vec![Error::new(span, ErrorKind::Deserialize(error.message))]
+    })
+}
+
+/// An error raised while deserializing, with the path to the value it's
+/// about once that's known.
+#[derive(Debug)]
+struct DeError {
+    message: String,
+    path: Option<Vec<Segment>>,
+}
+
+impl DeError {
+    /// Places the error at `path`, unless it's already placed deeper.
+    fn at(mut self, path: &[Segment]) -> Self {
+        self.path.get_or_insert_with(|| path.to_vec());
+        self
+    }
+}
+
+impl core::fmt::Display for DeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for DeError {}
+
+impl de::Error for DeError {
+    fn custom<T: core::fmt::Display>(message: T) -> Self {
+        Self {
+            message: message.to_string(),
+            path: None,
+        }
+    }
+}
+
+fn unexpected(value: &Value) -> Unexpected<'_> {
+    match value {
+        Value::String(string) => Unexpected::Str(string),
+        Value::Integer(integer) => Unexpected::Signed(*integer),
+        Value::Float(float) => Unexpected::Float(*float),
+        Value::Boolean(boolean) => Unexpected::Bool(*boolean),
+        Value::Array(_) => Unexpected::Seq,
+        Value::Table(_) => Unexpected::Map,
+    }
+}
+
+fn child(path: &[Segment], segment: Segment) -> Vec<Segment> {
+    let mut path = path.to_vec();
+    path.push(segment);
+    path
+}
+
+/// Deserializes the value at `path` in the document.
+struct Deserializer<'a> {
+    value: &'a Value,
+    path: Vec<Segment>,
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        let result = match self.value {
+            Value::String(string) => visitor.visit_str(string),
+            Value::Integer(integer) => visitor.visit_i64(*integer),
+            Value::Float(float) => visitor.visit_f64(*float),
+            Value::Boolean(boolean) => visitor.visit_bool(*boolean),
+            Value::Array(items) => visitor.visit_seq(Seq {
+                items: items.iter().enumerate(),
+                path: &self.path,
+            }),
+            Value::Table(table) => visitor.visit_map(Map {
+                entries: table.iter(),
+                next: None,
+                path: &self.path,
+            }),
+        };
+        result.map_err(|error| error.at(&self.path))
+    }
+
+    // A key that's there always has a value, TOML has no null
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Unit variants are written as strings, others as a table with the
+    /// variant as its only key, like `{ Circle = { radius = 1.0 } }`.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        let result = match self.value {
+            Value::String(variant) => visitor.visit_enum(StrDeserializer::<DeError>::new(variant)),
+            Value::Table(table) if table.len() == 1 => {
+                let (variant, value) = table.iter().next().expect("the table has one entry");
+                visitor.visit_enum(Enum {
+                    variant,
+                    value,
+                    path: &self.path,
+                })
+            }
+            value => Err(de::Error::invalid_type(
+                unexpected(value),
+                &"a string or a table with a single key",
+            )),
+        };
+        result.map_err(|error| error.at(&self.path))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier
+    }
+}
+
+struct Seq<'a> {
+    items: core::iter::Enumerate<core::slice::Iter<'a, Value>>,
+    path: &'a [Segment],
+}
+
+impl<'de> de::SeqAccess<'de> for Seq<'_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeError> {
+        let Some((index, value)) = self.items.next() else {
+            return Ok(None);
+        };
+        seed.deserialize(Deserializer {
+            value,
+            path: child(self.path, Segment::Index(index)),
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct Map<'a> {
+    entries: indexmap::map::Iter<'a, String, Value>,
+    /// The entry whose key was just deserialized.
+    next: Option<(&'a String, &'a Value)>,
+    path: &'a [Segment],
+}
+
+impl<'de> de::MapAccess<'de> for Map<'_> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeError> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.next = Some((key, value));
+        seed.deserialize(StrDeserializer::<DeError>::new(key))
+            .map(Some)
+            .map_err(|error| error.at(&child(self.path, Segment::Key(key.clone()))))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeError> {
+        let (key, value) = self
+            .next
+            .take()
+            .expect("next_value_seed is called after next_key_seed");
+        seed.deserialize(Deserializer {
+            value,
+            path: child(self.path, Segment::Key(key.clone())),
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct Enum<'a> {
+    variant: &'a String,
+    value: &'a Value,
+    path: &'a [Segment],
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for Enum<'a> {
+    type Error = DeError;
+    type Variant = Deserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Deserializer<'a>), DeError> {
+        let variant = seed.deserialize(StrDeserializer::<DeError>::new(self.variant))?;
+        let value = Deserializer {
+            value: self.value,
+            path: child(self.path, Segment::Key(self.variant.clone())),
+        };
+        Ok((variant, value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'_> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> Result<(), DeError> {
+        Err(de::Error::invalid_type(
+            Unexpected::Map,
+            &"a unit variant written as a string",
+        ))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, DeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
@@ -0,0 +1,135 @@
+//! Conversions to and from JSON, both plain and in the "tagged" encoding of
+//! [toml-test](https://github.com/toml-lang/toml-test), where every scalar
+//! carries its TOML type: `{"type": "integer", "value": "42"}`.
+
+use serde_json::{json, Map, Number};
+
+use super::{write, Table, Value};
+
+impl Value {
+    /// The value as plain JSON. Fails on `nan` and infinite floats, which
+    /// JSON can't represent.
+    pub fn to_json(&self) -> Result<serde_json::Value, String> {
+        Ok(match self {
+            Value::String(string) => json!(string),
+            Value::Integer(integer) => json!(integer),
+            Value::Float(float) => Number::from_f64(*float)
+                .map(serde_json::Value::Number)
+                .ok_or_else(|| {
+                    format!(
+                        "`{}` can't be written as plain JSON, try --tagged",
+                        write::float(*float)
+                    )
+                })?,
+            Value::Boolean(boolean) => json!(boolean),
+            Value::Array(items) => serde_json::Value::Array(
+                items.iter().map(Value::to_json).collect::<Result<_, _>>()?,
+            ),
+            Value::Table(table) => serde_json::Value::Object(
+                table
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), value.to_json()?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+        })
+    }
+
+    /// The value in the toml-test encoding.
+    pub fn to_tagged_json(&self) -> serde_json::Value {
+        let tagged = |kind: &str, value: String| json!({ "type": kind, "value": value });
+        match self {
+            Value::String(string) => tagged("string", string.clone()),
+            Value::Integer(integer) => tagged("integer", integer.to_string()),
+            Value::Float(float) => tagged("float", write::float(*float)),
+            Value::Boolean(boolean) => tagged("bool", boolean.to_string()),
+            Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(Value::to_tagged_json).collect())
+            }
+            Value::Table(table) => serde_json::Value::Object(
+                table
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.to_tagged_json()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Reads plain JSON. Fails on `null`, which TOML has no equivalent for.
+    pub fn from_json(json: &serde_json::Value) -> Result<Value, String> {
+        Ok(match json {
+            serde_json::Value::Null => return Err("TOML has no `null`".to_string()),
+            serde_json::Value::Bool(boolean) => Value::Boolean(*boolean),
+            serde_json::Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+                (Some(integer), _) => Value::Integer(integer),
+                (None, Some(float)) if number.is_f64() => Value::Float(float),
+                _ => return Err(format!("{number} doesn't fit in a 64-bit integer")),
+            },
+            serde_json::Value::String(string) => Value::String(string.clone()),
+            serde_json::Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(Value::from_json)
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(object) => Value::Table(table(object, Value::from_json)?),
+        })
+    }
+
+    /// Reads JSON in the toml-test encoding.
+    pub fn from_tagged_json(json: &serde_json::Value) -> Result<Value, String> {
+        match json {
+            serde_json::Value::Array(items) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(Value::from_tagged_json)
+                    .collect::<Result<_, _>>()?,
+            )),
+            serde_json::Value::Object(object) => match tag(object) {
+                Some((kind, value)) => scalar(kind, value),
+                None => Ok(Value::Table(table(object, Value::from_tagged_json)?)),
+            },
+            other => Err(format!(
+                "expected a tagged value like {{\"type\": \"string\", \"value\": \"…\"}}, found `{other}`"
+            )),
+        }
+    }
+}
+
+fn table(
+    object: &Map<String, serde_json::Value>,
+    value: impl Fn(&serde_json::Value) -> Result<Value, String>,
+) -> Result<Table, String> {
+    object
+        .iter()
+        .map(|(key, json)| Ok((key.clone(), value(json)?)))
+        .collect()
+}
+
+/// The type and value of a tagged scalar, if `object` is one.
+fn tag(object: &Map<String, serde_json::Value>) -> Option<(&str, &str)> {
+    if object.len() != 2 {
+        return None;
+    }
+    match (object.get("type")?, object.get("value")?) {
+        (serde_json::Value::String(kind), serde_json::Value::String(value)) => Some((kind, value)),
+        _ => None,
+    }
+}
+
+fn scalar(kind: &str, value: &str) -> Result<Value, String> {
+    let invalid = || format!("invalid {kind} `{value}`");
+    match kind {
+        "string" => Ok(Value::String(value.to_string())),
+        "integer" => value.parse().map(Value::Integer).map_err(|_| invalid()),
+        "float" => value.parse().map(Value::Float).map_err(|_| invalid()),
+        "bool" => match value {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(invalid()),
+        },
+        "datetime" | "datetime-local" | "date-local" | "time-local" => {
+            Err(format!("{kind} values are not supported"))
+        }
+        _ => Err(format!("unknown type `{kind}`")),
+    }
+}
@@ -0,0 +1,545 @@
+use indexmap::IndexMap;
+
+use super::{write, Table, Value};
+use crate::{
+    config::TomlVersion,
+    error::{Error, ErrorKind},
+    span::Span,
+    token::{self, Token},
+    tree::{self, Child, Tree},
+};
+
+/// How a table came to be, which decides what may add keys to it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Defined {
+    /// As the parent of a header, like `a` in `[a.b]`. A header of its own
+    /// may still define it.
+    Implicit,
+    Header,
+    /// By a dotted key, like `a` in `a.b = 1`. Only more dotted keys may
+    /// add to it.
+    Dotted,
+    Inline,
+}
+
+struct Builder {
+    defined: Defined,
+    /// Where the table was defined, to point at when it's defined again.
+    span: Span,
+    entries: IndexMap<String, Node>,
+}
+
+enum Node {
+    Value {
+        value: Value,
+        span: Span,
+    },
+    Table(Builder),
+    /// An array of tables, which only `[[header]]`s can add to.
+    Array {
+        tables: Vec<Builder>,
+        span: Span,
+    },
+}
+
+impl Node {
+    fn span(&self) -> Span {
+        match self {
+            Node::Value { span, .. } | Node::Array { span, .. } => *span,
+            Node::Table(table) => table.span,
+        }
+    }
+}
+
+impl Builder {
+    fn new(defined: Defined, span: Span) -> Self {
+        Self {
+            defined,
+            span,
+            entries: IndexMap::new(),
+        }
+    }
+
+    fn finish(self) -> Table {
+        self.entries
+            .into_iter()
+            .map(|(key, node)| {
+                let value = match node {
+                    Node::Value { value, .. } => value,
+                    Node::Table(table) => Value::Table(table.finish()),
+                    Node::Array { tables, .. } => Value::Array(
+                        tables
+                            .into_iter()
+                            .map(|table| Value::Table(table.finish()))
+                            .collect(),
+                    ),
+                };
+                (key, value)
+            })
+            .collect()
+    }
+}
+
+/// The table key-value pairs under the header at `path` go into.
+fn table_at<'t>(mut table: &'t mut Builder, path: &[String]) -> Option<&'t mut Builder> {
+    for key in path {
+        table = match table.entries.get_mut(key)? {
+            Node::Table(table) => table,
+            Node::Array { tables, .. } => tables.last_mut()?,
+            Node::Value { .. } => return None,
+        };
+    }
+    Some(table)
+}
+
+/// `keys` the way they are written in a dotted key.
+fn dotted(keys: &[(String, Span)]) -> String {
+    keys.iter()
+        .map(|(key, _)| write::key(key))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+struct Lowering<'src> {
+    source: &'src str,
+    version: TomlVersion,
+    errors: Vec<Error>,
+}
+
+/// Builds the data described by `tree`, the syntax tree of `source`.
+///
+/// Keys defined more than once and tables extended in ways the spec
+/// forbids are reported as errors and left out of the result, as are values
+/// that can't be represented, like integers that don't fit in 64 bits.
+pub fn lower(source: &str, tree: &Tree) -> (Table, Vec<Error>) {
+    lower_with(source, tree, TomlVersion::default())
+}
+
+/// Like [`lower`], reading strings as `version` of TOML.
+pub fn lower_with(source: &str, tree: &Tree, version: TomlVersion) -> (Table, Vec<Error>) {
+    let mut lowering = Lowering {
+        source,
+        version,
+        errors: Vec::new(),
+    };
+    let mut root = Builder::new(Defined::Header, tree.span);
+
+    for child in &tree.children {
+        let Child::Tree(node) = child else {
+            continue;
+        };
+        match node.kind {
+            tree::Kind::KeyVal => lowering.key_val(&mut root, node),
+            tree::Kind::Table | tree::Kind::TableArray => {
+                let Some(path) = lowering.header(&mut root, node) else {
+                    continue;
+                };
+                for child in &node.children {
+                    if let Child::Tree(key_val) = child
+                        && key_val.kind == tree::Kind::KeyVal
+                        && let Some(table) = table_at(&mut root, &path)
+                    {
+                        lowering.key_val(table, key_val);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (root.finish(), lowering.errors)
+}
+
+/// The parts of the dotted key `key`, quoted parts unescaped.
+pub(crate) fn key_names(source: &str, key: &Tree) -> Vec<String> {
+    // The errors are dropped, so the escapes of every version are fine
+    let mut lowering = Lowering {
+        source,
+        version: TomlVersion::V1_1,
+        errors: Vec::new(),
+    };
+    lowering
+        .keys(key)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// The text of the string token at `span`, escapes and delimiters removed.
+pub(crate) fn unescape(source: &str, span: Span) -> String {
+    // The errors are dropped, so the escapes of every version are fine
+    let mut lowering = Lowering {
+        source,
+        version: TomlVersion::V1_1,
+        errors: Vec::new(),
+    };
+    lowering.string(span)
+}
+
+impl<'src> Lowering<'src> {
+    fn text(&self, span: Span) -> &'src str {
+        self.source.get(span.start..span.end).unwrap_or_default()
+    }
+
+    fn duplicate(&mut self, span: Span, key: String, first: Span) {
+        self.errors.push(
+            Error::new(span, ErrorKind::DuplicateKey(key)).with_label(first, "first defined here"),
+        );
+    }
+
+    fn not_a_table(&mut self, span: Span, key: String, first: Span) {
+        self.errors.push(
+            Error::new(span, ErrorKind::NotATable(key))
+                .with_label(first, "defined here as a value"),
+        );
+    }
+
+    fn keys(&mut self, key: &Tree) -> Vec<(String, Span)> {
+        key.children
+            .iter()
+            .filter_map(|child| match child {
+                Child::Token(token) => Some(*token),
+                Child::Tree(_) => None,
+            })
+            .map(|token| {
+                let name = match token.kind {
+                    token::Kind::StringOrKey => self.string(token.span),
+                    _ => self.text(token.span).to_string(),
+                };
+                (name, token.span)
+            })
+            .collect()
+    }
+
+    /// Defines the table of a `[header]` or `[[header]]`, returning the path
+    /// to it, or `None` if it can't be defined.
+    fn header(&mut self, root: &mut Builder, header: &Tree) -> Option<Vec<String>> {
+        let array = header.kind == tree::Kind::TableArray;
+        let key = header.children.iter().find_map(|child| match child {
+            Child::Tree(key) if key.kind == tree::Kind::Key => Some(key),
+            _ => None,
+        })?;
+        let keys = self.keys(key);
+        if keys.is_empty() {
+            return None;
+        }
+
+        let mut table = root;
+        for (i, (name, span)) in keys.iter().enumerate() {
+            let last = i + 1 == keys.len();
+
+            match table.entries.get_mut(name) {
+                None => {
+                    let node = match (last, array) {
+                        (true, true) => Node::Array {
+                            tables: Vec::new(),
+                            span: key.span,
+                        },
+                        (true, false) => Node::Table(Builder::new(Defined::Header, key.span)),
+                        (false, _) => Node::Table(Builder::new(Defined::Implicit, *span)),
+                    };
+                    table.entries.insert(name.clone(), node);
+                }
+                Some(Node::Table(existing))
+                    if last && !array && existing.defined == Defined::Implicit =>
+                {
+                    existing.defined = Defined::Header;
+                    existing.span = key.span;
+                }
+                Some(Node::Array { .. }) if last && array => {}
+                Some(Node::Value { span: first, .. }) => {
+                    let first = *first;
+                    self.not_a_table(*span, dotted(&keys[..=i]), first);
+                    return None;
+                }
+                Some(existing) if last => {
+                    let first = existing.span();
+                    self.duplicate(key.span, dotted(&keys), first);
+                    return None;
+                }
+                Some(_) => {}
+            }
+
+            table = match table.entries.get_mut(name)? {
+                Node::Table(table) => table,
+                Node::Array { tables, .. } if last => {
+                    tables.push(Builder::new(Defined::Header, key.span));
+                    break;
+                }
+                Node::Array { tables, .. } => tables.last_mut()?,
+                Node::Value { .. } => return None,
+            };
+        }
+
+        Some(keys.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn key_val(&mut self, table: &mut Builder, key_val: &Tree) {
+        let mut children = key_val.children.iter();
+        let Some(Child::Tree(key)) = children.next() else {
+            return;
+        };
+        let keys = self.keys(key);
+        let Some(value) = children.next().and_then(|value| self.value(value)) else {
+            return;
+        };
+        let Some(((name, span), parents)) = keys.split_last() else {
+            return;
+        };
+
+        let mut table = table;
+        for (i, (parent, parent_span)) in parents.iter().enumerate() {
+            if !table.entries.contains_key(parent) {
+                let node = Node::Table(Builder::new(Defined::Dotted, *parent_span));
+                table.entries.insert(parent.clone(), node);
+            }
+
+            match table.entries.get(parent) {
+                Some(Node::Table(next)) if next.defined == Defined::Dotted => {}
+                Some(Node::Value { span: first, .. }) => {
+                    self.not_a_table(*parent_span, dotted(&keys[..=i]), *first);
+                    return;
+                }
+                Some(existing) => {
+                    self.duplicate(*parent_span, dotted(&keys[..=i]), existing.span());
+                    return;
+                }
+                None => return,
+            }
+            let Some(Node::Table(next)) = table.entries.get_mut(parent) else {
+                return;
+            };
+            table = next;
+        }
+
+        if let Some(existing) = table.entries.get(name) {
+            let first = existing.span();
+            self.duplicate(*span, dotted(&keys), first);
+            return;
+        }
+        table
+            .entries
+            .insert(name.clone(), Node::Value { value, span: *span });
+    }
+
+    fn value(&mut self, value: &Child) -> Option<Value> {
+        match value {
+            Child::Token(token) => self.scalar(*token),
+            Child::Tree(tree) if tree.kind == tree::Kind::Array => Some(Value::Array(
+                tree.children
+                    .iter()
+                    .filter_map(|child| self.value(child))
+                    .collect(),
+            )),
+            Child::Tree(tree) if tree.kind == tree::Kind::InlineTable => {
+                let mut table = Builder::new(Defined::Inline, tree.span);
+                for child in &tree.children {
+                    if let Child::Tree(key_val) = child
+                        && key_val.kind == tree::Kind::KeyVal
+                    {
+                        self.key_val(&mut table, key_val);
+                    }
+                }
+                Some(Value::Table(table.finish()))
+            }
+            Child::Tree(_) => None,
+        }
+    }
+
+    fn scalar(&mut self, token: Token) -> Option<Value> {
+        let text = self.text(token.span);
+        let parsed = match token.kind {
+            token::Kind::StringOrKey | token::Kind::StringMultiline => {
+                return Some(Value::String(self.string(token.span)));
+            }
+            token::Kind::Bool => return Some(Value::Boolean(text == "true")),
+            token::Kind::Integer => parse_integer(text).map(Value::Integer),
+            token::Kind::Float => parse_float(text).map(Value::Float),
+            _ => return None,
+        };
+
+        match parsed {
+            Ok(value) => Some(value),
+            Err(note) => {
+                self.errors
+                    .push(Error::new(token.span, ErrorKind::InvalidNumber).with_note(note));
+                None
+            }
+        }
+    }
+
+    /// Contents of the string token at `span`, escapes resolved.
+    fn string(&mut self, span: Span) -> String {
+        let text = self.text(span);
+        let delimiter = [r#"""""#, "'''", "\"", "'"]
+            .into_iter()
+            .find(|delimiter| text.starts_with(delimiter))
+            .unwrap_or_default();
+        let multiline = delimiter.len() == 3;
+
+        let mut start = span.start + delimiter.len();
+        let mut body = &text[delimiter.len()..];
+        body = body.strip_suffix(delimiter).unwrap_or(body);
+        if multiline {
+            // A newline right after the opening delimiter is trimmed
+            if let Some(newline) = ["\n", "\r\n"]
+                .into_iter()
+                .find(|newline| body.starts_with(newline))
+            {
+                start += newline.len();
+                body = &body[newline.len()..];
+            }
+        }
+
+        match delimiter {
+            "'''" => body.replace("\r\n", "\n"),
+            "'" => body.to_string(),
+            _ => self.unescape(body, start, multiline),
+        }
+    }
+
+    /// Resolves the escapes of a basic string whose body starts at byte
+    /// `offset` of the source.
+    fn unescape(&mut self, body: &str, offset: usize, multiline: bool) -> String {
+        let mut out = String::with_capacity(body.len());
+        let mut chars = body.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\r' if multiline && chars.peek().is_some_and(|(_, next)| *next == '\n') => {
+                    continue;
+                }
+                '\\' => {}
+                c => {
+                    out.push(c);
+                    continue;
+                }
+            }
+
+            let escaped = match chars.next() {
+                Some((_, 'b')) => Some('\u{8}'),
+                Some((_, 't')) => Some('\t'),
+                Some((_, 'n')) => Some('\n'),
+                Some((_, 'f')) => Some('\u{c}'),
+                Some((_, 'r')) => Some('\r'),
+                Some((_, '"')) => Some('"'),
+                Some((_, '\\')) => Some('\\'),
+                Some((_, 'e')) if self.version >= TomlVersion::V1_1 => Some('\u{1b}'),
+                Some((_, code @ ('u' | 'U' | 'x')))
+                    if code != 'x' || self.version >= TomlVersion::V1_1 =>
+                {
+                    let len = match code {
+                        'x' => 2,
+                        'u' => 4,
+                        _ => 8,
+                    };
+                    let start = i + 2;
+                    let digits = body.get(start..start + len).unwrap_or_default();
+                    let escaped = (digits.len() == len
+                        && digits.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .then(|| u32::from_str_radix(digits, 16).ok())
+                    .flatten()
+                    .and_then(char::from_u32);
+                    if escaped.is_some() {
+                        for _ in 0..len {
+                            chars.next();
+                        }
+                    }
+                    escaped
+                }
+                Some((_, ' ' | '\t' | '\r' | '\n')) if multiline => {
+                    // A line ending backslash trims all whitespace up to
+                    // the next non-whitespace character
+                    let rest = &body[i + 1..];
+                    let line = rest.split('\n').next().unwrap_or_default();
+                    if line.len() < rest.len() && line.trim().is_empty() {
+                        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                        continue;
+                    }
+                    None
+                }
+                _ => None,
+            };
+
+            match escaped {
+                Some(escaped) => out.push(escaped),
+                None => {
+                    let end = chars.peek().map_or(body.len(), |(end, _)| *end);
+                    let span = Span::from(offset + i..offset + end);
+                    let escape = self.text(span).to_string();
+                    let mut error = Error::new(span, ErrorKind::InvalidEscape(escape.clone()))
+                        .with_help(
+                            "use `\\\\` for a backslash, or a literal string in single quotes",
+                        );
+                    if self.version < TomlVersion::V1_1 && matches!(&escape[1..], "e" | "x") {
+                        error = error.with_note(format!("`{escape}` is only valid in TOML 1.1"));
+                    }
+                    self.errors.push(error);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// Whether every `_` in `number` sits between two characters that satisfy
+/// `digit`.
+fn underscores_between(number: &str, digit: impl Fn(u8) -> bool) -> bool {
+    let bytes = number.as_bytes();
+    bytes.iter().enumerate().all(|(i, b)| {
+        *b != b'_'
+            || (i > 0
+                && bytes.get(i - 1).copied().is_some_and(&digit)
+                && bytes.get(i + 1).copied().is_some_and(&digit))
+    })
+}
+
+fn has_leading_zero(digits: &str) -> bool {
+    let digits = digits.trim_start_matches(['+', '-']);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+fn parse_integer(text: &str) -> Result<i64, &'static str> {
+    let (radix, digits) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        _ => (10, text),
+    };
+
+    if !underscores_between(digits, |b| b.is_ascii_alphanumeric()) {
+        return Err("each `_` must be between two digits");
+    }
+    let digits = digits.replace('_', "");
+    if radix == 10 && has_leading_zero(&digits) {
+        return Err("integers can't have leading zeros");
+    }
+    if radix != 10 && digits.starts_with(['+', '-']) {
+        return Err("hexadecimal, octal and binary integers can't have a sign");
+    }
+
+    i64::from_str_radix(&digits, radix).map_err(|_| "integers must fit in 64 bits")
+}
+
+fn parse_float(text: &str) -> Result<f64, &'static str> {
+    match text.trim_start_matches(['+', '-']) {
+        "inf" if text.starts_with('-') => return Ok(f64::NEG_INFINITY),
+        "inf" => return Ok(f64::INFINITY),
+        "nan" => return Ok(f64::NAN),
+        _ => {}
+    }
+
+    if !underscores_between(text, |b| b.is_ascii_digit()) {
+        return Err("each `_` must be between two digits");
+    }
+    let number = text.replace('_', "");
+    let integer = number.split(['.', 'e', 'E']).next().unwrap_or_default();
+    if has_leading_zero(integer) {
+        return Err("floats can't have leading zeros");
+    }
+
+    number.parse().map_err(|_| {
+        "a float is an integer part followed by a fractional part, an exponent or both"
+    })
+}
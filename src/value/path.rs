@@ -0,0 +1,215 @@
+//! Key paths like `servers.alpha.ip`, `"quoted.key".name` or
+//! `products[1].name`, to look values up with [`Value::get`].
+
+use super::{write, Value};
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathError {
+    pub kind: PathErrorKind,
+    /// The part of the path the error is about.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathErrorKind {
+    /// The path itself is malformed.
+    Invalid(&'static str),
+    /// `parent` is the path up to the failing segment, empty at the top
+    /// level.
+    MissingKey {
+        key: String,
+        parent: String,
+    },
+    OutOfBounds {
+        index: usize,
+        len: usize,
+        parent: String,
+    },
+    NotATable {
+        parent: String,
+    },
+    NotAnArray {
+        parent: String,
+    },
+    /// The path is fine but the operation on it isn't possible.
+    Unsupported(&'static str),
+}
+
+/// How errors name the value at `parent`.
+fn describe(parent: &str) -> String {
+    if parent.is_empty() {
+        "the top level".to_string()
+    } else {
+        format!("`{parent}`")
+    }
+}
+
+impl core::fmt::Display for PathErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Invalid(message) => write!(f, "invalid path, {message}"),
+            Self::MissingKey { key, parent } => {
+                write!(f, "no key `{}` in {}", write::key(key), describe(parent))
+            }
+            Self::OutOfBounds { index, len, parent } => write!(
+                f,
+                "index {index} is out of bounds, {} has {len} item{}",
+                describe(parent),
+                if *len == 1 { "" } else { "s" }
+            ),
+            Self::NotATable { parent } => write!(f, "{} is not a table", describe(parent)),
+            Self::NotAnArray { parent } => write!(f, "{} is not an array", describe(parent)),
+            Self::Unsupported(message) => f.write_str(message),
+        }
+    }
+}
+
+impl core::fmt::Display for PathError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for PathError {}
+
+fn invalid(start: usize, end: usize, message: &'static str) -> PathError {
+    PathError {
+        kind: PathErrorKind::Invalid(message),
+        span: Span::from(start..end),
+    }
+}
+
+fn is_bare(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+/// Splits `path` into keys and indices, each with where it is in `path`.
+pub(crate) fn parse(path: &str) -> Result<Vec<(Segment, Span)>, PathError> {
+    let bytes = path.as_bytes();
+    let mut segments = Vec::new();
+    let mut i = 0;
+    let skip_spaces = |i: &mut usize| {
+        while bytes.get(*i).is_some_and(|b| *b == b' ' || *b == b'\t') {
+            *i += 1;
+        }
+    };
+
+    skip_spaces(&mut i);
+    if i == bytes.len() {
+        return Ok(segments);
+    }
+
+    loop {
+        skip_spaces(&mut i);
+        let start = i;
+        let key = match bytes.get(i) {
+            Some(quote @ (b'"' | b'\'')) => {
+                let end = path[i + 1..]
+                    .find(char::from(*quote))
+                    .map(|end| i + 1 + end)
+                    .ok_or_else(|| invalid(start, path.len(), "the quoted key is not closed"))?;
+                i = end + 1;
+                path[start + 1..end].to_string()
+            }
+            Some(b) if is_bare(*b) => {
+                while bytes.get(i).copied().is_some_and(is_bare) {
+                    i += 1;
+                }
+                path[start..i].to_string()
+            }
+            // An index right away looks into the value itself
+            Some(b'[') if start == 0 => String::new(),
+            _ => {
+                return Err(invalid(
+                    start,
+                    (start + 1).min(path.len()),
+                    "expected a key",
+                ))
+            }
+        };
+        if i > start {
+            segments.push((Segment::Key(key), Span::from(start..i)));
+        }
+
+        while bytes.get(i) == Some(&b'[') {
+            let start = i;
+            let digits = path[i + 1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(path.len(), |end| i + 1 + end);
+            if bytes.get(digits) != Some(&b']') || digits == i + 1 {
+                return Err(invalid(
+                    start,
+                    digits.min(path.len()),
+                    "expected an index like `[0]`",
+                ));
+            }
+            let index = path[i + 1..digits]
+                .parse()
+                .map_err(|_| invalid(start, digits + 1, "the index is too large"))?;
+            i = digits + 1;
+            segments.push((Segment::Index(index), Span::from(start..i)));
+        }
+
+        skip_spaces(&mut i);
+        match bytes.get(i) {
+            None => return Ok(segments),
+            Some(b'.') => i += 1,
+            Some(_) => return Err(invalid(i, i + 1, "expected `.` or `[` between keys")),
+        }
+    }
+}
+
+impl Value {
+    /// The value at `path`, a dotted key where keys may be quoted, like
+    /// `"quoted.key".name`, and followed by array indices, like
+    /// `products[1].name`. Quoted keys are taken as written, without escapes.
+    /// An empty path is the value itself.
+    pub fn get(&self, path: &str) -> Result<&Value, PathError> {
+        let mut value = self;
+        let mut parent = Span::from(0..0);
+        for (segment, span) in parse(path)? {
+            let parent_text = path[parent.start..parent.end].trim().to_string();
+            let error = |kind| PathError { kind, span };
+            value = match (segment, value) {
+                (Segment::Key(key), Value::Table(table)) => match table.get(&key) {
+                    Some(value) => value,
+                    None => {
+                        return Err(error(PathErrorKind::MissingKey {
+                            key,
+                            parent: parent_text,
+                        }))
+                    }
+                },
+                (Segment::Index(index), Value::Array(items)) => match items.get(index) {
+                    Some(value) => value,
+                    None => {
+                        return Err(error(PathErrorKind::OutOfBounds {
+                            index,
+                            len: items.len(),
+                            parent: parent_text,
+                        }))
+                    }
+                },
+                (Segment::Key(_), _) => {
+                    return Err(error(PathErrorKind::NotATable {
+                        parent: parent_text,
+                    }))
+                }
+                (Segment::Index(_), _) => {
+                    return Err(error(PathErrorKind::NotAnArray {
+                        parent: parent_text,
+                    }))
+                }
+            };
+            parent.end = span.end;
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,407 @@
+//! Serializing Rust types to TOML documents with serde.
+
+use serde::ser::{self, Serialize};
+
+use super::{write, Table, Value};
+
+/// How [`to_string_with`] lays out tables.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Layout {
+    /// Tables nested deeper than this many levels are written inline, like
+    /// `point = { x = 1, y = 2 }`; top-level tables are one level deep.
+    /// `None` puts every table under a header.
+    pub inline_depth: Option<usize>,
+}
+
+/// Serializes `value`, which must serialize to a table, as a TOML document.
+///
+/// Nested structs and maps become `[tables]`, sequences of them
+/// `[[arrays of tables]]`, and each table's plain values come before its
+/// sub-tables. `None` fields are left out, since TOML has no null.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, SerializeError> {
+    to_string_with(value, Layout::default())
+}
+
+/// Serializes `value` as [`to_string`] does, laying tables out as `layout`
+/// says.
+pub fn to_string_with<T: Serialize + ?Sized>(
+    value: &T,
+    layout: Layout,
+) -> Result<String, SerializeError> {
+    match value.serialize(Serializer)? {
+        Value::Table(table) => Ok(write::to_toml_with_depth(
+            &table,
+            layout.inline_depth.unwrap_or(usize::MAX),
+        )),
+        other => Err(SerializeError(format!(
+            "a document must be a table, found {}",
+            kind(&other)
+        ))),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializeError(pub String);
+
+impl core::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+impl ser::Error for SerializeError {
+    fn custom<T: core::fmt::Display>(message: T) -> Self {
+        Self(message.to_string())
+    }
+}
+
+/// Raised for `None`, which struct fields and map entries drop and
+/// everything else rejects.
+const NONE: &str = "TOML has no null, `None` can only be left out of a table";
+
+fn is_none(error: &SerializeError) -> bool {
+    error.0 == NONE
+}
+
+/// Serializes Rust values into [`Value`]s.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    type SerializeSeq = Array;
+    type SerializeTuple = Array;
+    type SerializeTupleStruct = Array;
+    type SerializeTupleVariant = Variant<Array>;
+    type SerializeMap = Map;
+    type SerializeStruct = Map;
+    type SerializeStructVariant = Variant<Map>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerializeError> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerializeError> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, SerializeError> {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| SerializeError(format!("{v} doesn't fit in a 64-bit integer")))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerializeError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerializeError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerializeError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerializeError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerializeError> {
+        Ok(Value::Array(
+            v.iter()
+                .map(|byte| Value::Integer((*byte).into()))
+                .collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value, SerializeError> {
+        Err(SerializeError(NONE.to_string()))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerializeError> {
+        Err(SerializeError("TOML has no unit value".to_string()))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Value, SerializeError> {
+        Err(SerializeError(format!(
+            "TOML has no unit value, for `{name}`"
+        )))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, SerializeError> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, SerializeError> {
+        Ok(self::variant(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Array, SerializeError> {
+        Ok(Array(Vec::with_capacity(len.unwrap_or_default())))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Array, SerializeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Array, SerializeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<Array>, SerializeError> {
+        Ok(Variant {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Map, SerializeError> {
+        Ok(Map {
+            table: Table::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Map, SerializeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<Map>, SerializeError> {
+        Ok(Variant {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+pub struct Array(Vec<Value>);
+
+impl ser::SerializeSeq for Array {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        self.0.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        Ok(Value::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for Array {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Array {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct Map {
+    table: Table,
+    /// The key of the entry being serialized.
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for Map {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerializeError> {
+        self.key = Some(match key.serialize(Serializer)? {
+            Value::String(key) => key,
+            Value::Integer(key) => key.to_string(),
+            Value::Boolean(key) => key.to_string(),
+            other => {
+                return Err(SerializeError(format!(
+                    "keys must be strings, found {}",
+                    kind(&other)
+                )))
+            }
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value is called after serialize_key");
+        match value.serialize(Serializer) {
+            Ok(value) => {
+                self.table.insert(key, value);
+                Ok(())
+            }
+            Err(error) if is_none(&error) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        Ok(Value::Table(self.table))
+    }
+}
+
+impl ser::SerializeStruct for Map {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        ser::SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// A tuple or struct variant, written as a table with the variant as its
+/// only key.
+pub struct Variant<T> {
+    variant: &'static str,
+    inner: T,
+}
+
+/// `value` as the value of the variant `variant`.
+fn variant(variant: &str, value: Value) -> Value {
+    let mut table = Table::new();
+    table.insert(variant.to_string(), value);
+    Value::Table(table)
+}
+
+impl ser::SerializeTupleVariant for Variant<Array> {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        Ok(variant(self.variant, Value::Array(self.inner.0)))
+    }
+}
+
+impl ser::SerializeStructVariant for Variant<Map> {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value, SerializeError> {
+        Ok(variant(self.variant, Value::Table(self.inner.table)))
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::Write as _;
+
+use super::{Table, Value};
+
+/// Writes `table` as a TOML document.
+///
+/// Each table's plain values come first, then its sub-tables under
+/// `[headers]` and its arrays of tables under `[[headers]]`. Tables holding
+/// only other tables get no header of their own.
+pub fn to_toml(table: &Table) -> String {
+    to_toml_with_depth(table, usize::MAX)
+}
+
+/// Writes `table` as [`to_toml`] does, except that tables nested more than
+/// `max_depth` levels deep are written inline, like `point = { x = 1 }`.
+/// Top-level tables are one level deep.
+pub fn to_toml_with_depth(table: &Table, max_depth: usize) -> String {
+    let mut out = String::new();
+    write_table(&mut out, &mut Vec::new(), table, max_depth);
+    out
+}
+
+fn is_array_of_tables(value: &Value) -> bool {
+    matches!(value, Value::Array(items)
+        if !items.is_empty() && items.iter().all(|item| matches!(item, Value::Table(_))))
+}
+
+/// Whether `value` goes under a header rather than after a key.
+fn is_section(value: &Value) -> bool {
+    matches!(value, Value::Table(_)) || is_array_of_tables(value)
+}
+
+fn header(out: &mut String, path: &[String], open: &str, close: &str) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    let _ = writeln!(out, "{open}{}{close}", path.join("."));
+}
+
+fn write_table(out: &mut String, path: &mut Vec<String>, table: &Table, max_depth: usize) {
+    // Whether values of this table, or of its children, get headers
+    let sections = path.len() < max_depth;
+    let children = path.len() + 1 < max_depth;
+
+    for (name, value) in table {
+        if !(sections && is_section(value)) {
+            let _ = writeln!(out, "{} = {}", key(name), inline(value));
+        }
+    }
+    if !sections {
+        return;
+    }
+
+    for (name, value) in table {
+        path.push(key(name));
+        match value {
+            Value::Table(child) => {
+                if child.is_empty() || child.values().any(|value| !(children && is_section(value)))
+                {
+                    header(out, path, "[", "]");
+                }
+                write_table(out, path, child, max_depth);
+            }
+            Value::Array(items) if is_array_of_tables(value) => {
+                for item in items {
+                    if let Value::Table(child) = item {
+                        header(out, path, "[[", "]]");
+                        write_table(out, path, child, max_depth);
+                    }
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+/// `value` written where a value goes, after `=` or inside an array.
+pub fn inline(value: &Value) -> String {
+    match value {
+        Value::String(string) => quote(string),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Float(float) => self::float(*float),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::Array(items) => {
+            let items = items.iter().map(inline).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Table(table) if table.is_empty() => "{}".to_string(),
+        Value::Table(table) => {
+            let entries = table
+                .iter()
+                .map(|(name, value)| format!("{} = {}", key(name), inline(value)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", entries.join(", "))
+        }
+    }
+}
+
+/// `name` as a key, quoted unless it's a valid bare key.
+pub fn key(name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if bare {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+/// `text` as a basic string.
+pub fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn float(float: f64) -> String {
+    if float.is_nan() {
+        "nan".to_string()
+    } else if float.is_infinite() {
+        if float > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        let mut text = float.to_string();
+        if !text.contains(['.', 'e', 'E']) {
+            text.push_str(".0");
+        }
+        text
+    }
+}
@@ -0,0 +1,201 @@
+//! Finding the TOML files of a workspace.
+//!
+//! Files ignored by git are left out, following the `.gitignore` files of
+//! the workspace, as are files matching the exclude patterns the server is
+//! configured with.
+
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+/// `*` and `?` stop at `/`, like they do in `.gitignore` files.
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// The files of the workspace at `root` to check.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    /// Patterns of paths relative to `root` to leave out, like `vendor/**`.
+    /// A directory that matches is left out whole.
+    pub exclude: Vec<Pattern>,
+}
+
+impl Workspace {
+    pub fn new(root: PathBuf, exclude: Vec<Pattern>) -> Self {
+        Self { root, exclude }
+    }
+
+    /// The `*.toml` files of the workspace, in order. Symlinked
+    /// directories aren't followed, so a link back up the tree can't loop.
+    pub fn scan(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let mut ignores = Vec::new();
+        self.walk(&self.root, &mut ignores, &mut files);
+        files
+    }
+
+    fn walk(&self, dir: &Path, ignores: &mut Vec<Ignore>, files: &mut Vec<PathBuf>) {
+        let pushed = match Ignore::read(dir) {
+            Some(ignore) => {
+                ignores.push(ignore);
+                true
+            }
+            None => false,
+        };
+
+        let mut entries = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Some((entry.path(), entry.file_type().ok()?.is_dir())))
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        for (path, is_dir) in entries {
+            if !self.admits(ignores, &path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                self.walk(&path, ignores, files);
+            } else if path.extension().is_some_and(|ext| ext == "toml") {
+                files.push(path);
+            }
+        }
+
+        if pushed {
+            ignores.pop();
+        }
+    }
+
+    /// Whether `path` is a `*.toml` file of the workspace, as [`scan`]
+    /// would find it.
+    ///
+    /// [`scan`]: Self::scan
+    pub fn contains(&self, path: &Path) -> bool {
+        if path.extension().is_none_or(|ext| ext != "toml") {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+
+        // Every directory on the way must be admitted too, with the
+        // `.gitignore` files found so far
+        let mut ignores = Vec::new();
+        let mut current = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            ignores.extend(Ignore::read(&current));
+            current.push(component);
+            let is_dir = components.peek().is_some();
+            if !self.admits(&ignores, &current, is_dir) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn admits(&self, ignores: &[Ignore], path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return false;
+        }
+        let relative = relative(&self.root, path);
+        if self
+            .exclude
+            .iter()
+            .any(|pattern| pattern.matches_with(&relative, OPTIONS))
+        {
+            return false;
+        }
+        !is_ignored(ignores, path, is_dir)
+    }
+}
+
+/// `path` relative to `base`, with `/` between components.
+fn relative(base: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Whether the last rule matching `path` ignores it.
+fn is_ignored(ignores: &[Ignore], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for ignore in ignores {
+        let relative = relative(&ignore.base, path);
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        for rule in &ignore.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let subject = if rule.anchored { &relative } else { name };
+            if rule.pattern.matches_with(subject, OPTIONS) {
+                ignored = !rule.negated;
+            }
+        }
+    }
+    ignored
+}
+
+/// The rules of a `.gitignore` file, which apply to the directory it's in.
+#[derive(Debug)]
+struct Ignore {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    /// A `!pattern`, which includes again what an earlier rule ignored.
+    negated: bool,
+    /// A `pattern/`, which only matches directories.
+    dir_only: bool,
+    /// A pattern with a `/` before its end, which matches the path relative
+    /// to the `.gitignore` rather than just the name.
+    anchored: bool,
+}
+
+impl Ignore {
+    fn read(dir: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(dir.join(".gitignore")).ok()?;
+        let rules = text.lines().filter_map(Rule::parse).collect();
+        Some(Self {
+            base: dir.to_path_buf(),
+            rules,
+        })
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let pattern = Pattern::new(line).ok()?;
+        Some(Self {
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+}
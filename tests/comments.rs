@@ -0,0 +1,180 @@
+use aoxo_toml::{
+    comment::Placement,
+    document::Document,
+    parser::Parser,
+    tree::{Child, Tree},
+    value::{self, PathErrorKind},
+};
+
+const SOURCE: &str = r#"# The name
+# of the package
+name = "demo" # not final
+
+version = 1
+# About the servers
+[servers] # all of them
+# The first
+alpha = { ip = "10.0.0.1" } # main
+ports = [
+  8000, # web
+]
+
+[[bin]] # first
+path = "a"
+"#;
+
+/// The comments of each commented node of `tree`, in order.
+fn comments(source: &str, tree: &Tree, out: &mut Vec<(Option<String>, Option<String>)>) {
+    for child in &tree.children {
+        if let Child::Tree(tree) = child {
+            if !tree.comments.is_empty() {
+                out.push((
+                    tree.comments.leading_text(source),
+                    tree.comments.trailing_text(source).map(str::to_string),
+                ));
+            }
+            comments(source, tree, out);
+        }
+    }
+}
+
+fn attached(source: &str) -> Vec<(Option<String>, Option<String>)> {
+    let (tree, _) = Parser::new(source).parse().tree();
+    let mut out = Vec::new();
+    comments(source, &tree, &mut out);
+    out
+}
+
+fn text(text: &str) -> Option<String> {
+    Some(text.to_string())
+}
+
+#[test]
+fn attaches_comments_to_pairs_and_tables() {
+    assert_eq!(
+        attached(SOURCE),
+        [
+            (text("The name\nof the package"), text("not final")),
+            (text("About the servers"), text("all of them")),
+            (text("The first"), text("main")),
+            (None, text("first")),
+        ]
+    );
+
+    // A blank line or a pair in between ends the leading comments, and a
+    // comment on the line of another node isn't one of them
+    assert_eq!(
+        attached("# Alone\n\na = 1 # of a\nb = 2\n"),
+        [(None, text("of a"))]
+    );
+    // Lines of a multiline string aren't comments
+    assert_eq!(
+        attached("a = '''\n# text'''\nb = 1\n"),
+        Vec::<(Option<String>, Option<String>)>::new()
+    );
+}
+
+#[test]
+fn reads_comments_by_path() {
+    let document = Document::new(SOURCE.to_string());
+    let comment = |path, placement| document.comment(path, placement).unwrap();
+    assert_eq!(
+        comment("name", Placement::Leading),
+        text("The name\nof the package")
+    );
+    assert_eq!(comment("servers", Placement::Trailing), text("all of them"));
+    assert_eq!(
+        comment("servers.alpha", Placement::Leading),
+        text("The first")
+    );
+    assert_eq!(comment("bin[0]", Placement::Trailing), text("first"));
+    assert_eq!(comment("version", Placement::Leading), None);
+
+    let error = document.comment("servers.alpha.ip", Placement::Leading);
+    assert_eq!(error, Ok(None));
+    let error = document
+        .comment("servers.ports[0]", Placement::Trailing)
+        .unwrap_err();
+    assert!(
+        matches!(error.kind, PathErrorKind::Unsupported(_)),
+        "{error:?}"
+    );
+    let error = document.comment("missing", Placement::Leading).unwrap_err();
+    assert!(
+        matches!(error.kind, PathErrorKind::MissingKey { .. }),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn changes_comments_by_path() {
+    let set = |source: &str, path, placement, comment| {
+        let mut document = Document::new(source.to_string());
+        document.set_comment(path, placement, comment).unwrap();
+        let (_, errors) = value::parse(&document.text);
+        assert!(errors.is_empty(), "{errors:?} in\n{}", document.text);
+        assert_eq!(
+            document.comment(path, placement).unwrap().as_deref(),
+            comment
+        );
+        document.text
+    };
+
+    let source = "[t]\n  # Old\n  # lines\n  a = 1 # old\nb = [1,\n  2]\n";
+    assert_eq!(
+        set(source, "t.a", Placement::Leading, Some("New\n\nlines")),
+        "[t]\n  # New\n  #\n  # lines\n  a = 1 # old\nb = [1,\n  2]\n"
+    );
+    assert_eq!(
+        set(source, "t.a", Placement::Leading, None),
+        "[t]\n  a = 1 # old\nb = [1,\n  2]\n"
+    );
+    assert_eq!(
+        set(source, "t.a", Placement::Trailing, Some("new")),
+        "[t]\n  # Old\n  # lines\n  a = 1 # new\nb = [1,\n  2]\n"
+    );
+    assert_eq!(
+        set(source, "t.a", Placement::Trailing, None),
+        "[t]\n  # Old\n  # lines\n  a = 1\nb = [1,\n  2]\n"
+    );
+    assert_eq!(
+        set(source, "t.b", Placement::Trailing, Some("last")),
+        "[t]\n  # Old\n  # lines\n  a = 1 # old\nb = [1,\n  2] # last\n"
+    );
+    assert_eq!(
+        set(source, "t", Placement::Trailing, Some("table")),
+        "[t] # table\n  # Old\n  # lines\n  a = 1 # old\nb = [1,\n  2]\n"
+    );
+    assert_eq!(
+        set("a = 1\r\n", "a", Placement::Leading, Some("doc")),
+        "# doc\r\na = 1\r\n"
+    );
+
+    let mut document = Document::new("a = { b = 1, c = 2 }\n".to_string());
+    let error = document
+        .set_comment("a.b", Placement::Trailing, Some("no"))
+        .unwrap_err();
+    assert!(
+        matches!(error.kind, PathErrorKind::Unsupported(_)),
+        "{error:?}"
+    );
+    let error = document
+        .set_comment("a", Placement::Trailing, Some("two\nlines"))
+        .unwrap_err();
+    assert!(
+        matches!(error.kind, PathErrorKind::Unsupported(_)),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn headers_cut_short_by_a_carriage_return() {
+    // The key of the header ends after the `\r` its line ends before
+    for source in ["['\r", "[a'\r", "[[\"\r\n", "['\r# c\n"] {
+        let _ = attached(source);
+    }
+    assert_eq!(
+        attached("[a] # c\r\n"),
+        [(None, text("c"))]
+    );
+}
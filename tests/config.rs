@@ -0,0 +1,127 @@
+use aoxo_toml::{
+    config::{self, Config, ProjectError, Severity, TomlVersion},
+    error::ErrorKind,
+    value::Value,
+};
+
+fn table(source: &str) -> aoxo_toml::value::Table {
+    let (table, errors) = aoxo_toml::value::parse(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    table
+}
+
+#[test]
+fn layers_override_only_what_they_set() {
+    let mut config = Config::default();
+    let client = "toml-version = \"1.1\"\ninlay-hints = false\n[lint]\nunsorted-keys = \"warn\"\nempty-table = \"error\"\n";
+    assert_eq!(config.apply(&table(client)), []);
+    let project = "[format]\nindent-width = 2\n[lint]\nunsorted-keys = \"allow\"\n";
+    assert_eq!(config.apply(&table(project)), []);
+
+    assert_eq!(config.format.indent_width, 2);
+    assert_eq!(config.toml_version, TomlVersion::V1_1);
+    assert!(!config.inlay_hints);
+    assert_eq!(config.lints["unsorted-keys"], Severity::Allow);
+    assert_eq!(config.lints["empty-table"], Severity::Error);
+
+    // Settings from JSON, like the client sends, read the same way
+    let json = serde_json::json!({ "schemas": { "Cargo.toml": "https://example.com/cargo.json" } });
+    let Ok(Value::Table(settings)) = Value::from_json(&json) else {
+        panic!("not a table");
+    };
+    assert_eq!(config.apply(&settings), []);
+    assert!(config.schemas[0].pattern.matches("Cargo.toml"));
+}
+
+#[test]
+fn invalid_settings_span_where_they_are_written() {
+    let source =
+        "colour = true\n[format]\nindent-width = \"four\"\n[lint]\nunsorted-keys = \"loud\"\n";
+    let (_, errors) = config::read(source);
+    let found = errors
+        .iter()
+        .map(|error| {
+            assert!(matches!(error.kind, ErrorKind::InvalidSetting(_)));
+            (&source[error.span.start..error.span.end], error.to_string())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            ("true", "invalid setting `colour`: no such setting".to_string()),
+            (
+                "\"four\"",
+                "invalid setting `format.indent-width`: expected an integer from 0 to 16"
+                    .to_string()
+            ),
+            (
+                "\"loud\"",
+                "invalid setting `lint.unsorted-keys`: expected `\"allow\"`, `\"warn\"` or `\"error\"`, found `loud`"
+                    .to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn reserved_settings_get_a_warning() {
+    let source =
+        "inlay-hints = false\n[schemas]\n\"Cargo.toml\" = \"https://example.com/cargo.json\"\n";
+    let (_, errors) = config::read(source);
+    let found = errors
+        .iter()
+        .map(|error| (error.code(), error.severity(), error.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            (
+                "E0021",
+                Severity::Warn,
+                "setting `inlay-hints` has no effect yet".to_string()
+            ),
+            (
+                "E0021",
+                Severity::Warn,
+                "setting `schemas` has no effect yet".to_string()
+            ),
+        ]
+    );
+    assert_eq!(&source[errors[0].span.start..errors[0].span.end], "false");
+
+    // The project file can still be used
+    let root = std::env::temp_dir().join(format!("aoxo-toml-reserved-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join(config::FILE_NAME), source).unwrap();
+    let config = Config::default().for_file(&root.join("a.toml")).unwrap();
+    assert!(!config.inlay_hints);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn files_use_the_closest_project_file() {
+    let root = std::env::temp_dir().join(format!("aoxo-toml-config-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("a/b")).unwrap();
+    std::fs::write(root.join(config::FILE_NAME), "[format]\nindent-width = 2\n").unwrap();
+    std::fs::write(
+        root.join("a/b").join(config::FILE_NAME),
+        "toml-version = \"1.1\"\n",
+    )
+    .unwrap();
+
+    let client = Config::default();
+    let config = client.for_file(&root.join("a/b/c.toml")).unwrap();
+    // Project files don't stack, the closest one is used on its own
+    assert_eq!(config.toml_version, TomlVersion::V1_1);
+    assert_eq!(config.format.indent_width, 4);
+    let config = client.for_file(&root.join("a/c.toml")).unwrap();
+    assert_eq!(config.format.indent_width, 2);
+
+    std::fs::write(root.join(config::FILE_NAME), "[format\n").unwrap();
+    let error = client.for_file(&root.join("a/c.toml")).unwrap_err();
+    assert!(matches!(error, ProjectError::Invalid { .. }), "{error}");
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,63 @@
+use aoxo_toml::{
+    config::TomlVersion,
+    document::Document,
+    span::Span,
+    value::{self, Value},
+};
+
+/// The codes of the errors of `source` read as `version`.
+fn codes(source: &str, version: TomlVersion) -> Vec<&'static str> {
+    let (_, errors) = value::parse_with(source, version);
+    errors.iter().map(|error| error.code()).collect()
+}
+
+#[test]
+fn inline_tables_span_lines_in_1_1() {
+    let source = "point = {\n  x = 1,\n\n  y = 2, # last\n}\n";
+    // One error for the table, not one for each line it spans
+    assert_eq!(codes(source, TomlVersion::V1_0), ["E0008"]);
+
+    let (table, errors) = value::parse_with(source, TomlVersion::V1_1);
+    assert!(errors.is_empty(), "{errors:?}");
+    let Some(Value::Table(point)) = table.get("point") else {
+        panic!("{table:?}");
+    };
+    assert_eq!(point.keys().collect::<Vec<_>>(), ["x", "y"]);
+
+    // A trailing comma still needs a pair before it
+    assert_eq!(codes("t = {,}\n", TomlVersion::V1_1), ["E0007"]);
+    assert_eq!(codes("t = { a = 1,, }\n", TomlVersion::V1_1), ["E0007"]);
+}
+
+#[test]
+fn escapes_of_1_1() {
+    let source = "s = \"\\e[0m \\xE9\"\n";
+    let (table, errors) = value::parse_with(source, TomlVersion::V1_1);
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(table["s"], Value::String("\u{1b}[0m é".to_string()));
+
+    let (_, errors) = value::parse_with(source, TomlVersion::V1_0);
+    let notes = errors
+        .iter()
+        .map(|error| (error.code(), error.notes[0].as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        notes,
+        [
+            ("E0015", "`\\e` is only valid in TOML 1.1"),
+            ("E0015", "`\\x` is only valid in TOML 1.1"),
+        ]
+    );
+    assert_eq!(codes("s = \"\\xG1\"\n", TomlVersion::V1_1), ["E0015"]);
+}
+
+#[test]
+fn documents_keep_their_version_across_edits() {
+    let mut document = Document::with_version("a = { b = 1 }\n".to_string(), TomlVersion::V1_1);
+    document.edit(Span::from(5..5), "\n");
+    assert!(document.errors.is_empty(), "{:?}", document.errors);
+
+    let mut document = Document::new("a = { b = 1 }\n".to_string());
+    document.edit(Span::from(5..5), "\n");
+    assert_eq!(document.errors.len(), 1);
+}
@@ -0,0 +1,204 @@
+use aoxo_toml::{
+    document::Document,
+    value::{self, PathErrorKind, Value},
+};
+
+const SOURCE: &str = r#"# Settings
+title = "demo"  # shown in the header
+version = 1
+
+[servers.alpha]
+ip = "10.0.0.1" # main
+ports = [ 8000, 8001 ]
+opts = { fast = true, level = 2 }
+
+[[products]]
+name = "Hammer"
+
+[[products]]
+name = "Nail"
+
+[fruit]
+apple.color = "red"
+"#;
+
+fn set(path: &str, value: Value) -> String {
+    let mut document = Document::new(SOURCE.to_string());
+    document.set(path, &value).unwrap();
+    check(document)
+}
+
+fn unset(path: &str) -> String {
+    let mut document = Document::new(SOURCE.to_string());
+    document.unset(path).unwrap();
+    check(document)
+}
+
+/// The text of `document`, which must parse without errors.
+fn check(document: Document) -> String {
+    let (_, errors) = value::parse(&document.text);
+    assert!(errors.is_empty(), "{errors:?} in\n{}", document.text);
+    document.text
+}
+
+#[test]
+fn replaces_values_in_place() {
+    assert_eq!(
+        set("title", Value::String("new".into())),
+        SOURCE.replace(r#""demo""#, r#""new""#)
+    );
+    assert_eq!(
+        set("servers.alpha.ports[1]", Value::Integer(9)),
+        SOURCE.replace("8001", "9")
+    );
+    assert_eq!(
+        set("servers.alpha.opts.level", Value::Integer(3)),
+        SOURCE.replace("level = 2", "level = 3")
+    );
+}
+
+#[test]
+fn inserts_keys_after_their_siblings() {
+    assert_eq!(
+        set("debug", Value::Boolean(true)),
+        SOURCE.replace("version = 1\n", "version = 1\ndebug = true\n")
+    );
+    assert_eq!(
+        set("products[1].price", Value::Integer(2)),
+        SOURCE.replace("\"Nail\"\n", "\"Nail\"\nprice = 2\n")
+    );
+    assert_eq!(
+        set("fruit.apple.shape", Value::String("round".into())),
+        SOURCE.replace("\"red\"\n", "\"red\"\napple.shape = \"round\"\n")
+    );
+    assert_eq!(
+        set("servers.alpha.opts.new", Value::Integer(3)),
+        SOURCE.replace("level = 2 }", "level = 2, new = 3 }")
+    );
+    assert_eq!(
+        set("servers.alpha.ports[2]", Value::Integer(8002)),
+        SOURCE.replace("8001 ]", "8001, 8002 ]")
+    );
+}
+
+#[test]
+fn creates_tables_next_to_their_relatives() {
+    assert_eq!(
+        set("servers.beta.ip", Value::String("10.0.0.2".into())),
+        SOURCE.replace(
+            "level = 2 }\n",
+            "level = 2 }\n\n[servers.beta]\nip = \"10.0.0.2\"\n"
+        )
+    );
+    assert_eq!(
+        set("products[2].name", Value::String("Saw".into())),
+        SOURCE.replace("\"Nail\"\n", "\"Nail\"\n\n[[products]]\nname = \"Saw\"\n")
+    );
+    assert_eq!(
+        set("owner.name", Value::String("Tom".into())),
+        format!("{SOURCE}\n[owner]\nname = \"Tom\"\n")
+    );
+}
+
+#[test]
+fn removes_whole_lines() {
+    assert_eq!(
+        unset("title"),
+        SOURCE.replace("# Settings\ntitle = \"demo\"  # shown in the header\n", "")
+    );
+    assert_eq!(
+        unset("servers.alpha.ports[0]"),
+        SOURCE.replace("[ 8000, 8001 ]", "[ 8001 ]")
+    );
+    assert_eq!(
+        unset("servers.alpha.opts.level"),
+        SOURCE.replace("true, level = 2 }", "true }")
+    );
+    assert_eq!(
+        unset("products[0]"),
+        SOURCE.replace("name = \"Hammer\"\n\n[[products]]\n", "")
+    );
+    assert_eq!(
+        unset("fruit"),
+        SOURCE.replace("\n[fruit]\napple.color = \"red\"\n", "")
+    );
+}
+
+#[test]
+fn replaces_tables_with_values() {
+    assert_eq!(
+        set("products", Value::Array(Vec::new())),
+        SOURCE
+            .replace("version = 1\n", "version = 1\nproducts = []\n")
+            .replace(
+                "[[products]]\nname = \"Hammer\"\n\n[[products]]\nname = \"Nail\"\n\n",
+                ""
+            )
+    );
+}
+
+#[test]
+fn reports_paths_that_cannot_be_edited() {
+    let mut document = Document::new(SOURCE.to_string());
+    let error = document.set("title.x", &Value::Integer(1)).unwrap_err();
+    assert!(matches!(error.kind, PathErrorKind::NotATable { .. }));
+    let error = document.unset("servers.beta").unwrap_err();
+    assert!(matches!(error.kind, PathErrorKind::MissingKey { .. }));
+    let error = document
+        .set("servers.alpha.ports[5]", &Value::Integer(1))
+        .unwrap_err();
+    assert!(matches!(error.kind, PathErrorKind::OutOfBounds { .. }));
+    assert_eq!(document.text, SOURCE);
+}
+
+/// `document` after `edit`, which must succeed.
+fn edited(source: &str, edit: impl FnOnce(&mut Document)) -> String {
+    let mut document = Document::new(source.to_string());
+    edit(&mut document);
+    check(document)
+}
+
+#[test]
+fn removes_tables_up_to_their_last_sub_table() {
+    assert_eq!(
+        edited("[t]\na = 1\n\n[t.u]\nk = 1\n", |document| {
+            document.unset("t").unwrap()
+        }),
+        ""
+    );
+    assert_eq!(
+        edited("x = 1\n\n[t]\na = 1\n\n[t.u]\nk = 1\n", |document| {
+            document.unset("t").unwrap()
+        }),
+        "x = 1\n"
+    );
+    assert_eq!(
+        edited("[t]\n", |document| {
+            document.set("t.u.k", &Value::Integer(7)).unwrap();
+            document.set("t", &Value::Integer(7)).unwrap();
+        }),
+        "t = 7\n"
+    );
+
+    // The byte order mark isn't part of the first line
+    assert_eq!(
+        edited("\u{feff}[t]\nb = 1\n", |document| {
+            document.unset("t").unwrap()
+        }),
+        "\u{feff}"
+    );
+    assert_eq!(
+        edited("\u{feff}a = 1\nb = 2\n", |document| {
+            document.unset("a").unwrap()
+        }),
+        "\u{feff}b = 2\n"
+    );
+}
+
+#[test]
+fn new_keys_in_inline_tables_take_no_index() {
+    let mut document = Document::new("inline = {}\n".to_string());
+    let error = document.set("inline.y[0]", &Value::Integer(7)).unwrap_err();
+    assert!(matches!(error.kind, PathErrorKind::MissingKey { .. }));
+    assert_eq!(document.text, "inline = {}\n");
+}
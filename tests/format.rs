@@ -0,0 +1,74 @@
+use aoxo_toml::{
+    format::{format, format_with, Style},
+    lexer::Lexer,
+    token::Kind,
+};
+use proptest::prelude::*;
+
+const LINES: &[&str] = &[
+    "[a]\n",
+    "  [ a . b ]  # header\n",
+    "[[c]]\n",
+    "key=1\n",
+    "\"quoted key\" = 'value'   \n",
+    "list = [ 1,2 ,\n  3, ]\n",
+    "inline = {x=1,y=[true]}\n",
+    "text = \"\"\"\nmulti  \n\"\"\"\n",
+    "# comment\n",
+    "\n",
+    "\t\n",
+    "junk }\n",
+    "broken = \n",
+    "name = \"Ünï 🚀\"\n",
+];
+
+/// Every token but whitespace, as it appears in the source.
+fn content(source: &str) -> Vec<String> {
+    Lexer::tokens(source)
+        .filter(|token| !matches!(token.kind, Kind::Space | Kind::Newline))
+        .map(|token| {
+            source[token.span.start..token.span.end]
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn lays_out_a_document() {
+    let source = "  # top\n\n\n\na . b={x=1,y=[ 1,2 ,3 ] , z={}}   # trailing   \n\
+                  arr = [\n  1,\n      2, # two\n]\n# about t\n[ t . u ]\nk=true\n[[ c ]]\n\n\n";
+    let expected = "# top\n\na.b = { x = 1, y = [1, 2, 3], z = {} } # trailing\n\
+                    arr = [\n    1,\n    2, # two\n]\n\n# about t\n[t.u]\nk = true\n\n[[c]]\n";
+    assert_eq!(format(source), expected);
+}
+
+#[test]
+fn indents_by_the_style_width() {
+    let style = Style { indent_width: 2 };
+    assert_eq!(
+        format_with("a = [\n1,\n[\n2,\n],\n]", style),
+        "a = [\n  1,\n  [\n    2,\n  ],\n]\n"
+    );
+}
+
+#[test]
+fn keeps_crlf_line_endings() {
+    assert_eq!(format("a=1\r\n[t]\r\nb=2"), "a = 1\r\n\r\n[t]\r\nb = 2\r\n");
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn formatting_is_idempotent(lines in prop::collection::vec(prop::sample::select(LINES), 0..20)) {
+        let once = format(&lines.concat());
+        prop_assert_eq!(format(&once), once);
+    }
+
+    #[test]
+    fn formatting_keeps_content(lines in prop::collection::vec(prop::sample::select(LINES), 0..20)) {
+        let source = lines.concat();
+        prop_assert_eq!(content(&format(&source)), content(&source));
+    }
+}
@@ -0,0 +1,76 @@
+use aoxo_toml::{document::Document, span::Span};
+use proptest::prelude::*;
+
+const LINES: &[&str] = &[
+    "[a]\n",
+    "[a.b]\n",
+    "[[c]]\n",
+    "key = 1\n",
+    "\"quoted key\" = 'value'\n",
+    "list = [1, 2,\n  3]\n",
+    "inline = { x = 1, y = [true] }\n",
+    "text = \"\"\"\nmulti\n\"\"\"\n",
+    "# comment\n",
+    "other = 2 # note\n",
+    "\n",
+    "junk }\n",
+    "broken = \n",
+    "name = \"Ünï 🚀\"\n",
+];
+
+const SNIPPETS: &[&str] = &[
+    "",
+    "\n",
+    "[",
+    "]",
+    "[[",
+    "=",
+    " = 2",
+    "x",
+    "\"",
+    "'''",
+    "{",
+    "}",
+    ",",
+    "#",
+    "é",
+    "[t]\nk = 1\n",
+];
+
+fn snapshot(document: &Document) -> (String, Vec<String>) {
+    let mut errors: Vec<_> = document
+        .errors
+        .iter()
+        .map(|error| format!("{:?} {:?}", error.span, error.kind))
+        .collect();
+    errors.sort();
+    (format!("{:?}", document.tree), errors)
+}
+
+fn boundary(text: &str, at: usize) -> usize {
+    (at.min(text.len())..=text.len())
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap()
+}
+
+proptest! {
+    #[test]
+    fn edits_match_full_reparse(
+        lines in prop::collection::vec(prop::sample::select(LINES), 0..12),
+        edits in prop::collection::vec(
+            (any::<prop::sample::Index>(), 0..6usize, prop::sample::select(SNIPPETS)),
+            1..6,
+        ),
+    ) {
+        let mut document = Document::new(lines.concat());
+
+        for (at, len, snippet) in edits {
+            let start = boundary(&document.text, at.index(document.text.len() + 1));
+            let end = boundary(&document.text, start + len);
+            document.edit(Span::from(start..end), snippet);
+
+            let full = Document::new(document.text.clone());
+            prop_assert_eq!(snapshot(&document), snapshot(&full), "source: {:?}", document.text);
+        }
+    }
+}
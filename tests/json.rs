@@ -0,0 +1,92 @@
+use aoxo_toml::value::{self, Value};
+use serde_json::json;
+
+fn parse(source: &str) -> Value {
+    let (table, errors) = value::parse(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    Value::Table(table)
+}
+
+#[test]
+fn arrays_of_tables_convert_both_ways() {
+    let value = parse("[[bin]]\nname = \"a\"\n[[bin]]\nname = \"b\"\ntest = false\n");
+    let expected = json!({ "bin": [{ "name": "a" }, { "name": "b", "test": false }] });
+    assert_eq!(value.to_json().unwrap(), expected);
+    assert_eq!(Value::from_json(&expected).unwrap(), value);
+
+    let tagged = value.to_tagged_json();
+    assert_eq!(
+        tagged["bin"][1],
+        json!({
+            "name": { "type": "string", "value": "b" },
+            "test": { "type": "bool", "value": "false" },
+        })
+    );
+    assert_eq!(Value::from_tagged_json(&tagged).unwrap(), value);
+}
+
+#[test]
+fn special_floats_only_go_through_tagged_json() {
+    let value = parse("a = nan\nb = -inf\nc = +inf\nd = 1.5\n");
+    assert_eq!(
+        value.to_json().unwrap_err(),
+        "`nan` can't be written as plain JSON, try --tagged"
+    );
+
+    let tagged = value.to_tagged_json();
+    assert_eq!(tagged["a"], json!({ "type": "float", "value": "nan" }));
+    assert_eq!(tagged["b"], json!({ "type": "float", "value": "-inf" }));
+    assert_eq!(tagged["c"], json!({ "type": "float", "value": "inf" }));
+    let Value::Table(back) = Value::from_tagged_json(&tagged).unwrap() else {
+        panic!("not a table");
+    };
+    assert!(matches!(back["a"], Value::Float(float) if float.is_nan()));
+    assert_eq!(back["b"], Value::Float(f64::NEG_INFINITY));
+    assert_eq!(back["c"], Value::Float(f64::INFINITY));
+    assert_eq!(back["d"], Value::Float(1.5));
+
+    // Plain JSON keeps integers and floats apart
+    let plain = Value::from_json(&json!({ "i": 1, "f": 1.0 })).unwrap();
+    assert_eq!(plain, parse("i = 1\nf = 1.0\n"));
+}
+
+#[test]
+fn datetimes_are_not_supported() {
+    for kind in ["datetime", "datetime-local", "date-local", "time-local"] {
+        let tagged = json!({ "when": { "type": kind, "value": "1979-05-27" } });
+        assert_eq!(
+            Value::from_tagged_json(&tagged).unwrap_err(),
+            format!("{kind} values are not supported")
+        );
+    }
+}
+
+#[test]
+fn bad_values_are_named() {
+    let error = |json| Value::from_tagged_json(&json).unwrap_err();
+    assert_eq!(
+        error(json!({ "type": "integer", "value": "4x" })),
+        "invalid integer `4x`"
+    );
+    assert_eq!(
+        error(json!({ "type": "bool", "value": "yes" })),
+        "invalid bool `yes`"
+    );
+    assert_eq!(
+        error(json!({ "type": "decimal", "value": "1" })),
+        "unknown type `decimal`"
+    );
+    assert_eq!(
+        error(json!({ "a": 1 })),
+        "expected a tagged value like {\"type\": \"string\", \"value\": \"…\"}, found `1`"
+    );
+
+    assert_eq!(
+        Value::from_json(&json!({ "a": null })).unwrap_err(),
+        "TOML has no `null`"
+    );
+    assert_eq!(
+        Value::from_json(&json!(u64::MAX)).unwrap_err(),
+        "18446744073709551615 doesn't fit in a 64-bit integer"
+    );
+}
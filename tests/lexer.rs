@@ -0,0 +1,181 @@
+use aoxo_toml::{
+    parser::Parser,
+    value::{self, Value},
+};
+
+/// The code and message of each error of `source`.
+fn errors(source: &str) -> Vec<(&'static str, String)> {
+    let (_, errors) = value::parse(source);
+    errors
+        .iter()
+        .map(|error| (error.code(), error.to_string()))
+        .collect()
+}
+
+#[test]
+fn values_are_bare_keys_where_keys_go() {
+    let source = "1 = 'a'\ntrue = 'b'\ninf = 'c'\n-1 = 'd'\n3.14 = 'e'\n[0.1]\nnan.x = 'f'\n";
+    let (table, found) = value::parse(source);
+    assert!(found.is_empty(), "{found:?}");
+    let json = Value::Table(table).to_json().unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "1": "a", "true": "b", "inf": "c", "-1": "d", "3": { "14": "e" },
+            "0": { "1": { "nan": { "x": "f" } } }
+        })
+    );
+
+    // Only the characters of bare keys
+    assert_eq!(errors("+1 = 1\n")[0].0, "E0005");
+}
+
+#[test]
+fn keys_outside_ascii_must_be_quoted() {
+    assert_eq!(
+        errors("café = 1\n\"thé\" = 2\n"),
+        [("E0018", "`café` must be quoted to be a key".to_string())]
+    );
+    assert_eq!(errors("a.ключ.1日 = 1\n").len(), 2);
+}
+
+#[test]
+fn control_characters_are_named_by_code_point() {
+    assert_eq!(
+        errors("a = \"\u{1}\" # \u{7f}\n\u{7}\n"),
+        [
+            (
+                "E0019",
+                "control character U+0001 is not allowed".to_string()
+            ),
+            (
+                "E0019",
+                "control character U+007F is not allowed".to_string()
+            ),
+            (
+                "E0019",
+                "control character U+0007 is not allowed".to_string()
+            ),
+        ]
+    );
+    // Other characters, like U+0080, are fine in strings and comments
+    assert!(errors("a = \"\u{80}\" # \u{80}\n").is_empty());
+
+    assert_eq!(
+        errors("a = 1\rb = 2\n")[0],
+        ("E0020", "carriage return without a line feed".to_string())
+    );
+    assert!(errors("a = 1 # comment\r\nb = '''\r\n'''\r\n").is_empty());
+}
+
+#[test]
+fn byte_order_marks_only_start_files() {
+    assert!(errors("\u{feff}a = 1\n").is_empty());
+    assert_eq!(errors("a = 1\n\u{feff}b = 2\n")[0].0, "E0001");
+    assert_eq!(
+        aoxo_toml::format::format("\u{feff}a=1\n"),
+        "\u{feff}a = 1\n"
+    );
+}
+
+#[test]
+fn line_endings_are_read_alike() {
+    let source = concat!(
+        "# settings\n",
+        "\n",
+        "a = 1\n",
+        "\n",
+        "# about b\n",
+        "\n",
+        "b = [\n",
+        "\n",
+        "  1, # one\n",
+        "\n",
+        "]\n",
+        "[t]\n",
+        "\n",
+        "c = '''\n",
+        "x\n",
+        "'''\n",
+        "\n",
+        "[[u]]\n",
+        "d = 2",
+    );
+    let (lf, errors) = value::parse(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    let (crlf, errors) = value::parse(&source.replace('\n', "\r\n"));
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(crlf, lf);
+
+    // The same tree, but for spans shifted by the extra `\r`s
+    let kinds = |source: &str| {
+        let (tree, _) = Parser::new(source).parse().tree();
+        format!("{tree:#?}")
+            .lines()
+            .filter(|line| !line.contains("span"))
+            .collect::<String>()
+    };
+    assert_eq!(kinds(&source.replace('\n', "\r\n")), kinds(source));
+}
+
+#[test]
+fn pairs_end_at_a_newline_or_the_end_of_the_file() {
+    assert!(errors("a = 1").is_empty());
+    assert!(errors("[t]").is_empty());
+    assert!(errors("a = 1 # comment").is_empty());
+    assert_eq!(
+        errors("a = 1 b = 2\n")[0],
+        ("E0003", "expected a newline after `a = 1`".to_string())
+    );
+}
+
+#[test]
+fn exponents_take_a_sign() {
+    let (table, found) = value::parse("a = 1.5e+3\nb = 1e+3\nc = 2E-2\nd = -1e6\n1e3 = 'key'\n");
+    assert!(found.is_empty(), "{found:?}");
+    assert_eq!(table["a"], Value::Float(1500.0));
+    assert_eq!(table["b"], Value::Float(1000.0));
+    assert_eq!(table["c"], Value::Float(0.02));
+    assert_eq!(table["d"], Value::Float(-1e6));
+    assert_eq!(table["1e3"], Value::String("key".to_string()));
+
+    // The note names what's wrong with the number
+    let (_, found) = value::parse("a = 1.5e+\n");
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        found[0].notes,
+        ["an exponent is `e` followed by an integer, like in `1e6` or `6.626e-34`"]
+    );
+    let (_, found) = value::parse("a = 1.2.3\n");
+    assert_eq!(
+        found[0].notes,
+        ["a float has a single `.` between its integer and fractional parts"]
+    );
+}
+
+#[test]
+fn integers_take_a_radix_prefix() {
+    let (table, found) = value::parse("a = 0xdead_BEEF\nb = 0o755\nc = 0b101\n0x1 = 'key'\n");
+    assert!(found.is_empty(), "{found:?}");
+    assert_eq!(table["a"], Value::Integer(0xdead_beef));
+    assert_eq!(table["b"], Value::Integer(0o755));
+    assert_eq!(table["c"], Value::Integer(0b101));
+    assert_eq!(table["0x1"], Value::String("key".to_string()));
+
+    // Floats need digits before their `.`
+    let (_, found) = value::parse("a = -.5\n");
+    assert_eq!(
+        found[0].notes,
+        ["a float has digits before its `.`, like `0.5`"]
+    );
+}
+
+#[test]
+fn quotes_next_to_multiline_delimiters_belong_to_the_string() {
+    let (table, found) = value::parse("a = '''''two'''''\nb = \"\"\"\"one\"\"\"\"\n");
+    assert!(found.is_empty(), "{found:?}");
+    assert_eq!(table["a"], Value::String("''two''".to_string()));
+    assert_eq!(table["b"], Value::String("\"one\"".to_string()));
+    // Six are one too many
+    assert_eq!(errors("c = \"\"\"x\"\"\"\"\"\"\n")[0].0, "E0002");
+}
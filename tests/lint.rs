@@ -0,0 +1,155 @@
+use aoxo_toml::{
+    config::{Config, Severity},
+    lint::{self, RULES},
+    parser::Parser,
+    span::Span,
+};
+
+/// The rule and the text of each lint in `source`.
+fn lints(source: &str, config: &Config) -> Vec<(&'static str, String)> {
+    let (tree, errors) = Parser::new(source).parse().tree();
+    assert!(errors.is_empty(), "{errors:?}");
+    lint::lint(source, &tree, config)
+        .into_iter()
+        .map(|lint| {
+            let span = lint.error.span;
+            (lint.error.code(), source[span.start..span.end].to_string())
+        })
+        .collect()
+}
+
+fn config(lints: &[(&str, Severity)]) -> Config {
+    let mut config = Config::default();
+    for (rule, severity) in lints {
+        config.lints.insert(rule.to_string(), *severity);
+    }
+    config
+}
+
+#[test]
+fn finds_what_each_rule_checks() {
+    let source = concat!(
+        "\"a b\" = \"y\"\n",
+        "\"name\" = 'x'\n",
+        "other = \"z\"  \n",
+        "\n",
+        "[empty]\n",
+        "[t]\n",
+        "\tk = [\n",
+        "\t\t1,\n",
+        "    ]\n",
+        "[u]\n",
+        "v = \"\"\"text  \n",
+        "    more\"\"\"\n",
+        "[t.sub]\n",
+        "b = 1\n",
+        "a = 2\n",
+        "\n",
+        "c = 3\n",
+    );
+    assert_eq!(
+        lints(source, &config(&[("unsorted-keys", Severity::Warn)])),
+        [
+            ("quoted-key", "\"name\"".to_string()),
+            ("mixed-quotes", "'x'".to_string()),
+            ("trailing-whitespace", "  ".to_string()),
+            ("empty-table", "empty".to_string()),
+            ("inconsistent-indentation", "    ".to_string()),
+            ("table-order", "t.sub".to_string()),
+            ("unsorted-keys", "a".to_string()),
+        ]
+    );
+
+    // Allowed rules don't run, and rules can be made errors
+    let config = config(&[
+        ("quoted-key", Severity::Allow),
+        ("empty-table", Severity::Error),
+    ]);
+    let (tree, _) = Parser::new(source).parse().tree();
+    let severities = lint::lint(source, &tree, &config)
+        .iter()
+        .map(|lint| (lint.error.code(), lint.error.severity()))
+        .collect::<Vec<_>>();
+    assert!(!severities.iter().any(|(rule, _)| *rule == "quoted-key"));
+    assert!(severities.contains(&("empty-table", Severity::Error)));
+    assert!(severities.contains(&("mixed-quotes", Severity::Warn)));
+}
+
+#[test]
+fn whitespace_inside_multiline_strings_is_left_alone() {
+    let source = concat!(
+        "s = \"\"\"\n",
+        "  indented  \n",
+        "\tmixed\t\n",
+        "   end\"\"\"  \n",
+        "[t]\n",
+        "    k = \"\"\"\n",
+        "\t  x  \n",
+        "\"\"\"\n",
+        "    j = 1 \n",
+    );
+    // Only the whitespace after the strings counts
+    assert_eq!(
+        lints(source, &Config::default()),
+        [
+            ("trailing-whitespace", "  ".to_string()),
+            ("trailing-whitespace", " ".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn comments_silence_rules() {
+    let source = concat!(
+        "# aoxo-toml: allow-file(mixed-quotes)\n",
+        "\"a\" = 'x' # aoxo-toml: allow(quoted-key)\n",
+        "# aoxo-toml: allow(quoted-key, trailing-whitespace)\n",
+        "\"b\" = \"y\" \n",
+        "\"c\" = \"z\"\n",
+    );
+    assert_eq!(
+        lints(source, &Config::default()),
+        [("quoted-key", "\"c\"".to_string())]
+    );
+}
+
+#[test]
+fn fixes_what_can_be_fixed() {
+    let source = concat!(
+        "\"name\" = 'x'   \n",
+        "other = \"y\"\n",
+        "more = \"z\"\n",
+        "[empty]\n",
+        "[t]\n",
+        "    a = [\n",
+        "\t1,\n",
+        "    ]\n",
+    );
+    assert_eq!(
+        lint::fix_all(source, &Config::default()).unwrap(),
+        "name = \"x\"\nother = \"y\"\nmore = \"z\"\n[empty]\n[t]\n    a = [\n    1,\n    ]\n"
+    );
+    assert_eq!(lint::fix_all("a = [\n", &Config::default()), None);
+
+    // Removing an empty table changes the data, so it's only offered
+    let source = "[workspace]
+";
+    let (tree, _) = Parser::new(source).parse().tree();
+    let lints = lint::lint(source, &tree, &Config::default());
+    let fix = lints[0].fix.as_ref().unwrap();
+    assert!(!fix.safe);
+    assert_eq!(fix.edits[0].span, Span::from(0..12));
+    assert_eq!(lint::fix_all(source, &Config::default()).unwrap(), source);
+}
+
+#[test]
+fn every_rule_is_in_the_catalogue() {
+    let catalogue = include_str!("../docs/lints.md");
+    for rule in RULES {
+        assert!(
+            catalogue.contains(&format!("## `{}`", rule.id)),
+            "`{}` is missing from docs/lints.md",
+            rule.id
+        );
+    }
+}
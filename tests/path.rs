@@ -0,0 +1,78 @@
+use aoxo_toml::value::{self, PathErrorKind, Value};
+
+const SOURCE: &str = r#"
+[servers.alpha]
+ip = "10.0.0.1"
+"dc.name" = "eqdc10"
+
+[[products]]
+name = "Hammer"
+
+[[products]]
+name = "Nail"
+"#;
+
+fn document() -> Value {
+    let (table, errors) = value::parse(SOURCE);
+    assert!(errors.is_empty(), "{errors:?}");
+    Value::Table(table)
+}
+
+#[test]
+fn finds_values() {
+    let document = document();
+    let get = |path| document.get(path).unwrap().clone();
+
+    assert_eq!(get("servers.alpha.ip"), Value::String("10.0.0.1".into()));
+    assert_eq!(
+        get("servers.alpha.\"dc.name\""),
+        Value::String("eqdc10".into())
+    );
+    assert_eq!(
+        get(" servers . 'alpha' . ip "),
+        Value::String("10.0.0.1".into())
+    );
+    assert_eq!(get("products[1].name"), Value::String("Nail".into()));
+    assert_eq!(get(""), document);
+}
+
+#[test]
+fn errors_point_at_the_missing_segment() {
+    let document = document();
+    let error = |path: &'static str| {
+        let error = document.get(path).unwrap_err();
+        (error.kind, &path[error.span.start..error.span.end])
+    };
+
+    assert_eq!(
+        error("servers.beta.ip"),
+        (
+            PathErrorKind::MissingKey {
+                key: "beta".into(),
+                parent: "servers".into()
+            },
+            "beta"
+        )
+    );
+    assert_eq!(
+        error("products[2].name"),
+        (
+            PathErrorKind::OutOfBounds {
+                index: 2,
+                len: 2,
+                parent: "products".into()
+            },
+            "[2]"
+        )
+    );
+    assert_eq!(
+        error("servers.alpha.ip.x"),
+        (
+            PathErrorKind::NotATable {
+                parent: "servers.alpha.ip".into()
+            },
+            "x"
+        )
+    );
+    assert!(matches!(error("a..b").0, PathErrorKind::Invalid(_)));
+}
@@ -0,0 +1,84 @@
+//! Snapshots of how the parser recovers from syntax errors.
+//!
+//! Every `.toml` file under `tests/recovery/` is broken on purpose. Its
+//! `.snap` twin holds the short diagnostics the parser reports for it,
+//! followed by the text of every error node, the tokens skipped to get
+//! back on track. A broken construct should report one error, and skip as
+//! little as it can. Run with `RECOVERY_BLESS=1` to rewrite the snapshots
+//! from the current results.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use aoxo_toml::{
+    parser::Parser,
+    report::Report,
+    tree::{self, Child, Tree},
+};
+
+const ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/recovery");
+
+fn skipped(source: &str, tree: &Tree, out: &mut String) {
+    if tree.kind == tree::Kind::Error {
+        let _ = writeln!(out, "skipped {:?}", &source[tree.span.start..tree.span.end]);
+    }
+    for child in &tree.children {
+        if let Child::Tree(tree) = child {
+            skipped(source, tree, out);
+        }
+    }
+}
+
+/// The snapshot of the file `name` holding `source`.
+fn snapshot(name: &str, source: &str) -> String {
+    let (tree, mut errors) = Parser::new(source).parse().tree();
+    errors.sort_by_key(|error| (error.span.start, error.span.end));
+    let report = Report {
+        path: name,
+        source,
+        errors: &errors,
+    };
+    let mut out = report.short();
+    skipped(source, &tree, &mut out);
+    out
+}
+
+#[test]
+fn recovery() {
+    let mut cases = std::fs::read_dir(ROOT)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect::<Vec<PathBuf>>();
+    cases.sort();
+    let bless = std::env::var_os("RECOVERY_BLESS").is_some();
+
+    let mut changed = Vec::new();
+    for path in &cases {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let source = std::fs::read_to_string(path).unwrap();
+        let actual = snapshot(&name, &source);
+        let snap = path.with_extension("snap");
+        if bless {
+            std::fs::write(&snap, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&snap).unwrap_or_default();
+        if actual != expected {
+            changed.push(format!(
+                "{name}:\n--- expected\n{expected}--- actual\n{actual}"
+            ));
+        }
+    }
+    assert!(
+        changed.is_empty(),
+        "{} recovery snapshots changed, rerun with RECOVERY_BLESS=1 if that's right:\n{}",
+        changed.len(),
+        changed.join("\n")
+    );
+    assert!(
+        !cases.is_empty(),
+        "no cases under {}",
+        Path::new(ROOT).display()
+    );
+}
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use aoxo_toml::{
+    config::Config,
+    error::ErrorKind,
+    lint,
+    parser::{Error, Parser},
+    report::{self, Report},
+    token::Kind,
+};
+use serde_json::json;
+
+/// A syntax error on a line with a tab and a character wider than a byte.
+const BROKEN: &str = "name = 1\n\tk = \"é\" x\n";
+
+/// Only a lint warning, on the second line.
+const WARNED: &str = "a = 1\n\"b\" = 2\n";
+
+/// The syntax errors of `source` or, if there are none, its lints.
+fn errors(source: &str) -> Vec<Error> {
+    let (tree, errors) = Parser::new(source).parse().tree();
+    if !errors.is_empty() {
+        return errors;
+    }
+    lint::lint(source, &tree, &Config::default())
+        .into_iter()
+        .map(|lint| lint.error)
+        .collect()
+}
+
+#[test]
+fn renders_snippets_for_humans() {
+    let errors = errors(BROKEN);
+    let report = Report {
+        path: "x.toml",
+        source: BROKEN,
+        errors: &errors,
+    };
+    assert_eq!(
+        report.human(false),
+        concat!(
+            "error[E0003]: expected a newline after `k = \"é\"`\n",
+            " --> x.toml:2:10\n",
+            "  |\n",
+            "2 |     k = \"é\" x\n",
+            "  |             ^\n",
+            "  |\n",
+            "  = help: put each key-value pair on a line of its own\n",
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn renders_one_line_per_error() {
+    let broken = errors(BROKEN);
+    let warned = errors(WARNED);
+    let short = [("x.toml", BROKEN, &broken), ("y.toml", WARNED, &warned)]
+        .map(|(path, source, errors)| {
+            Report {
+                path,
+                source,
+                errors,
+            }
+            .short()
+        })
+        .concat();
+    assert_eq!(
+        short,
+        concat!(
+            "x.toml:2:10: error[E0003]: expected a newline after `k = \"é\"`\n",
+            "y.toml:2:1: warning[quoted-key]: `\"b\"` doesn't need quotes\n",
+        )
+    );
+}
+
+#[test]
+fn renders_json_with_character_columns() {
+    let errors = errors(BROKEN);
+    let report = Report {
+        path: "x.toml",
+        source: BROKEN,
+        errors: &errors,
+    };
+    assert_eq!(
+        report.json(),
+        [json!({
+            "file": "x.toml",
+            "severity": "error",
+            "code": "E0003",
+            "message": "expected a newline after `k = \"é\"`",
+            "span": {
+                "offset": { "start": 19, "end": 20 },
+                "start": { "line": 2, "column": 10 },
+                "end": { "line": 2, "column": 11 },
+            },
+            "notes": [],
+            "help": "put each key-value pair on a line of its own",
+            "labels": [],
+        })]
+    );
+}
+
+#[test]
+fn renders_sarif() {
+    let errors = errors(WARNED);
+    let report = Report {
+        path: "y.toml",
+        source: WARNED,
+        errors: &errors,
+    };
+    let sarif = report::sarif(&[report]);
+    let run = &sarif["runs"][0];
+    assert_eq!(sarif["version"], "2.1.0");
+    assert_eq!(
+        run["tool"]["driver"]["rules"],
+        json!([{
+            "id": "quoted-key",
+            "helpUri": "https://github.com/AOx0/toml-lsp/blob/main/docs/lints.md#quoted-key",
+        }])
+    );
+    assert_eq!(
+        run["results"],
+        json!([{
+            "ruleId": "quoted-key",
+            "level": "warning",
+            "message": { "text": "`\"b\"` doesn't need quotes" },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": "y.toml" },
+                    "region": {
+                        "startLine": 2,
+                        "startColumn": 1,
+                        "endLine": 2,
+                        "endColumn": 4,
+                    },
+                },
+            }],
+        }])
+    );
+}
+
+/// The exit code of the command line run with `args` on a file holding
+/// `source`.
+fn exit_code(name: &str, source: &str, args: &[&str]) -> Option<i32> {
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "aoxo-toml-report-{name}-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_aoxo-toml"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output.status.code()
+}
+
+#[test]
+fn only_errors_fail() {
+    assert_eq!(exit_code("warned", WARNED, &["lint"]), Some(0));
+    assert_eq!(
+        exit_code("warned-json", WARNED, &["lint", "--format", "json"]),
+        Some(0)
+    );
+    assert_eq!(exit_code("broken", BROKEN, &["lint"]), Some(1));
+    assert_eq!(exit_code("check", BROKEN, &["check"]), Some(1));
+    assert_eq!(exit_code("clean", WARNED, &["check"]), Some(0));
+}
+
+#[test]
+fn check_reports_what_lowering_finds() {
+    // Parses fine, but can't be turned into data
+    for (name, source) in [("twice", "a = 1\na = 2\n"), ("table", "[t]\n[t]\n")] {
+        assert_eq!(
+            exit_code(name, source, &["check", "--format", "short"]),
+            Some(1),
+            "{source:?}"
+        );
+    }
+}
+
+#[test]
+fn expected_tokens_read_as_a_choice() {
+    let expected = |expected| ErrorKind::Expected {
+        expected,
+        after: None,
+    };
+    assert_eq!(expected(&[Kind::Comma]).to_string(), "expected `,`");
+    assert_eq!(
+        expected(&[Kind::Equal, Kind::Dot, Kind::Key]).to_string(),
+        "expected `=`, `.` or a key"
+    );
+    assert_eq!(
+        errors("t = { a = 1 b = 2 }\n")[0].to_string(),
+        "expected `,` or `}`"
+    );
+}
@@ -0,0 +1,237 @@
+use aoxo_toml::{error::ErrorKind, parser::Parser, report::Report, value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Config {
+    title: String,
+    debug: Option<bool>,
+    server: Server,
+    #[serde(default)]
+    products: Vec<Product>,
+    shape: Shape,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Server {
+    host: String,
+    port: u16,
+    ratio: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Product {
+    name: String,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+enum Shape {
+    Point,
+    Circle { radius: f64 },
+}
+
+const SOURCE: &str = r#"
+title = "demo"
+shape = { Circle = { radius = 1.5 } }
+
+[server]
+host = "localhost"
+port = 8080
+ratio = 0.5
+
+[[products]]
+name = "Hammer"
+tags = ["tool", "steel"]
+"#;
+
+#[test]
+fn deserializes_structs() {
+    let config: Config = value::from_str(SOURCE).unwrap();
+    assert_eq!(
+        config,
+        Config {
+            title: "demo".into(),
+            debug: None,
+            server: Server {
+                host: "localhost".into(),
+                port: 8080,
+                ratio: 0.5,
+            },
+            products: vec![Product {
+                name: "Hammer".into(),
+                tags: vec!["tool".into(), "steel".into()],
+            }],
+            shape: Shape::Circle { radius: 1.5 },
+        }
+    );
+
+    let config: Config = value::from_str(
+        &SOURCE.replace("{ Circle = { radius = 1.5 } }", "\"Point\"\ndebug = true"),
+    )
+    .unwrap();
+    assert_eq!(config.shape, Shape::Point);
+    assert_eq!(config.debug, Some(true));
+}
+
+/// The text the single error of deserializing `source` spans.
+fn error_at(source: &str) -> String {
+    let errors = value::from_str::<Config>(source).unwrap_err();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    let error = &errors[0];
+    assert!(matches!(error.kind, ErrorKind::Deserialize(_)));
+    source[error.span.start..error.span.end].to_string()
+}
+
+#[test]
+fn errors_span_the_offending_value() {
+    assert_eq!(
+        error_at(&SOURCE.replace("8080", "\"eighty\"")),
+        "\"eighty\""
+    );
+    assert_eq!(error_at(&SOURCE.replace("8080", "70000")), "70000");
+    assert_eq!(error_at(&SOURCE.replace("\"steel\"", "1")), "1");
+    assert_eq!(
+        error_at(&SOURCE.replace("radius = 1.5", "diameter = 3.0")),
+        "{ diameter = 3.0 }"
+    );
+    // Missing keys are reported on the table they're missing from
+    assert_eq!(error_at(&SOURCE.replace("ratio = 0.5\n", "")), "server");
+}
+
+#[test]
+fn errors_render_like_syntax_errors() {
+    let source = SOURCE.replace("8080", "\"eighty\"");
+    let errors = value::from_str::<Config>(&source).unwrap_err();
+    let report = Report {
+        path: "config.toml",
+        source: &source,
+        errors: &errors,
+    };
+    let human = report.human(false);
+    assert!(human.contains("E0016"), "{human}");
+    assert!(human.contains("port = \"eighty\""), "{human}");
+    assert!(
+        report.short().starts_with("config.toml:7:8: "),
+        "{}",
+        report.short()
+    );
+}
+
+#[test]
+fn syntax_errors_come_first() {
+    let errors = value::from_str::<Config>("title = ").unwrap_err();
+    assert!(matches!(errors[0].kind, ErrorKind::MissingValue));
+}
+
+fn config() -> Config {
+    Config {
+        title: "demo".into(),
+        debug: None,
+        server: Server {
+            host: "localhost".into(),
+            port: 8080,
+            ratio: 0.5,
+        },
+        products: vec![
+            Product {
+                name: "Hammer".into(),
+                tags: vec!["tool".into()],
+            },
+            Product {
+                name: "Nail".into(),
+                tags: Vec::new(),
+            },
+        ],
+        shape: Shape::Point,
+    }
+}
+
+#[test]
+fn serializes_idiomatic_toml() {
+    let toml = value::to_string(&config()).unwrap();
+    assert_eq!(
+        toml,
+        r#"title = "demo"
+shape = "Point"
+
+[server]
+host = "localhost"
+port = 8080
+ratio = 0.5
+
+[[products]]
+name = "Hammer"
+tags = ["tool"]
+
+[[products]]
+name = "Nail"
+tags = []
+"#
+    );
+    let (_, errors) = Parser::new(&toml).parse().tree();
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(value::from_str::<Config>(&toml).unwrap(), config());
+}
+
+#[test]
+fn inlines_tables_past_the_configured_depth() {
+    let mut config = config();
+    config.shape = Shape::Circle { radius: 2.0 };
+    config.products.truncate(1);
+    let layout = value::Layout {
+        inline_depth: Some(1),
+    };
+    let toml = value::to_string_with(&config, layout).unwrap();
+    assert_eq!(
+        toml,
+        r#"title = "demo"
+
+[server]
+host = "localhost"
+port = 8080
+ratio = 0.5
+
+[[products]]
+name = "Hammer"
+tags = ["tool"]
+
+[shape]
+Circle = { radius = 2.0 }
+"#
+    );
+    assert_eq!(value::from_str::<Config>(&toml).unwrap(), config);
+
+    let layout = value::Layout {
+        inline_depth: Some(0),
+    };
+    let toml = value::to_string_with(&config, layout).unwrap();
+    assert!(
+        toml.starts_with("title = \"demo\"\nserver = { host = "),
+        "{toml}"
+    );
+    assert_eq!(value::from_str::<Config>(&toml).unwrap(), config);
+}
+
+#[test]
+fn quotes_keys_that_are_not_bare() {
+    let mut table = std::collections::BTreeMap::new();
+    table.insert("dc.name", "eqdc10");
+    table.insert("plain-key_1", "x");
+    table.insert("", "empty");
+    let toml = value::to_string(&table).unwrap();
+    assert_eq!(
+        toml,
+        "\"\" = \"empty\"\n\"dc.name\" = \"eqdc10\"\nplain-key_1 = \"x\"\n"
+    );
+}
+
+#[test]
+fn rejects_what_toml_cannot_represent() {
+    assert!(value::to_string(&42).is_err());
+    assert!(value::to_string(&vec![Some(1), None]).is_err());
+    #[derive(Serialize)]
+    struct Big {
+        n: u64,
+    }
+    assert!(value::to_string(&Big { n: u64::MAX }).is_err());
+}
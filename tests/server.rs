@@ -0,0 +1,540 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const INITIALIZE: &str =
+    r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#;
+
+fn server(args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_aoxo-toml"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+/// The address the server says it listens on.
+fn address(server: &mut Child) -> String {
+    let mut line = String::new();
+    BufReader::new(server.stderr.as_mut().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    line.trim()
+        .strip_prefix("listening on ")
+        .unwrap_or_else(|| panic!("unexpected output {line:?}"))
+        .to_string()
+}
+
+fn send(stream: &mut impl Write, message: &str) {
+    write!(stream, "Content-Length: {}\r\n\r\n{message}", message.len()).unwrap();
+    stream.flush().unwrap();
+}
+
+fn receive(reader: &mut impl BufRead) -> String {
+    let mut len = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some(value) = header.trim().strip_prefix("Content-Length: ") {
+            len = value.parse().unwrap();
+        }
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+    String::from_utf8(body).unwrap()
+}
+
+fn initializes(stream: &mut (impl Read + Write)) {
+    send(stream, INITIALIZE);
+    let response = receive(&mut BufReader::new(stream));
+    assert!(response.contains(r#""capabilities""#), "{response}");
+}
+
+/// A client talking to a server over stdio.
+struct Session {
+    server: Child,
+    reader: BufReader<std::process::ChildStdout>,
+    /// What the client answers `workspace/configuration` requests with.
+    settings: serde_json::Value,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self::initialize(serde_json::json!({ "capabilities": {} }))
+    }
+
+    fn initialize(params: serde_json::Value) -> Self {
+        let mut server = server(&[]);
+        let reader = BufReader::new(server.stdout.take().unwrap());
+        let mut session = Session {
+            server,
+            reader,
+            settings: serde_json::Value::Null,
+        };
+        let initialize = serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": params
+        });
+        session.send(&initialize.to_string());
+        session.receive_until(r#""id":1"#);
+        session.send(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#);
+        session
+    }
+
+    fn send(&mut self, message: &str) {
+        send(self.server.stdin.as_mut().unwrap(), message);
+    }
+
+    fn notify(&mut self, method: &str, params: serde_json::Value) {
+        let message = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.send(&message.to_string());
+    }
+
+    /// Sends the request `method` and reads its result, checking that no
+    /// diagnostics are published in the meantime.
+    fn request(&mut self, id: u32, method: &str, params: serde_json::Value) -> serde_json::Value {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0", "id": id, "method": method, "params": params
+        });
+        self.send(&message.to_string());
+        let mut messages = self.receive_all_until(&format!(r#""id":{id}"#));
+        assert!(
+            messages
+                .iter()
+                .all(|message| message["method"] != "textDocument/publishDiagnostics"),
+            "{messages:?}"
+        );
+        messages.pop().unwrap()["result"].clone()
+    }
+
+    /// Reads messages up to the first one containing `needle`, answering
+    /// requests from the server on the way.
+    fn receive_all_until(&mut self, needle: &str) -> Vec<serde_json::Value> {
+        let mut messages = Vec::new();
+        loop {
+            let text = receive(&mut self.reader);
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            if message.get("id").is_some() && message.get("method").is_some() {
+                let result = match message["method"].as_str() {
+                    Some("workspace/configuration") => serde_json::json!([self.settings]),
+                    _ => serde_json::Value::Null,
+                };
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0", "id": message["id"], "result": result
+                });
+                self.send(&response.to_string());
+            }
+            messages.push(message);
+            if text.contains(needle) {
+                return messages;
+            }
+        }
+    }
+
+    fn receive_until(&mut self, needle: &str) -> serde_json::Value {
+        self.receive_all_until(needle).pop().unwrap()
+    }
+
+    /// The codes of the next diagnostics published.
+    fn diagnostics(&mut self) -> (serde_json::Value, Vec<String>) {
+        let message = self.receive_until("textDocument/publishDiagnostics");
+        let params = &message["params"];
+        let codes = params["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|diagnostic| diagnostic["code"].as_str().unwrap().to_string())
+            .collect();
+        (params["version"].clone(), codes)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+    }
+}
+
+#[test]
+fn serves_clients_over_tcp() {
+    let mut server = server(&["--listen", "tcp:127.0.0.1:0"]);
+    let address = address(&mut server);
+    let address = address.strip_prefix("tcp:").unwrap();
+
+    // Clients are served independently, at the same time
+    let mut first = std::net::TcpStream::connect(address).unwrap();
+    let mut second = std::net::TcpStream::connect(address).unwrap();
+    initializes(&mut first);
+    initializes(&mut second);
+
+    server.kill().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn serves_clients_over_unix_sockets() {
+    let path = std::env::temp_dir().join(format!("aoxo-toml-{}.sock", std::process::id()));
+    let listen = format!("unix:{}", path.display());
+    let mut server = server(&["--listen", &listen]);
+    assert_eq!(address(&mut server), listen);
+
+    let mut client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    initializes(&mut client);
+
+    server.kill().unwrap();
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn exits_with_the_client_process() {
+    let mut client = Command::new(env!("CARGO_BIN_EXE_aoxo-toml"))
+        .arg("--help")
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let pid = client.id().to_string();
+    client.wait().unwrap();
+
+    let mut server = server(&["--clientProcessId", &pid]);
+    let start = Instant::now();
+    while server.try_wait().unwrap().is_none() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the server outlived its client"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn rejects_unknown_transports() {
+    let output = Command::new(env!("CARGO_BIN_EXE_aoxo-toml"))
+        .args(["--listen", "pipe:x"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("tcp:HOST:PORT"), "{stderr}");
+}
+
+#[test]
+fn tracks_documents_from_open_to_close() {
+    let mut session = Session::new();
+    let uri = "file:///config.toml";
+    let document = serde_json::json!({ "uri": uri });
+
+    // Duplicate keys are found by the checks beyond syntax, which run on
+    // open and save
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": "a = 1\na = 2\n" }
+        }),
+    );
+    assert_eq!(session.diagnostics(), (1.into(), vec!["E0013".to_string()]));
+
+    session.notify(
+        "textDocument/didChange",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "a = 1\na = 2\nb = \n" }]
+        }),
+    );
+    assert_eq!(session.diagnostics(), (2.into(), vec!["E0006".to_string()]));
+
+    session.notify(
+        "textDocument/didSave",
+        serde_json::json!({ "textDocument": document }),
+    );
+    assert_eq!(
+        session.diagnostics(),
+        (2.into(), vec!["E0006".to_string(), "E0013".to_string()])
+    );
+
+    session.notify(
+        "textDocument/didClose",
+        serde_json::json!({ "textDocument": document }),
+    );
+    assert_eq!(session.diagnostics(), (serde_json::Value::Null, Vec::new()));
+}
+
+#[test]
+fn checks_workspace_files_that_are_not_open() {
+    let root = std::env::temp_dir().join(format!("aoxo-toml-workspace-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("target")).unwrap();
+    std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+    std::fs::write(root.join("broken.toml"), "a = \n").unwrap();
+    std::fs::write(root.join("good.toml"), "a = 1\n").unwrap();
+    std::fs::write(root.join("target/ignored.toml"), "a = \n").unwrap();
+    let root_uri = format!("file://{}", root.display());
+
+    let mut session = Session::initialize(serde_json::json!({
+        "rootUri": root_uri,
+        "capabilities": {
+            "window": { "workDoneProgress": true },
+            "workspace": { "didChangeWatchedFiles": { "dynamicRegistration": true } }
+        }
+    }));
+    let messages = session.receive_all_until(r#""kind":"end""#);
+    let methods = messages
+        .iter()
+        .filter_map(|message| message["method"].as_str())
+        .collect::<Vec<_>>();
+    assert!(
+        methods.contains(&"client/registerCapability"),
+        "{methods:?}"
+    );
+    assert!(
+        methods.contains(&"window/workDoneProgress/create"),
+        "{methods:?}"
+    );
+    let published = messages
+        .iter()
+        .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+        .map(|message| {
+            let params = &message["params"];
+            let uri = params["uri"].as_str().unwrap();
+            let name = uri.rsplit('/').next().unwrap().to_string();
+            (name, params["diagnostics"].as_array().unwrap().len())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        published,
+        [("broken.toml".to_string(), 1), ("good.toml".to_string(), 0)]
+    );
+    let end = messages.last().unwrap();
+    assert_eq!(end["params"]["value"]["message"], "2 files, 1 with errors");
+
+    // Files changed outside the editor are checked again
+    std::fs::write(root.join("broken.toml"), "a = 1\n").unwrap();
+    session.notify(
+        "workspace/didChangeWatchedFiles",
+        serde_json::json!({ "changes": [{ "uri": format!("{root_uri}/broken.toml"), "type": 2 }] }),
+    );
+    assert_eq!(session.diagnostics(), (serde_json::Value::Null, Vec::new()));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn lets_clients_pull_diagnostics() {
+    let mut session = Session::initialize(serde_json::json!({
+        "capabilities": { "textDocument": { "diagnostic": {} } }
+    }));
+    let uri = "file:///config.toml";
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": "a = 1\na = 2\n" }
+        }),
+    );
+
+    let pull = |session: &mut Session, id, previous: &serde_json::Value| {
+        let params = serde_json::json!({
+            "textDocument": { "uri": uri }, "previousResultId": previous
+        });
+        session.request(id, "textDocument/diagnostic", params)
+    };
+    let report = pull(&mut session, 2, &serde_json::Value::Null);
+    assert_eq!(report["kind"], "full");
+    assert_eq!(report["items"][0]["code"], "E0013");
+    let result_id = report["resultId"].clone();
+
+    // Nothing changed, so there's nothing to send
+    let report = pull(&mut session, 3, &result_id);
+    assert_eq!(report["kind"], "unchanged");
+    assert_eq!(report["resultId"], result_id);
+
+    session.notify(
+        "textDocument/didChange",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "a = 1\n" }]
+        }),
+    );
+    let report = pull(&mut session, 4, &result_id);
+    assert_eq!(report["kind"], "full");
+    assert_eq!(report["items"], serde_json::json!([]));
+    let result_id = report["resultId"].clone();
+
+    let report = session.request(
+        5,
+        "workspace/diagnostic",
+        serde_json::json!({ "previousResultIds": [{ "uri": uri, "value": result_id }] }),
+    );
+    assert_eq!(
+        report["items"],
+        serde_json::json!([
+            { "kind": "unchanged", "uri": uri, "version": 2, "resultId": result_id }
+        ])
+    );
+}
+
+#[test]
+fn formats_with_the_settings_of_the_project() {
+    let root = std::env::temp_dir().join(format!("aoxo-toml-settings-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join(".aoxo-toml.toml"), "[format]\nindent-width = 2\n").unwrap();
+
+    let mut session = Session::initialize(serde_json::json!({
+        "capabilities": { "workspace": { "configuration": true } }
+    }));
+    session.settings = serde_json::json!({ "inlay-hints": "yes", "format": { "indent-width": 8 } });
+    let message = session.receive_until("invalid setting");
+    assert_eq!(
+        message["params"]["message"],
+        "invalid setting `inlay-hints`: expected a boolean"
+    );
+
+    // The project file wins over the client
+    let uri = format!("file://{}/a.toml", root.display());
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": "a = [\n1,\n]\n" }
+        }),
+    );
+    session.diagnostics();
+    let edits = session.request(
+        2,
+        "textDocument/formatting",
+        serde_json::json!({
+            "textDocument": { "uri": uri },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }),
+    );
+    assert_eq!(edits[0]["newText"], "a = [\n  1,\n]\n");
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn offers_fixes_for_lints() {
+    let mut session = Session::new();
+    let uri = "file:///lint.toml";
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": "\"a\" = 1 \n" }
+        }),
+    );
+    let (_, codes) = session.diagnostics();
+    assert_eq!(codes, ["quoted-key", "trailing-whitespace"]);
+
+    let range = serde_json::json!({
+        "start": { "line": 0, "character": 1 }, "end": { "line": 0, "character": 1 }
+    });
+    let actions = session.request(
+        2,
+        "textDocument/codeAction",
+        serde_json::json!({
+            "textDocument": { "uri": uri }, "range": range, "context": { "diagnostics": [] }
+        }),
+    );
+    assert_eq!(actions[0]["title"], "remove the quotes");
+    assert_eq!(actions[0]["diagnostics"][0]["severity"], 2);
+    assert_eq!(actions[0]["edit"]["changes"][uri][0]["newText"], "a");
+    assert_eq!(actions[1]["kind"], "source.fixAll");
+    assert_eq!(actions[1]["edit"]["changes"][uri][0]["newText"], "a = 1\n");
+
+    let actions = session.request(
+        3,
+        "textDocument/codeAction",
+        serde_json::json!({
+            "textDocument": { "uri": uri },
+            "range": range,
+            "context": { "diagnostics": [], "only": ["source"] }
+        }),
+    );
+    assert_eq!(actions.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn quotes_keys_that_are_not_ascii() {
+    let mut session = Session::new();
+    let uri = "file:///keys.toml";
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": "café = 1\n" }
+        }),
+    );
+    let (_, codes) = session.diagnostics();
+    assert_eq!(codes, ["E0018"]);
+
+    let actions = session.request(
+        2,
+        "textDocument/codeAction",
+        serde_json::json!({
+            "textDocument": { "uri": uri },
+            "range": {
+                "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 }
+            },
+            "context": { "diagnostics": [] }
+        }),
+    );
+    assert_eq!(actions[0]["title"], "Quote the key");
+    assert_eq!(actions[0]["edit"]["changes"][uri][0]["newText"], "\"café\"");
+}
+
+#[test]
+fn shows_the_comments_above_a_key_on_hover() {
+    let mut session = Session::new();
+    let uri = "file:///hover.toml";
+    let text = "# The name\n# of the package\nname = \"demo\"\n\n[t]\nx = 1\n";
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": text }
+        }),
+    );
+    session.diagnostics();
+
+    let hover = |session: &mut Session, id, line, character| {
+        session.request(
+            id,
+            "textDocument/hover",
+            serde_json::json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character }
+            }),
+        )
+    };
+    let result = hover(&mut session, 2, 2, 2);
+    assert_eq!(result["contents"]["value"], "The name\nof the package");
+    assert_eq!(result["range"]["end"]["character"], 4);
+    // Values and keys without comments have nothing to show
+    assert!(hover(&mut session, 3, 2, 9).is_null());
+    assert!(hover(&mut session, 4, 5, 0).is_null());
+}
+
+#[test]
+fn sorts_documents_on_command() {
+    let mut session = Session::new();
+    let uri = "file:///sort.toml";
+    session.notify(
+        "textDocument/didOpen",
+        serde_json::json!({
+            "textDocument": { "uri": uri, "languageId": "toml", "version": 1, "text": "b = 1\na = 2\n" }
+        }),
+    );
+    session.diagnostics();
+
+    let message = serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "workspace/executeCommand",
+        "params": { "command": "aoxo-toml.sort", "arguments": [{ "uri": uri }] }
+    });
+    session.send(&message.to_string());
+    let apply = session.receive_until("workspace/applyEdit");
+    assert_eq!(
+        apply["params"]["edit"]["changes"][uri][0]["newText"],
+        "a = 2\nb = 1\n"
+    );
+    session.receive_until(r#""id":2"#);
+}
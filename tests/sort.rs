@@ -0,0 +1,149 @@
+use aoxo_toml::{
+    config::{Config, Severity},
+    lint,
+    sort::{sort, Options},
+};
+
+#[test]
+fn sorts_keys_with_their_comments_within_groups() {
+    let source = concat!(
+        "# Settings\n",
+        "\n",
+        "zeta = 1\n",
+        "# Comes first\n",
+        "alpha = [\n",
+        "  1,\n",
+        "]\n",
+        "\"beta\".x = 3\n",
+        "beta.a = 4 # dotted\n",
+        "\n",
+        "later = 5\n",
+        "early = 6\n",
+        "[table]\n",
+        "b = 1\n",
+        "a = 2\n",
+    );
+    assert_eq!(
+        sort(source, Options::default()).unwrap(),
+        concat!(
+            "# Settings\n",
+            "\n",
+            "# Comes first\n",
+            "alpha = [\n",
+            "  1,\n",
+            "]\n",
+            "beta.a = 4 # dotted\n",
+            "\"beta\".x = 3\n",
+            "zeta = 1\n",
+            "\n",
+            "early = 6\n",
+            "later = 5\n",
+            "[table]\n",
+            "a = 2\n",
+            "b = 1\n",
+        )
+    );
+    assert_eq!(sort("a = [\n", Options::default()), None);
+    assert_eq!(
+        sort("b = 1\r\na = 2", Options::default()).unwrap(),
+        "a = 2\r\nb = 1"
+    );
+}
+
+#[test]
+fn sorts_tables_keeping_arrays_of_tables_together() {
+    let source = concat!(
+        "name = \"x\"\n",
+        "\n",
+        "[server]\n",
+        "port = 80\n",
+        "\n",
+        "[[bin]]\n",
+        "name = \"b\"\n",
+        "[bin.extra]\n",
+        "z = 1\n",
+        "\n",
+        "# The database\n",
+        "[database]\n",
+        "url = \"u\"\n",
+        "\n",
+        "[[bin]]\n",
+        "name = \"a\"\n",
+    );
+    assert_eq!(
+        sort(
+            source,
+            Options {
+                tables: true,
+                ..Options::default()
+            }
+        )
+        .unwrap(),
+        concat!(
+            "name = \"x\"\n",
+            "\n",
+            "[[bin]]\n",
+            "name = \"b\"\n",
+            "[bin.extra]\n",
+            "z = 1\n",
+            "\n",
+            "[[bin]]\n",
+            "name = \"a\"\n",
+            "\n",
+            "# The database\n",
+            "[database]\n",
+            "url = \"u\"\n",
+            "\n",
+            "[server]\n",
+            "port = 80\n",
+        )
+    );
+}
+
+#[test]
+fn unsorted_keys_are_fixed_by_sorting_their_group() {
+    let mut config = Config::default();
+    config
+        .lints
+        .insert("unsorted-keys".to_string(), Severity::Warn);
+    let source = "b = 1\na = 2\n\nd = 3\nc = 4\n";
+    assert_eq!(
+        lint::fix_all(source, &config).unwrap(),
+        "a = 2\nb = 1\n\nc = 4\nd = 3\n"
+    );
+}
+
+#[test]
+fn the_byte_order_mark_stays_first() {
+    let source = "\u{feff}x.y.z = 1\nkey = 1\n";
+    for tables in [false, true] {
+        let options = Options {
+            tables,
+            ..Options::default()
+        };
+        assert_eq!(
+            sort(source, options).unwrap(),
+            "\u{feff}key = 1\nx.y.z = 1\n"
+        );
+    }
+    assert_eq!(
+        sort(
+            "\u{feff}[b]\nk = 1\n[a]\nk = 2\n",
+            Options {
+                tables: true,
+                ..Options::default()
+            }
+        )
+        .unwrap(),
+        "\u{feff}[a]\nk = 2\n[b]\nk = 1\n"
+    );
+
+    let mut config = Config::default();
+    config
+        .lints
+        .insert("unsorted-keys".to_string(), Severity::Warn);
+    assert_eq!(
+        lint::fix_all(source, &config).unwrap(),
+        "\u{feff}key = 1\nx.y.z = 1\n"
+    );
+}
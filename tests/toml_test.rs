@@ -0,0 +1,184 @@
+//! Runs the vendored [toml-test](https://github.com/toml-lang/toml-test)
+//! corpus through the parser.
+//!
+//! Every file under `valid/` must parse without errors into the value tree
+//! of its `.json` twin, and every file under `invalid/` must produce at
+//! least one error, without panicking either way. Cases listed in
+//! `known-failures.txt` are expected to fail; one that starts passing must
+//! be removed from the list, so the list only ever shrinks. Run with
+//! `TOML_TEST_BLESS=1` to rewrite the list from the current results; the
+//! comments at its top are kept.
+//!
+//! The corpus follows TOML 1.1, so files are read as 1.1.
+
+use std::path::{Path, PathBuf};
+
+use aoxo_toml::{
+    config::TomlVersion,
+    value::{self, Value},
+};
+use serde_json::Value as Json;
+
+const ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/toml-test");
+
+fn files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            files(&entry, out);
+        } else if entry.extension().is_some_and(|ext| ext == "toml") {
+            out.push(entry);
+        }
+    }
+}
+
+fn float(value: &str) -> Option<f64> {
+    match value.trim_start_matches(['+', '-']) {
+        "nan" => Some(f64::NAN),
+        _ => value.parse().ok(),
+    }
+}
+
+/// Whether `actual` is the same value tree as `expected`, both in the
+/// tagged encoding.
+fn same(actual: &Json, expected: &Json) -> bool {
+    match (actual, expected) {
+        (Json::Array(actual), Json::Array(expected)) => {
+            actual.len() == expected.len()
+                && actual
+                    .iter()
+                    .zip(expected)
+                    .all(|(actual, expected)| same(actual, expected))
+        }
+        (Json::Object(actual), Json::Object(expected)) => {
+            if let (Some(Json::String(kind)), Some(Json::String(value))) =
+                (expected.get("type"), expected.get("value"))
+            {
+                let actual_value = actual.get("value").and_then(Json::as_str);
+                return actual.get("type").and_then(Json::as_str) == Some(kind.as_str())
+                    && match (kind.as_str(), actual_value) {
+                        ("float", Some(actual)) => match (float(actual), float(value)) {
+                            (Some(a), Some(b)) => a == b || (a.is_nan() && b.is_nan()),
+                            _ => false,
+                        },
+                        ("integer", Some(actual)) => {
+                            actual.parse::<i64>().ok() == value.parse::<i64>().ok()
+                        }
+                        (_, actual) => actual == Some(value.as_str()),
+                    };
+            }
+            actual.len() == expected.len()
+                && expected.iter().all(|(key, expected)| {
+                    actual.get(key).is_some_and(|actual| same(actual, expected))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Why the case at `path` fails, if it does.
+fn run(path: &Path) -> Option<String> {
+    let valid = path.starts_with(Path::new(ROOT).join("valid"));
+    let bytes = std::fs::read(path).unwrap();
+    let Ok(source) = String::from_utf8(bytes) else {
+        return valid.then(|| "not valid UTF-8".to_string());
+    };
+
+    let parsed = std::panic::catch_unwind(|| value::parse_with(&source, TomlVersion::V1_1));
+    let Ok((table, errors)) = parsed else {
+        return Some("panicked".to_string());
+    };
+
+    if !valid {
+        return errors
+            .is_empty()
+            .then(|| "accepted an invalid file".to_string());
+    }
+    if let Some(error) = errors.first() {
+        return Some(format!("{error} at {:?}", error.span));
+    }
+
+    let expected = std::fs::read_to_string(path.with_extension("json")).unwrap();
+    let expected: Json = serde_json::from_str(&expected).unwrap();
+    let actual = Value::Table(table).to_tagged_json();
+    (!same(&actual, &expected)).then(|| format!("decoded to {actual}"))
+}
+
+#[test]
+fn toml_test() {
+    let root = Path::new(ROOT);
+    let mut cases = Vec::new();
+    files(&root.join("valid"), &mut cases);
+    files(&root.join("invalid"), &mut cases);
+
+    let known_path = root.join("known-failures.txt");
+    let known = std::fs::read_to_string(&known_path).unwrap_or_default();
+    let known = known
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect::<Vec<_>>();
+
+    let mut failing = Vec::new();
+    let mut regressions = Vec::new();
+    let mut fixed = Vec::new();
+    for path in &cases {
+        let name = path
+            .strip_prefix(root)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        let failure = run(path);
+        match (&failure, known.contains(&name.as_str())) {
+            (Some(failure), false) => regressions.push(format!("{name}: {failure}")),
+            (None, true) => fixed.push(name.clone()),
+            _ => {}
+        }
+        if failure.is_some() {
+            failing.push(name);
+        }
+    }
+
+    if std::env::var_os("TOML_TEST_BLESS").is_some() {
+        // The comments at the top, saying why cases fail, stay
+        let mut list = String::new();
+        for line in std::fs::read_to_string(&known_path)
+            .unwrap_or_default()
+            .lines()
+            .take_while(|line| line.starts_with('#'))
+        {
+            list.push_str(line);
+            list.push('\n');
+        }
+        if list.is_empty() {
+            list.push_str("# toml-test cases aoxo-toml is known to fail, see tests/toml_test.rs\n");
+        }
+        for name in &failing {
+            list.push_str(name);
+            list.push('\n');
+        }
+        std::fs::write(&known_path, list).unwrap();
+        return;
+    }
+
+    assert!(
+        regressions.is_empty(),
+        "{} toml-test cases fail that used to pass:\n{}",
+        regressions.len(),
+        regressions.join("\n")
+    );
+    assert!(
+        fixed.is_empty(),
+        "{} toml-test cases pass now, remove them from known-failures.txt:\n{}",
+        fixed.len(),
+        fixed.join("\n")
+    );
+    println!(
+        "{} of {} toml-test cases pass",
+        cases.len() - failing.len(),
+        cases.len()
+    );
+}
@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+
+use aoxo_toml::workspace::Workspace;
+
+/// A fresh directory holding `files`, given as paths and contents.
+fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("aoxo-toml-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (path, contents) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+fn scan(workspace: &Workspace) -> Vec<String> {
+    workspace
+        .scan()
+        .iter()
+        .map(|path| relative(&workspace.root, path))
+        .collect()
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap()
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+#[test]
+fn scans_toml_files_not_ignored_by_git() {
+    let root = tree(
+        "gitignore",
+        &[
+            (".gitignore", "target/\n*.generated.toml\n/build.toml\n"),
+            ("Cargo.toml", ""),
+            ("build.toml", ""),
+            ("notes.txt", ""),
+            ("a.generated.toml", ""),
+            ("target/debug.toml", ""),
+            ("crates/x/Cargo.toml", ""),
+            // Only the root `build.toml` is anchored
+            ("crates/x/build.toml", ""),
+            ("crates/x/.gitignore", "*.toml\n!keep.toml\n"),
+            ("crates/x/keep.toml", ""),
+            ("crates/x/drop.toml", ""),
+            (".git/config.toml", ""),
+        ],
+    );
+    let workspace = Workspace::new(root.clone(), Vec::new());
+    assert_eq!(scan(&workspace), ["Cargo.toml", "crates/x/keep.toml"]);
+
+    assert!(workspace.contains(&root.join("crates/x/keep.toml")));
+    assert!(workspace.contains(&root.join("new.toml")));
+    assert!(!workspace.contains(&root.join("target/new.toml")));
+    assert!(!workspace.contains(&root.join("crates/x/drop.toml")));
+    assert!(!workspace.contains(&root.join("notes.txt")));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn leaves_out_excluded_paths() {
+    let root = tree(
+        "exclude",
+        &[
+            ("Cargo.toml", ""),
+            ("vendor/a/Cargo.toml", ""),
+            ("tests/fixtures/bad.toml", ""),
+            ("tests/good.toml", ""),
+        ],
+    );
+    let exclude = ["vendor", "**/fixtures/*.toml"]
+        .map(|pattern| glob::Pattern::new(pattern).unwrap())
+        .to_vec();
+    let workspace = Workspace::new(root.clone(), exclude);
+    assert_eq!(scan(&workspace), ["Cargo.toml", "tests/good.toml"]);
+    assert!(!workspace.contains(&root.join("vendor/b.toml")));
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn paths_from_the_command_line_skip_ignored_files_and_links() {
+    let root = tree(
+        "expand",
+        &[
+            (".gitignore", "target/\n"),
+            ("Cargo.toml", ""),
+            ("target/debug.toml", ""),
+            (".git/config.toml", ""),
+            ("crates/x/Cargo.toml", ""),
+        ],
+    );
+    // A link back up the tree would never end if it were followed
+    std::os::unix::fs::symlink(&root, root.join("crates/x/loop")).unwrap();
+
+    let files = aoxo_toml::cli::expand_paths(&[root.to_string_lossy().into_owned()]).unwrap();
+    let files = files
+        .iter()
+        .map(|path| relative(&root, path))
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["Cargo.toml", "crates/x/Cargo.toml"]);
+
+    std::fs::remove_dir_all(root).unwrap();
+}